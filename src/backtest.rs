@@ -0,0 +1,232 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+use crate::data_feed::{DataFeed, DataFeedError, MarketData};
+
+/// Simulation clock of a backtest. Every strategy pulls its own feed in its own task, so
+/// without coordination a shared (dummy) broker sees the events of different strategies out
+/// of time order. The clock only releases the globally earliest pending event, and only once
+/// the strategy that received the previous event asks for its next one.
+#[derive(Clone)]
+pub struct BacktestClock {
+    shared: Arc<ClockShared>,
+}
+
+struct ClockShared {
+    state: Mutex<ClockState>,
+    changes: watch::Sender<()>,
+}
+
+#[derive(Debug, Default)]
+struct ClockState {
+    feeds: Vec<FeedState>,
+    now: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FeedState {
+    /// The strategy did not ask for its first event yet
+    Starting,
+    /// The strategy is processing its last event
+    Busy,
+    /// The feed has an event with this timestamp. Errors have none and go first.
+    Waiting(Option<DateTime<Utc>>),
+    Ended,
+}
+
+impl Default for BacktestClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BacktestClock {
+    pub fn new() -> Self {
+        let (changes, _) = watch::channel(());
+        Self {
+            shared: Arc::new(ClockShared {
+                state: Mutex::new(ClockState::default()),
+                changes,
+            }),
+        }
+    }
+
+    /// The timestamp of the last dispatched event
+    pub fn now(&self) -> Option<DateTime<Utc>> {
+        self.shared.state.lock().unwrap().now
+    }
+
+    /// Put a feed under the control of the clock. All feeds have to be added before the
+    /// strategies start, otherwise the earlier ones may run ahead of the later ones.
+    pub fn synchronize(&self, feed: Box<dyn DataFeed>) -> ClockedDataFeed {
+        let mut state = self.shared.state.lock().unwrap();
+        state.feeds.push(FeedState::Starting);
+        ClockedDataFeed {
+            id: state.feeds.len() - 1,
+            inner: feed,
+            shared: self.shared.clone(),
+            changes: self.shared.changes.subscribe(),
+        }
+    }
+}
+
+impl ClockState {
+    /// The feed whose event is next. None while any strategy is busy or not started, because
+    /// it may still produce an earlier event.
+    fn next_feed(&self) -> Option<usize> {
+        let mut next: Option<(usize, Option<DateTime<Utc>>)> = None;
+        for (id, feed) in self.feeds.iter().enumerate() {
+            match feed {
+                FeedState::Starting | FeedState::Busy => return None,
+                FeedState::Ended => {}
+                FeedState::Waiting(timestamp) => {
+                    if next.is_none_or(|(_, earliest)| *timestamp < earliest) {
+                        next = Some((id, *timestamp));
+                    }
+                }
+            }
+        }
+        next.map(|(id, _)| id)
+    }
+}
+
+impl ClockShared {
+    fn set(&self, id: usize, feed_state: FeedState) {
+        self.state.lock().unwrap().feeds[id] = feed_state;
+        self.changes.send_replace(());
+    }
+}
+
+/// A data feed that delivers its events only when the `BacktestClock` says so
+pub struct ClockedDataFeed {
+    id: usize,
+    inner: Box<dyn DataFeed>,
+    shared: Arc<ClockShared>,
+    changes: watch::Receiver<()>,
+}
+
+impl ClockedDataFeed {
+    async fn wait_for_turn(&mut self) {
+        loop {
+            self.changes.borrow_and_update();
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                if state.next_feed() == Some(self.id) {
+                    if let FeedState::Waiting(Some(timestamp)) = state.feeds[self.id] {
+                        state.now = Some(timestamp);
+                    }
+                    state.feeds[self.id] = FeedState::Busy;
+                    return;
+                }
+            }
+            // The sender lives as long as this feed, so this can not fail
+            let _ = self.changes.changed().await;
+        }
+    }
+}
+
+#[async_trait]
+impl DataFeed for ClockedDataFeed {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        let Some(event) = self.inner.next_tick().await else {
+            self.shared.set(self.id, FeedState::Ended);
+            return None;
+        };
+        let timestamp = event.as_ref().ok().map(|data| data.timestamp);
+        self.shared.set(self.id, FeedState::Waiting(timestamp));
+        self.wait_for_turn().await;
+        Some(event)
+    }
+}
+
+impl Drop for ClockedDataFeed {
+    fn drop(&mut self) {
+        self.shared.set(self.id, FeedState::Ended);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    struct VecDataFeed(VecDeque<MarketData>);
+
+    #[async_trait]
+    impl DataFeed for VecDataFeed {
+        fn name(&self) -> &str {
+            "vec"
+        }
+        async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+            self.0.pop_front().map(Ok)
+        }
+    }
+
+    fn feed(symbol: &str, seconds: &[i64]) -> Box<dyn DataFeed> {
+        let ticks = seconds
+            .iter()
+            .map(|second| {
+                let timestamp = DateTime::from_timestamp(1_700_000_000 + second, 0).unwrap();
+                MarketData::tick(symbol.to_string(), 100.0, 0.0, timestamp)
+            })
+            .collect();
+        Box::new(VecDataFeed(ticks))
+    }
+
+    #[test]
+    fn test_next_feed_waits_for_busy_feeds() {
+        let timestamp = DateTime::from_timestamp(1_700_000_000, 0);
+        let mut state = ClockState {
+            feeds: vec![FeedState::Waiting(timestamp), FeedState::Busy],
+            now: None,
+        };
+        assert_eq!(state.next_feed(), None);
+
+        state.feeds[1] = FeedState::Ended;
+        assert_eq!(state.next_feed(), Some(0));
+
+        // Errors go before any event
+        state.feeds[1] = FeedState::Waiting(None);
+        assert_eq!(state.next_feed(), Some(1));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+    async fn test_events_are_dispatched_in_chronological_order() {
+        let clock = BacktestClock::new();
+        let feeds = [
+            feed("A", &[0, 3, 4, 10]),
+            feed("B", &[1, 2, 5, 6, 7]),
+            feed("C", &[0, 8, 9]),
+        ]
+        .map(|feed| clock.synchronize(feed));
+        let dispatched = Arc::new(Mutex::new(Vec::new()));
+        let mut handles = Vec::new();
+        for mut feed in feeds {
+            let dispatched = dispatched.clone();
+            handles.push(tokio::spawn(async move {
+                while let Some(Ok(data)) = feed.next_tick().await {
+                    dispatched
+                        .lock()
+                        .unwrap()
+                        .push((data.timestamp, data.symbol));
+                    // Simulate a strategy that is slow to process some events
+                    tokio::task::yield_now().await;
+                }
+            }));
+        }
+        futures::future::join_all(handles).await;
+
+        let dispatched = dispatched.lock().unwrap();
+        assert_eq!(dispatched.len(), 12);
+        assert!(dispatched.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        // Equal timestamps go in the order the feeds were added
+        assert_eq!(dispatched[0].1, "A");
+        assert_eq!(dispatched[1].1, "C");
+        assert_eq!(clock.now(), DateTime::from_timestamp(1_700_000_010, 0));
+    }
+}
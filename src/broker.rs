@@ -1,4 +1,8 @@
-use crate::types::{Fill, Order, OrderSide, Position};
+use crate::{
+    corporate_actions::{CorporateAction, CorporateActionKind},
+    data_feed::MarketData,
+    types::{Fill, Order, OrderId, OrderSide, OrderStatus, Position},
+};
 use async_trait::async_trait;
 use std::collections::HashMap;
 use thiserror::Error;
@@ -10,7 +14,12 @@ pub mod ib;
 #[async_trait]
 pub trait Broker: Send + Sync {
     fn name(&self) -> &str;
-    async fn place_order(&self, order: &Order) -> Result<(), BrokerError>;
+    /// Place `order`. Limit and stop orders rest until their price is reached.
+    async fn place_order(&self, order: &Order) -> Result<OrderId, BrokerError>;
+    /// Cancel an open order. The cash reserved for its unfilled part is released.
+    async fn cancel_order(&self, order_id: &str) -> Result<(), BrokerError>;
+    /// `None` for orders that were not placed with this broker
+    async fn order_status(&self, order_id: &str) -> Option<OrderStatus>;
     fn portfolio_manager(&self) -> &PortfolioManager;
     async fn portfolio_snapshot(&self) -> AccountInfo {
         self.portfolio_manager().snapshot().await
@@ -21,26 +30,57 @@ pub trait Broker: Send + Sync {
         current_price: f64,
     ) -> Result<(), PortfolioError> {
         self.portfolio_manager()
-            .pre_reserve_for_order(order, current_price)
+            .pre_reserve_for_order(order, current_price, self.allows_short_selling())
             .await
     }
-    async fn portfolio_release_reserved_cash(&self, qty: u32, price: f64) {
+    /// Release what was reserved for `order` when it could not be placed
+    async fn portfolio_release_reservation(&self, order: &Order, price: f64) {
         self.portfolio_manager()
-            .release_reserved_cash(qty, price)
+            .release_reservation(order.side, &order.symbol, order.qty, price)
             .await
     }
+    /// Negative for short positions
+    async fn position_qty(&self, symbol: &str) -> i64 {
+        self.portfolio_manager().position_qty(symbol).await
+    }
+    /// The part of the position in `symbol` that the orders of `strategy_name` filled.
+    /// Negative for short positions.
+    async fn strategy_position_qty(&self, strategy_name: &str, symbol: &str) -> i64 {
+        self.portfolio_manager()
+            .strategy_position_qty(strategy_name, symbol)
+            .await
+    }
+    /// Whether sell orders may exceed the position and open (or add to) a short position
+    fn allows_short_selling(&self) -> bool {
+        false
+    }
+    /// Strategies pass every event of their feed, so simulated brokers can follow the time
+    /// and prices of the market
+    async fn on_market_data(&self, _data: &MarketData) {}
 }
 
 #[derive(Debug, Error)]
 pub enum BrokerError {
     #[error("Failed to place order: {0}")]
     PlaceOrder(String),
+    #[error("Connection `{0}` of the broker is down")]
+    Disconnected(String),
+    #[error("Failed to cancel order: {0}")]
+    CancelOrder(String),
+    #[error("Unknown order `{0}`")]
+    UnknownOrder(String),
 }
 
 pub struct Portfolio {
     pub cash: f64,
     pub reserved_cash: f64,
     pub positions: HashMap<String, Position>,
+    /// The quantity of each symbol that the fills of each strategy added up to, by strategy
+    /// and symbol. Positions held before the start belong to no strategy.
+    strategy_positions: HashMap<(String, String), i64>,
+    /// The quantity of the sell orders of each symbol that were reserved for and did not
+    /// execute yet. They lock the shares of the position they will deliver.
+    open_sell_qty: HashMap<String, u32>,
 }
 
 #[derive(Debug)]
@@ -56,6 +96,8 @@ impl Portfolio {
             cash,
             reserved_cash,
             positions,
+            strategy_positions: HashMap::new(),
+            open_sell_qty: HashMap::new(),
         }
     }
 
@@ -64,8 +106,8 @@ impl Portfolio {
         let equity = self.cash
             + self
                 .positions
-                .iter()
-                .map(|(_, p)| p.qty as f64 * p.avg_price)
+                .values()
+                .map(|p| p.qty as f64 * p.avg_price)
                 .sum::<f64>();
         AccountInfo {
             cash: self.cash,
@@ -74,12 +116,16 @@ impl Portfolio {
         }
     }
 
-    /// Pre-check and reserve funds for a buy order. For sell, check position availability.
+    /// Pre-check and reserve funds for a buy order. For sell, check position availability: the
+    /// shares that open sell orders will deliver are not available anymore.
+    /// With `allow_short`, the part of a sell beyond the position is a short sale that reserves
+    /// as much of the buying power as it is worth, as margin.
     /// This returns Ok if we can proceed (and reserves), or Err if not possible.
     pub fn pre_reserve_for_order(
         &mut self,
         order: &Order,
         current_price: f64,
+        allow_short: bool,
     ) -> Result<(), PortfolioError> {
         match order.side {
             OrderSide::Buy => {
@@ -95,60 +141,166 @@ impl Portfolio {
                 }
             }
             OrderSide::Sell => {
-                let position_qty = self
-                    .positions
-                    .get(&order.symbol)
-                    .map(|p| p.qty)
-                    .unwrap_or(0);
-                if position_qty >= order.qty {
-                    Ok(())
-                } else {
-                    Err(PortfolioError::InsufficientPosition(
+                let open_qty = self.open_sell_qty(&order.symbol);
+                let short_qty = self.short_qty(&order.symbol, open_qty + order.qty)
+                    - self.short_qty(&order.symbol, open_qty);
+                if short_qty > 0 && !allow_short {
+                    let position_qty = self
+                        .positions
+                        .get(&order.symbol)
+                        .map(|p| p.qty)
+                        .unwrap_or(0);
+                    return Err(PortfolioError::InsufficientPosition(
                         order.qty,
-                        position_qty,
-                    ))
+                        position_qty - open_qty as i64,
+                    ));
+                }
+                let required = short_qty as f64 * current_price;
+                let available = self.buying_power();
+                if available < required {
+                    return Err(PortfolioError::InsufficientCash(required, available));
                 }
+                self.reserved_cash += required;
+                *self.open_sell_qty.entry(order.symbol.clone()).or_default() += order.qty;
+                Ok(())
             }
         }
     }
 
-    /// Called once a Fill arrives (from broker). This updates cash, positions, and releases reservations.
+    /// Called once a Fill arrives (from broker). This updates cash, positions, and releases
+    /// reservations at the price the order reserved at, whatever the price of the fill.
     pub fn apply_fill(&mut self, fill: Fill) {
-        match fill.side {
+        self.cash -= fill.commission;
+        let value = fill.price * fill.qty as f64;
+        let open_short_qty = self.short_qty(&fill.symbol, self.open_sell_qty(&fill.symbol));
+        let qty = match fill.side {
             OrderSide::Buy => {
-                let cost = fill.price * fill.qty as f64;
-                self.reserved_cash -= cost;
-                self.cash -= cost;
-                let position = self
-                    .positions
-                    .entry(fill.symbol.clone())
-                    .or_insert(Position {
-                        symbol: fill.symbol,
-                        qty: 0,
-                        avg_price: 0.0,
-                    });
-
-                // Update avg price of position
-                let new_qty = position.qty + fill.qty;
-                let prev_value = (position.qty as f64) * position.avg_price;
-                let added_value = (fill.qty as f64) * fill.price;
-                position.avg_price = (prev_value + added_value) / (new_qty as f64);
-                position.qty = new_qty;
+                self.release_reserved_cash(fill.qty, fill.reservation_price);
+                self.cash -= value;
+                fill.qty as i64
             }
             OrderSide::Sell => {
-                self.cash += fill.price * fill.qty as f64;
-                // TODO: There should always be a position at this point. Consider verifying and return an error
-                let position = self.positions.get_mut(&fill.symbol).unwrap();
-                position.qty -= fill.qty;
-                // Do not update avg_price of position: Because avg_price represents the cost basis of my remaining shares.
-                // When I sell, I remove shares at the same cost basis. PnL = (sell_price - avg_price) × qty.
+                self.close_open_sell(&fill.symbol, fill.qty);
+                self.cash += value;
+                -(fill.qty as i64)
             }
+        };
+        *self
+            .strategy_positions
+            .entry((fill.strategy_name, fill.symbol.clone()))
+            .or_default() += qty;
+        // Selling keeps the avg_price: it is the cost basis of the remaining shares.
+        // PnL = (sell_price - avg_price) × qty.
+        self.positions
+            .entry(fill.symbol.clone())
+            .or_insert(Position {
+                symbol: fill.symbol.clone(),
+                qty: 0,
+                avg_price: 0.0,
+            })
+            .apply(qty, fill.price);
+        // Short sales are backed by their position from now on, and bought shares cover the
+        // short part of the open sells
+        let remaining_short_qty = self.short_qty(&fill.symbol, self.open_sell_qty(&fill.symbol));
+        self.release_reserved_cash(
+            open_short_qty.saturating_sub(remaining_short_qty),
+            fill.reservation_price,
+        );
+    }
+
+    /// The part of the position in `symbol` that the fills of `strategy_name` added up to
+    pub fn strategy_position_qty(&self, strategy_name: &str, symbol: &str) -> i64 {
+        self.strategy_positions
+            .get(&(strategy_name.to_string(), symbol.to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Book a commission that is charged after its fill was applied
+    pub fn apply_commission(&mut self, commission: f64) {
+        self.cash -= commission;
+    }
+
+    /// Release cash that was reserved for a buy order that will not (fully) execute
+    pub fn release_reserved_cash(&mut self, qty: u32, price: f64) {
+        self.reserved_cash = (self.reserved_cash - (qty as f64) * price).max(0.0);
+    }
+
+    /// Release what was reserved for `qty` of an order that will not execute: the cash of a
+    /// buy, or the margin of the part of a sell beyond the position
+    pub fn release_reservation(&mut self, side: OrderSide, symbol: &str, qty: u32, price: f64) {
+        let reserved_qty = match side {
+            OrderSide::Buy => qty,
+            OrderSide::Sell => {
+                let open_qty = self.open_sell_qty(symbol);
+                self.close_open_sell(symbol, qty);
+                self.short_qty(symbol, open_qty)
+                    - self.short_qty(symbol, self.open_sell_qty(symbol))
+            }
+        };
+        self.release_reserved_cash(reserved_qty, price);
+    }
+
+    fn open_sell_qty(&self, symbol: &str) -> u32 {
+        self.open_sell_qty.get(symbol).copied().unwrap_or(0)
+    }
+
+    /// `qty` of the open sells in `symbol` executed or will not execute anymore
+    fn close_open_sell(&mut self, symbol: &str, qty: u32) {
+        if let Some(open_qty) = self.open_sell_qty.get_mut(symbol) {
+            *open_qty = open_qty.saturating_sub(qty);
         }
     }
 
-    fn release_reserved_cash(&self, qty: u32, price: f64) {
-        // TODO: Implement
-        // self.reserved_cash -= (qty as f64) * data.price;
+    /// The part of sells of `qty` that goes beyond the long position in `symbol`
+    fn short_qty(&self, symbol: &str, qty: u32) -> u32 {
+        let long_qty = self.positions.get(symbol).map_or(0, |p| p.qty.max(0));
+        (qty as i64 - long_qty).max(0) as u32
+    }
+
+    /// The cash that is neither reserved nor owed for buying back the short positions, which
+    /// are valued at their average price
+    fn buying_power(&self) -> f64 {
+        let short_value: f64 = self
+            .positions
+            .values()
+            .filter(|p| p.qty < 0)
+            .map(|p| -p.qty as f64 * p.avg_price)
+            .sum();
+        self.cash - self.reserved_cash - short_value
+    }
+
+    /// Adjust the position in the symbol of a split or pay out a dividend on it. Fractional
+    /// shares of a split are paid out in cash at `price`. Each strategy's part of the position
+    /// (and the part of no strategy) is split on its own, so that they keep adding up to the
+    /// position.
+    pub fn apply_corporate_action(&mut self, action: &CorporateAction, price: f64) {
+        let Some(position) = self.positions.get_mut(&action.symbol) else {
+            return;
+        };
+        match action.kind {
+            CorporateActionKind::Split(ratio) => {
+                let split = |qty: i64| (qty as f64 * ratio).trunc() as i64;
+                let mut strategies_qty = 0;
+                let mut new_qty = 0;
+                for ((_, symbol), qty) in &mut self.strategy_positions {
+                    if *symbol == action.symbol {
+                        strategies_qty += *qty;
+                        *qty = split(*qty);
+                        new_qty += *qty;
+                    }
+                }
+                new_qty += split(position.qty - strategies_qty);
+                // Fractions of short positions are bought back
+                self.cash += (position.qty as f64 * ratio - new_qty as f64) * price;
+                position.avg_price /= ratio;
+                position.qty = new_qty;
+            }
+            // Short positions pay the dividend
+            CorporateActionKind::Dividend(dividend) => {
+                self.cash += position.qty as f64 * dividend;
+            }
+        }
     }
 }
 
@@ -157,7 +309,7 @@ pub enum PortfolioError {
     #[error("Insufficient cash. Required {0} available {1}")]
     InsufficientCash(f64, f64),
     #[error("Insufficient position. Trying to sell {0} have {1}")]
-    InsufficientPosition(u32, u32),
+    InsufficientPosition(u32, i64),
 }
 
 pub struct PortfolioManager {
@@ -177,21 +329,46 @@ impl PortfolioManager {
         &self,
         order: &Order,
         current_price: f64,
+        allow_short: bool,
     ) -> Result<(), PortfolioError> {
         self.portfolio
             .lock()
             .await
-            .pre_reserve_for_order(order, current_price)
+            .pre_reserve_for_order(order, current_price, allow_short)
     }
-    async fn release_reserved_cash(&self, qty: u32, price: f64) {
+    /// The quantity held of `symbol`, 0 without a position and negative for short positions
+    pub async fn position_qty(&self, symbol: &str) -> i64 {
         self.portfolio
             .lock()
             .await
-            .release_reserved_cash(qty, price)
+            .positions
+            .get(symbol)
+            .map_or(0, |position| position.qty)
+    }
+    pub async fn strategy_position_qty(&self, strategy_name: &str, symbol: &str) -> i64 {
+        self.portfolio
+            .lock()
+            .await
+            .strategy_position_qty(strategy_name, symbol)
+    }
+    async fn release_reservation(&self, side: OrderSide, symbol: &str, qty: u32, price: f64) {
+        self.portfolio
+            .lock()
+            .await
+            .release_reservation(side, symbol, qty, price)
     }
     async fn apply_fill(&self, fill: Fill) {
         self.portfolio.lock().await.apply_fill(fill);
     }
+    async fn apply_commission(&self, commission: f64) {
+        self.portfolio.lock().await.apply_commission(commission);
+    }
+    async fn apply_corporate_action(&self, action: &CorporateAction, price: f64) {
+        self.portfolio
+            .lock()
+            .await
+            .apply_corporate_action(action, price);
+    }
 }
 
 #[cfg(test)]
@@ -217,6 +394,10 @@ mod tests {
             price,
             side,
             timestamp: chrono::Local::now().naive_local(),
+            execution_id: None,
+            commission: 0.0,
+            strategy_name: "test".into(),
+            reservation_price: price,
         }
     }
 
@@ -245,7 +426,7 @@ mod tests {
         let mut portfolio = Portfolio::new(1000.0, 0.0, HashMap::new());
         let order = make_order("AAPL", OrderSide::Buy, 5);
 
-        let result = portfolio.pre_reserve_for_order(&order, 100.0);
+        let result = portfolio.pre_reserve_for_order(&order, 100.0, false);
 
         assert!(result.is_ok());
         assert_eq!(portfolio.reserved_cash, 500.0);
@@ -256,7 +437,7 @@ mod tests {
         let mut portfolio = Portfolio::new(200.0, 0.0, HashMap::new());
         let order = make_order("AAPL", OrderSide::Buy, 5);
 
-        let result = portfolio.pre_reserve_for_order(&order, 100.0);
+        let result = portfolio.pre_reserve_for_order(&order, 100.0, false);
 
         assert!(matches!(result, Err(PortfolioError::InsufficientCash(..))));
     }
@@ -275,7 +456,7 @@ mod tests {
         let mut portfolio = Portfolio::new(1000.0, 0.0, positions);
         let order = make_order("AAPL", OrderSide::Sell, 5);
 
-        let result = portfolio.pre_reserve_for_order(&order, 150.0);
+        let result = portfolio.pre_reserve_for_order(&order, 150.0, false);
 
         assert!(result.is_ok());
     }
@@ -294,7 +475,7 @@ mod tests {
         let mut portfolio = Portfolio::new(1000.0, 0.0, positions);
         let order = make_order("AAPL", OrderSide::Sell, 5);
 
-        let result = portfolio.pre_reserve_for_order(&order, 150.0);
+        let result = portfolio.pre_reserve_for_order(&order, 150.0, false);
 
         assert!(matches!(
             result,
@@ -317,6 +498,25 @@ mod tests {
         assert_eq!(pos.avg_price, 100.0);
     }
 
+    #[test]
+    fn test_portfolio_apply_fill_releases_the_reservation_of_its_order() {
+        let mut portfolio = Portfolio::new(1000.0, 0.0, HashMap::new());
+        let order = make_order("AAPL", OrderSide::Buy, 5);
+        portfolio
+            .pre_reserve_for_order(&order, 100.0, false)
+            .unwrap();
+        // Slipped to a worse price than reserved at
+        let fill = Fill {
+            reservation_price: 100.0,
+            ..make_fill("AAPL", OrderSide::Buy, 5, 101.0)
+        };
+
+        portfolio.apply_fill(fill);
+
+        assert_eq!(portfolio.cash, 1000.0 - 505.0);
+        assert_eq!(portfolio.reserved_cash, 0.0);
+    }
+
     #[test]
     fn test_portfolio_apply_fill_sell_updates_cash_and_position() {
         let mut positions = HashMap::new();
@@ -338,4 +538,208 @@ mod tests {
         assert_eq!(pos.qty, 5); // 5 left
         assert_eq!(pos.avg_price, 100.0); // unchanged
     }
+
+    #[test]
+    fn test_portfolio_apply_fill_deducts_commission() {
+        let mut portfolio = Portfolio::new(1000.0, 500.0, HashMap::new());
+        let mut fill = make_fill("AAPL", OrderSide::Buy, 5, 100.0);
+        fill.commission = 1.5;
+
+        portfolio.apply_fill(fill);
+
+        assert_eq!(portfolio.cash, 1000.0 - 500.0 - 1.5);
+    }
+
+    #[test]
+    fn test_portfolio_applies_splits_and_dividends() {
+        let mut positions = HashMap::new();
+        positions.insert(
+            "AAPL".to_string(),
+            Position {
+                symbol: "AAPL".into(),
+                qty: 5,
+                avg_price: 300.0,
+            },
+        );
+        let mut portfolio = Portfolio::new(1000.0, 0.0, positions);
+        let action = |kind| CorporateAction {
+            symbol: "AAPL".into(),
+            ex_date: chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            kind,
+        };
+
+        // 3-for-2: 7.5 shares, the half share is paid out at 190
+        portfolio.apply_corporate_action(&action(CorporateActionKind::Split(1.5)), 190.0);
+        let pos = portfolio.positions.get("AAPL").unwrap();
+        assert_eq!(pos.qty, 7);
+        assert_eq!(pos.avg_price, 200.0);
+        assert_eq!(portfolio.cash, 1095.0);
+
+        portfolio.apply_corporate_action(&action(CorporateActionKind::Dividend(0.5)), 190.0);
+        assert_eq!(portfolio.cash, 1098.5);
+    }
+
+    #[test]
+    fn test_portfolio_short_selling() {
+        let mut portfolio = Portfolio::new(1000.0, 0.0, HashMap::new());
+        let order = make_order("AAPL", OrderSide::Sell, 5);
+        assert!(matches!(
+            portfolio.pre_reserve_for_order(&order, 100.0, false),
+            Err(PortfolioError::InsufficientPosition(5, 0))
+        ));
+        assert!(portfolio.pre_reserve_for_order(&order, 100.0, true).is_ok());
+        // The short sale reserves as much cash as it is worth
+        assert_eq!(portfolio.reserved_cash, 500.0);
+        let order = make_order("AAPL", OrderSide::Sell, 6);
+        assert!(matches!(
+            portfolio.pre_reserve_for_order(&order, 100.0, true),
+            Err(PortfolioError::InsufficientCash(..))
+        ));
+
+        portfolio.apply_fill(make_fill("AAPL", OrderSide::Sell, 5, 100.0));
+        assert_eq!(portfolio.reserved_cash, 0.0);
+        portfolio.apply_fill(make_fill("AAPL", OrderSide::Sell, 5, 110.0));
+        let pos = portfolio.positions.get("AAPL").unwrap();
+        assert_eq!(pos.qty, -10);
+        assert_eq!(pos.avg_price, 105.0);
+        assert_eq!(portfolio.cash, 1000.0 + 1050.0);
+        assert_eq!(portfolio.snapshot().equity, 1000.0);
+        // The proceeds of the open short positions do not back new ones
+        let order = make_order("AAPL", OrderSide::Sell, 11);
+        assert!(matches!(
+            portfolio.pre_reserve_for_order(&order, 100.0, true),
+            Err(PortfolioError::InsufficientCash(..))
+        ));
+        let order = make_order("AAPL", OrderSide::Sell, 10);
+        assert!(portfolio.pre_reserve_for_order(&order, 100.0, true).is_ok());
+        portfolio.release_reservation(OrderSide::Sell, "AAPL", 10, 100.0);
+        assert_eq!(portfolio.reserved_cash, 0.0);
+
+        // Cover and turn long
+        portfolio.reserved_cash = 1140.0;
+        portfolio.apply_fill(make_fill("AAPL", OrderSide::Buy, 12, 95.0));
+        let pos = portfolio.positions.get("AAPL").unwrap();
+        assert_eq!(pos.qty, 2);
+        assert_eq!(pos.avg_price, 95.0);
+        assert_eq!(portfolio.cash, 2050.0 - 1140.0);
+    }
+
+    #[test]
+    fn test_portfolio_tracks_the_positions_of_each_strategy() {
+        let mut positions = HashMap::new();
+        positions.insert(
+            "AAPL".to_string(),
+            Position {
+                symbol: "AAPL".into(),
+                qty: 4,
+                avg_price: 100.0,
+            },
+        );
+        let mut portfolio = Portfolio::new(1000.0, 0.0, positions);
+        let fill = |strategy_name: &str, side, qty| Fill {
+            strategy_name: strategy_name.into(),
+            ..make_fill("AAPL", side, qty, 100.0)
+        };
+
+        portfolio.apply_fill(fill("a", OrderSide::Buy, 3));
+        portfolio.apply_fill(fill("b", OrderSide::Buy, 2));
+        portfolio.apply_fill(fill("b", OrderSide::Sell, 5));
+        assert_eq!(portfolio.positions.get("AAPL").unwrap().qty, 4);
+        assert_eq!(portfolio.strategy_position_qty("a", "AAPL"), 3);
+        assert_eq!(portfolio.strategy_position_qty("b", "AAPL"), -3);
+        assert_eq!(portfolio.strategy_position_qty("c", "AAPL"), 0);
+
+        let split = CorporateAction {
+            symbol: "AAPL".into(),
+            ex_date: chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            kind: CorporateActionKind::Split(2.0),
+        };
+        portfolio.apply_corporate_action(&split, 50.0);
+        assert_eq!(portfolio.strategy_position_qty("a", "AAPL"), 6);
+        assert_eq!(portfolio.strategy_position_qty("b", "AAPL"), -6);
+    }
+
+    #[test]
+    fn test_portfolio_split_keeps_the_strategy_positions_adding_up() {
+        let mut portfolio = Portfolio::new(1000.0, 0.0, HashMap::new());
+        let fill = |strategy_name: &str, qty| Fill {
+            strategy_name: strategy_name.into(),
+            ..make_fill("AAPL", OrderSide::Buy, qty, 100.0)
+        };
+        portfolio.apply_fill(fill("a", 3));
+        portfolio.apply_fill(fill("b", 1));
+        portfolio.apply_fill(fill("c", 1));
+
+        let split = CorporateAction {
+            symbol: "AAPL".into(),
+            ex_date: chrono::NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            kind: CorporateActionKind::Split(1.5),
+        };
+        portfolio.apply_corporate_action(&split, 60.0);
+        // 4.5, 1.5 and 1.5 shares, the halves are paid out
+        let strategies_qty: i64 = ["a", "b", "c"]
+            .iter()
+            .map(|strategy| portfolio.strategy_position_qty(strategy, "AAPL"))
+            .sum();
+        assert_eq!(portfolio.strategy_position_qty("a", "AAPL"), 4);
+        assert_eq!(portfolio.positions.get("AAPL").unwrap().qty, 6);
+        assert_eq!(strategies_qty, 6);
+        assert_eq!(portfolio.cash, 1000.0 - 500.0 + 1.5 * 60.0);
+    }
+
+    #[test]
+    fn test_portfolio_open_sells_lock_their_shares() {
+        let mut positions = HashMap::new();
+        positions.insert(
+            "AAPL".to_string(),
+            Position {
+                symbol: "AAPL".into(),
+                qty: 10,
+                avg_price: 100.0,
+            },
+        );
+        let mut portfolio = Portfolio::new(1000.0, 0.0, positions);
+        let order = make_order("AAPL", OrderSide::Sell, 8);
+        assert!(
+            portfolio
+                .pre_reserve_for_order(&order, 100.0, false)
+                .is_ok()
+        );
+        // Only 2 shares are left to sell
+        assert!(matches!(
+            portfolio.pre_reserve_for_order(&order, 100.0, false),
+            Err(PortfolioError::InsufficientPosition(8, 2))
+        ));
+        // or to deliver while going short with the rest
+        assert!(portfolio.pre_reserve_for_order(&order, 100.0, true).is_ok());
+        assert_eq!(portfolio.reserved_cash, 600.0);
+
+        // Whichever of the two executes first, the other one opens the short position
+        portfolio.apply_fill(make_fill("AAPL", OrderSide::Sell, 8, 100.0));
+        assert_eq!(portfolio.reserved_cash, 600.0);
+        portfolio.release_reservation(OrderSide::Sell, "AAPL", 8, 100.0);
+        assert_eq!(portfolio.reserved_cash, 0.0);
+        assert!(matches!(
+            portfolio.pre_reserve_for_order(&order, 100.0, false),
+            Err(PortfolioError::InsufficientPosition(8, 2))
+        ));
+        let order = make_order("AAPL", OrderSide::Sell, 2);
+        assert!(
+            portfolio
+                .pre_reserve_for_order(&order, 100.0, false)
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_portfolio_release_reserved_cash() {
+        let mut portfolio = Portfolio::new(1000.0, 500.0, HashMap::new());
+
+        portfolio.release_reserved_cash(3, 100.0);
+        assert_eq!(portfolio.reserved_cash, 200.0);
+
+        // Never goes below zero
+        portfolio.release_reserved_cash(3, 100.0);
+        assert_eq!(portfolio.reserved_cash, 0.0);
+    }
 }
@@ -1,16 +1,27 @@
-use crate::types::{Fill, Order};
+use crate::{
+    corporate_actions::{CorporateAction, CorporateActionKind, CorporateActions},
+    data_feed::{MarketData, MarketDataKind},
+    types::{Fill, Order, OrderId, OrderSide, OrderStatus, OrderType},
+};
 use async_trait::async_trait;
 use chrono::Local;
 use std::collections::HashMap;
 use tokio::sync::Mutex;
-use tracing::info;
+use tracing::{info, warn};
 
 use super::{Broker, BrokerError, Portfolio, PortfolioManager};
 
 pub struct DummyBroker {
     name: String,
+    /// Every placed order. The id of an order is its index.
     orders: Mutex<Vec<Order>>,
     portfolio_manager: PortfolioManager,
+    /// Limit and stop orders waiting for the market to reach their price, by id
+    resting_orders: Mutex<Vec<(usize, Order)>>,
+    order_statuses: Mutex<HashMap<OrderId, OrderStatus>>,
+    /// Corporate actions whose ex-date the market data did not reach yet
+    pending_corporate_actions: Mutex<Vec<CorporateAction>>,
+    allow_short: bool,
 }
 
 #[async_trait]
@@ -18,26 +29,66 @@ impl Broker for DummyBroker {
     fn name(&self) -> &str {
         &self.name
     }
-    async fn place_order(&self, order: &Order) -> Result<(), BrokerError> {
-        self.orders.lock().await.push(order.clone());
-        let fill = Fill {
-            order_id: Default::default(),
-            symbol: order.symbol.clone(),
-            qty: order.qty,
-            price: order.price.unwrap_or(100.0), // TODO: This should be fixed and use the actual price that it was used
-            side: order.side.clone(),
-            timestamp: Local::now().naive_local(),
+    /// Market orders fill right away at their price, limit and stop orders once the market data
+    /// reaches their price, at that price or at the open when the market gapped through it
+    async fn place_order(&self, order: &Order) -> Result<OrderId, BrokerError> {
+        let id = {
+            let mut orders = self.orders.lock().await;
+            orders.push(order.clone());
+            orders.len() - 1
         };
-        self.portfolio_manager().apply_fill(fill).await;
-        info!(
-            "New account status after filled order = {:?}",
-            self.portfolio_snapshot().await
+        if let (OrderType::Limit | OrderType::Stop, Some(_)) = (&order.order_type, order.price) {
+            self.resting_orders.lock().await.push((id, order.clone()));
+            self.order_statuses
+                .lock()
+                .await
+                .insert(id.to_string(), OrderStatus::Open { filled_qty: 0 });
+        } else {
+            let price = order.price.unwrap_or(100.0); // TODO: This should be fixed and use the actual price that it was used
+            self.fill(id, order, price).await;
+        }
+        Ok(id.to_string())
+    }
+    async fn cancel_order(&self, order_id: &str) -> Result<(), BrokerError> {
+        let cancelled = {
+            let mut resting = self.resting_orders.lock().await;
+            let index = resting
+                .iter()
+                .position(|(id, _)| id.to_string() == order_id);
+            index.map(|index| resting.remove(index).1)
+        };
+        let Some(order) = cancelled else {
+            return match self.order_status(order_id).await {
+                Some(status) => Err(BrokerError::CancelOrder(format!(
+                    "order {order_id} is {status:?}"
+                ))),
+                None => Err(BrokerError::UnknownOrder(order_id.to_string())),
+            };
+        };
+        if let Some(price) = order.price {
+            self.portfolio_manager
+                .release_reservation(order.side, &order.symbol, order.qty, price)
+                .await;
+        }
+        self.order_statuses.lock().await.insert(
+            order_id.to_string(),
+            OrderStatus::Cancelled { filled_qty: 0 },
         );
         Ok(())
     }
+    async fn order_status(&self, order_id: &str) -> Option<OrderStatus> {
+        self.order_statuses.lock().await.get(order_id).copied()
+    }
     fn portfolio_manager(&self) -> &PortfolioManager {
         &self.portfolio_manager
     }
+    fn allows_short_selling(&self) -> bool {
+        self.allow_short
+    }
+    async fn on_market_data(&self, data: &MarketData) {
+        self.apply_corporate_actions(data).await;
+        self.fill_resting_orders(data).await;
+    }
 }
 
 impl DummyBroker {
@@ -48,10 +99,448 @@ impl DummyBroker {
             name,
             orders: Default::default(),
             portfolio_manager,
+            resting_orders: Default::default(),
+            order_statuses: Default::default(),
+            pending_corporate_actions: Default::default(),
+            allow_short: false,
+        }
+    }
+
+    /// Let sell orders open short positions
+    pub fn with_short_selling(mut self) -> Self {
+        self.allow_short = true;
+        self
+    }
+
+    /// Adjust the positions for splits and pay out dividends once the market data reaches
+    /// their ex-dates. Meant for backtests on unadjusted prices.
+    pub fn with_corporate_actions(self, corporate_actions: &CorporateActions) -> Self {
+        Self {
+            pending_corporate_actions: Mutex::new(corporate_actions.actions().to_vec()),
+            ..self
         }
     }
 
     pub async fn get_orders(&self) -> Vec<Order> {
         self.orders.lock().await.clone()
     }
+
+    async fn fill(&self, id: usize, order: &Order, price: f64) {
+        let fill = Fill {
+            order_id: id.to_string(),
+            symbol: order.symbol.clone(),
+            qty: order.qty,
+            price,
+            side: order.side,
+            timestamp: Local::now().naive_local(),
+            execution_id: None,
+            commission: 0.0,
+            strategy_name: order.strategy_name.clone(),
+            reservation_price: order.price.unwrap_or(price),
+        };
+        self.portfolio_manager().apply_fill(fill).await;
+        self.order_statuses
+            .lock()
+            .await
+            .insert(id.to_string(), OrderStatus::Filled);
+        info!(
+            "New account status after filled order = {:?}",
+            self.portfolio_snapshot().await
+        );
+    }
+
+    async fn apply_corporate_actions(&self, data: &MarketData) {
+        let due: Vec<_> = {
+            let mut pending = self.pending_corporate_actions.lock().await;
+            if pending.is_empty() {
+                return;
+            }
+            let date = data.timestamp.date_naive();
+            let (due, later) = pending
+                .drain(..)
+                .partition(|action| action.symbol == data.symbol && action.ex_date <= date);
+            *pending = later;
+            due
+        };
+        for action in due {
+            info!("{} applies {:?}", self.name, action);
+            match action.kind {
+                CorporateActionKind::Split(ratio) => {
+                    self.apply_split(&action, ratio, data.price).await
+                }
+                CorporateActionKind::Dividend(_) => {
+                    self.portfolio_manager
+                        .apply_corporate_action(&action, data.price)
+                        .await
+                }
+            }
+        }
+    }
+
+    /// Split the position and the resting orders in the symbol of `action`. The quantity of
+    /// the orders is multiplied by the ratio (rounded down) and their price divided by it, and
+    /// they reserve again for what they became. Orders of less than a share are cancelled.
+    async fn apply_split(&self, action: &CorporateAction, ratio: f64, price: f64) {
+        let orders: Vec<_> = {
+            let mut resting = self.resting_orders.lock().await;
+            let (split, other) = resting
+                .drain(..)
+                .partition(|(_, order)| order.symbol == action.symbol);
+            *resting = other;
+            split
+        };
+        for (_, order) in &orders {
+            if let Some(price) = order.price {
+                self.portfolio_manager
+                    .release_reservation(order.side, &order.symbol, order.qty, price)
+                    .await;
+            }
+        }
+        self.portfolio_manager
+            .apply_corporate_action(action, price)
+            .await;
+        for (id, mut order) in orders {
+            order.qty = (order.qty as f64 * ratio).trunc() as u32;
+            order.price = order.price.map(|price| price / ratio);
+            let reserved = match order.price {
+                Some(price) if order.qty > 0 => self
+                    .portfolio_manager
+                    .pre_reserve_for_order(&order, price, self.allow_short)
+                    .await
+                    .map_err(|err| err.to_string()),
+                _ => Err("less than a share is left".to_string()),
+            };
+            match reserved {
+                Ok(()) => {
+                    let mut resting = self.resting_orders.lock().await;
+                    resting.push((id, order));
+                    // Keep filling in the order they were placed
+                    resting.sort_by_key(|(id, _)| *id);
+                }
+                Err(err) => {
+                    warn!(
+                        "{} cancels order {} after {:?}: {}",
+                        self.name, id, action, err
+                    );
+                    self.order_statuses
+                        .lock()
+                        .await
+                        .insert(id.to_string(), OrderStatus::Cancelled { filled_qty: 0 });
+                }
+            }
+        }
+    }
+
+    /// Fill the limit and stop orders in the symbol of `data` whose price it reached, in the
+    /// order they were placed
+    async fn fill_resting_orders(&self, data: &MarketData) {
+        let filled: Vec<_> = {
+            let mut resting = self.resting_orders.lock().await;
+            if resting.is_empty() {
+                return;
+            }
+            let (filled, waiting) = resting.drain(..).partition(|(_, order)| {
+                order.symbol == data.symbol
+                    && order.price.is_some_and(|price| match order.order_type {
+                        OrderType::Stop => reaches_stop(order.side, price, data),
+                        _ => reaches_limit(order.side, price, data),
+                    })
+            });
+            *resting = waiting;
+            filled
+        };
+        for (id, order) in filled {
+            let Some(price) = order.price else { continue };
+            let price = resting_fill_price(&order, price, data);
+            info!(
+                "{} fills resting order {} at {}: {:?}",
+                self.name, id, price, order
+            );
+            self.fill(id, &order, price).await;
+        }
+    }
+}
+
+/// Whether the market data traded (or is quoted) at or through the limit price of an order
+fn reaches_limit(side: OrderSide, limit: f64, data: &MarketData) -> bool {
+    match (side, data.kind) {
+        (OrderSide::Buy, MarketDataKind::Quote(quote)) => quote.ask > 0.0 && quote.ask <= limit,
+        (OrderSide::Sell, MarketDataKind::Quote(quote)) => quote.bid > 0.0 && quote.bid >= limit,
+        (OrderSide::Buy, _) => data.low <= limit,
+        (OrderSide::Sell, _) => data.high >= limit,
+    }
+}
+
+/// Whether the market data traded (or is quoted) at or through the stop price of an order,
+/// which triggers it
+fn reaches_stop(side: OrderSide, stop: f64, data: &MarketData) -> bool {
+    match (side, data.kind) {
+        (OrderSide::Buy, MarketDataKind::Quote(quote)) => quote.ask > 0.0 && quote.ask >= stop,
+        (OrderSide::Sell, MarketDataKind::Quote(quote)) => quote.bid > 0.0 && quote.bid <= stop,
+        (OrderSide::Buy, _) => data.high >= stop,
+        (OrderSide::Sell, _) => data.low <= stop,
+    }
+}
+
+/// The price a reached limit or stop order fills at: its own price, or the open (the quote for
+/// quotes) when the market gapped through it, which is worse for a stop and better for a limit
+fn resting_fill_price(order: &Order, price: f64, data: &MarketData) -> f64 {
+    let market = match (order.side, data.kind) {
+        (OrderSide::Buy, MarketDataKind::Quote(quote)) => quote.ask,
+        (OrderSide::Sell, MarketDataKind::Quote(quote)) => quote.bid,
+        _ => data.open,
+    };
+    match (&order.order_type, order.side) {
+        (OrderType::Stop, OrderSide::Buy) => price.max(market),
+        (OrderType::Stop, OrderSide::Sell) => price.min(market),
+        (_, OrderSide::Buy) => price.min(market),
+        (_, OrderSide::Sell) => price.max(market),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{corporate_actions::CorporateActionKind, types::OrderSide};
+    use chrono::{DateTime, NaiveDate};
+
+    #[tokio::test]
+    async fn test_corporate_actions_apply_at_ex_date() {
+        let actions = CorporateActions::new(vec![CorporateAction {
+            symbol: "AAPL".into(),
+            ex_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            kind: CorporateActionKind::Split(2.0),
+        }]);
+        let broker = DummyBroker::new("dummy".into()).with_corporate_actions(&actions);
+        broker
+            .place_order(&Order {
+                symbol: "AAPL".into(),
+                side: OrderSide::Buy,
+                qty: 3,
+                price: Some(100.0),
+                order_type: crate::types::OrderType::Market,
+                strategy_name: "test".into(),
+            })
+            .await
+            .unwrap();
+
+        let tick = |timestamp, symbol: &str| {
+            let timestamp = DateTime::from_timestamp(timestamp, 0).unwrap();
+            MarketData::tick(symbol.into(), 50.0, 0.0, timestamp)
+        };
+        // 2024-01-01 and 2024-01-02
+        broker.on_market_data(&tick(1_704_067_200, "AAPL")).await;
+        broker.on_market_data(&tick(1_704_153_600, "MSFT")).await;
+        assert_eq!(broker.position_qty("AAPL").await, 3);
+        broker.on_market_data(&tick(1_704_153_600, "AAPL")).await;
+        assert_eq!(broker.position_qty("AAPL").await, 6);
+        broker.on_market_data(&tick(1_704_240_000, "AAPL")).await;
+        assert_eq!(broker.position_qty("AAPL").await, 6);
+    }
+
+    #[tokio::test]
+    async fn test_splits_adjust_resting_orders() {
+        let actions = CorporateActions::new(vec![CorporateAction {
+            symbol: "AAPL".into(),
+            ex_date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+            kind: CorporateActionKind::Split(2.0),
+        }]);
+        let broker = DummyBroker::new("dummy".into()).with_corporate_actions(&actions);
+        let order = |side, qty, price, order_type| Order {
+            symbol: "AAPL".into(),
+            side,
+            qty,
+            price: Some(price),
+            order_type,
+            strategy_name: "test".into(),
+        };
+        let buy = order(OrderSide::Buy, 3, 100.0, OrderType::Market);
+        let limit_buy = order(OrderSide::Buy, 3, 90.0, OrderType::Limit);
+        let stop_sell = order(OrderSide::Sell, 3, 80.0, OrderType::Stop);
+        for order in [&buy, &limit_buy, &stop_sell] {
+            broker
+                .portfolio_pre_reserve_for_order(order, order.price.unwrap())
+                .await
+                .unwrap();
+            broker.place_order(order).await.unwrap();
+        }
+        let tick = |timestamp, price| {
+            let timestamp = DateTime::from_timestamp(timestamp, 0).unwrap();
+            MarketData::tick("AAPL".into(), price, 0.0, timestamp)
+        };
+
+        // 2024-01-02
+        broker.on_market_data(&tick(1_704_153_600, 50.0)).await;
+        assert_eq!(broker.position_qty("AAPL").await, 6);
+        assert_eq!(broker.portfolio_snapshot().await.reserved_cash, 270.0);
+        // The 6 split shares are locked by the stop sell
+        let sell = order(OrderSide::Sell, 1, 50.0, OrderType::Market);
+        assert!(
+            broker
+                .portfolio_pre_reserve_for_order(&sell, 50.0)
+                .await
+                .is_err()
+        );
+
+        broker.on_market_data(&tick(1_704_153_660, 44.0)).await;
+        assert_eq!(broker.position_qty("AAPL").await, 12);
+        assert_eq!(broker.portfolio_snapshot().await.reserved_cash, 0.0);
+        broker.on_market_data(&tick(1_704_153_720, 39.0)).await;
+        assert_eq!(broker.position_qty("AAPL").await, 6);
+    }
+
+    #[tokio::test]
+    async fn test_limit_orders_rest_until_reached() {
+        let broker = DummyBroker::new("dummy".into());
+        let limit_buy = |price| Order {
+            symbol: "AAPL".into(),
+            side: OrderSide::Buy,
+            qty: 2,
+            price: Some(price),
+            order_type: crate::types::OrderType::Limit,
+            strategy_name: "test".into(),
+        };
+        let tick = |price| {
+            let timestamp = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+            MarketData::tick("AAPL".into(), price, 0.0, timestamp)
+        };
+        for price in [95.0, 90.0] {
+            broker
+                .portfolio_pre_reserve_for_order(&limit_buy(price), price)
+                .await
+                .unwrap();
+        }
+        let first = broker.place_order(&limit_buy(95.0)).await.unwrap();
+        let second = broker.place_order(&limit_buy(90.0)).await.unwrap();
+        assert_eq!(
+            broker.order_status(&first).await,
+            Some(OrderStatus::Open { filled_qty: 0 })
+        );
+
+        broker.on_market_data(&tick(96.0)).await;
+        assert_eq!(broker.position_qty("AAPL").await, 0);
+        broker.on_market_data(&tick(94.0)).await;
+        assert_eq!(broker.order_status(&first).await, Some(OrderStatus::Filled));
+        assert_eq!(broker.position_qty("AAPL").await, 2);
+
+        broker.cancel_order(&second).await.unwrap();
+        assert_eq!(
+            broker.order_status(&second).await,
+            Some(OrderStatus::Cancelled { filled_qty: 0 })
+        );
+        assert!(broker.cancel_order(&first).await.is_err());
+        broker.on_market_data(&tick(80.0)).await;
+        let account = broker.portfolio_snapshot().await;
+        // The tick traded below the limit, so the order filled at the tick
+        assert_eq!(account.cash, 1000.0 - 188.0);
+        assert_eq!(account.reserved_cash, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_stop_orders_rest_until_triggered() {
+        let broker = DummyBroker::new("dummy".into());
+        let order = |side, order_type, price| Order {
+            symbol: "AAPL".into(),
+            side,
+            qty: 5,
+            price: Some(price),
+            order_type,
+            strategy_name: "test".into(),
+        };
+        let tick = |price| {
+            let timestamp = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+            MarketData::tick("AAPL".into(), price, 0.0, timestamp)
+        };
+        let buy = order(OrderSide::Buy, OrderType::Market, 100.0);
+        broker
+            .portfolio_pre_reserve_for_order(&buy, 100.0)
+            .await
+            .unwrap();
+        broker.place_order(&buy).await.unwrap();
+
+        let stop_loss = order(OrderSide::Sell, OrderType::Stop, 90.0);
+        broker
+            .portfolio_pre_reserve_for_order(&stop_loss, 90.0)
+            .await
+            .unwrap();
+        let stop_id = broker.place_order(&stop_loss).await.unwrap();
+        // The stop locks the shares it will sell
+        let take_profit = order(OrderSide::Sell, OrderType::Limit, 120.0);
+        assert!(
+            broker
+                .portfolio_pre_reserve_for_order(&take_profit, 120.0)
+                .await
+                .is_err()
+        );
+
+        broker.on_market_data(&tick(95.0)).await;
+        assert_eq!(
+            broker.order_status(&stop_id).await,
+            Some(OrderStatus::Open { filled_qty: 0 })
+        );
+        broker.on_market_data(&tick(89.0)).await;
+        assert_eq!(
+            broker.order_status(&stop_id).await,
+            Some(OrderStatus::Filled)
+        );
+        assert_eq!(broker.position_qty("AAPL").await, 0);
+        assert_eq!(
+            broker.portfolio_snapshot().await.cash,
+            1000.0 - 500.0 + 445.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resting_orders_fill_at_the_open_when_the_market_gaps() {
+        let broker = DummyBroker::new("dummy".into());
+        let order = |side, order_type, price| Order {
+            symbol: "AAPL".into(),
+            side,
+            qty: 5,
+            price: Some(price),
+            order_type,
+            strategy_name: "test".into(),
+        };
+        let bar = |open: f64, high: f64, low: f64| {
+            let timestamp = DateTime::from_timestamp(1_704_067_200, 0).unwrap();
+            let mut data = MarketData::tick("AAPL".into(), open, 0.0, timestamp);
+            data.open = open;
+            data.high = high;
+            data.low = low;
+            data
+        };
+        let buy = order(OrderSide::Buy, OrderType::Market, 100.0);
+        broker
+            .portfolio_pre_reserve_for_order(&buy, 100.0)
+            .await
+            .unwrap();
+        broker.place_order(&buy).await.unwrap();
+        let stop_loss = order(OrderSide::Sell, OrderType::Stop, 90.0);
+        broker
+            .portfolio_pre_reserve_for_order(&stop_loss, 90.0)
+            .await
+            .unwrap();
+        broker.place_order(&stop_loss).await.unwrap();
+
+        // The bar opens below the stop, which sells at the open rather than at the stop
+        broker.on_market_data(&bar(85.0, 87.0, 80.0)).await;
+        assert_eq!(broker.position_qty("AAPL").await, 0);
+        assert_eq!(
+            broker.portfolio_snapshot().await.cash,
+            1000.0 - 500.0 + 425.0
+        );
+
+        let limit_buy = order(OrderSide::Buy, OrderType::Limit, 95.0);
+        broker
+            .portfolio_pre_reserve_for_order(&limit_buy, 95.0)
+            .await
+            .unwrap();
+        broker.place_order(&limit_buy).await.unwrap();
+        // The bar opens below the limit, which buys at the open rather than at the limit
+        broker.on_market_data(&bar(92.0, 96.0, 91.0)).await;
+        assert_eq!(broker.position_qty("AAPL").await, 5);
+        let account = broker.portfolio_snapshot().await;
+        assert_eq!(account.cash, 1000.0 - 500.0 + 425.0 - 460.0);
+        assert_eq!(account.reserved_cash, 0.0);
+    }
 }
@@ -1,24 +1,34 @@
-use crate::types::{Fill, Order, OrderSide, Position};
+use crate::{
+    calendar::ExchangeCalendar,
+    data_feed::spawn_ib_task,
+    ib_connection::IbConnection,
+    types::{Fill, Order, OrderId, OrderSide, OrderStatus, OrderType, Position},
+};
 use async_trait::async_trait;
-use chrono::Local;
+use chrono::{Local, Utc};
 use ibapi::{
-    Client,
     accounts::{AccountPortfolioValue, AccountUpdate, AccountValue},
     contracts::Contract,
     orders::{Action, OrderUpdate, order_builder},
 };
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 use thiserror::Error;
 use tokio::sync::Mutex;
-use tracing::info;
+use tracing::{debug, error, info, warn};
 
 use super::{Broker, BrokerError, Portfolio, PortfolioManager};
 
 pub struct Ib {
     name: String,
-    client: Arc<Client>,
+    connection: Arc<IbConnection>,
     portfolio_manager: Arc<PortfolioManager>,
-    open_orders: Arc<Mutex<HashMap<i32, Order>>>,
+    order_tracker: Arc<Mutex<IbOrderTracker>>,
+    /// Without a calendar, orders may always execute outside regular trading hours
+    calendar: Option<Arc<ExchangeCalendar>>,
+    allow_short: bool,
 }
 
 #[async_trait]
@@ -26,35 +36,76 @@ impl Broker for Ib {
     fn name(&self) -> &str {
         &self.name
     }
-    async fn place_order(&self, order: &Order) -> Result<(), BrokerError> {
-        let order_id = self
-            .client
+    async fn place_order(&self, order: &Order) -> Result<OrderId, BrokerError> {
+        if !self.connection.is_connected() {
+            return Err(BrokerError::Disconnected(
+                self.connection.name().to_string(),
+            ));
+        }
+        let client = self.connection.client();
+        let order_id = client
             .next_valid_order_id()
             .map_err(|err| BrokerError::PlaceOrder(err.to_string()))?;
         // TODO: Do not build only stock contracts
         let contract = Contract::stock(&order.symbol);
-        // TODO: Do not build only market orders
-        let mut ib_order = order_builder::market_order(order.side.into(), order.qty as f64);
-        // TODO: Should this be kept true? Maybe make it configurable?
-        ib_order.outside_rth = true;
-        let _subscription = self
-            .client
-            .place_order(order_id, &contract, &ib_order)
-            .map_err(|err| BrokerError::PlaceOrder(err.to_string()))?;
-        self.open_orders
+        let (action, qty) = (order.side.into(), order.qty as f64);
+        let mut ib_order = match (&order.order_type, order.price) {
+            (OrderType::Limit, Some(price)) => order_builder::limit_order(action, qty, price),
+            (OrderType::Stop, Some(price)) => order_builder::stop(action, qty, price),
+            _ => order_builder::market_order(action, qty),
+        };
+        // Orders placed in the pre-market or after hours are meant to execute there, the
+        // others wait for the regular session
+        ib_order.outside_rth = match &self.calendar {
+            Some(calendar) => calendar.session(Utc::now()).is_extended(),
+            None => true,
+        };
+        // Track the order before it is placed, its first executions may arrive right away
+        self.order_tracker
             .lock()
             .await
             .insert(order_id, order.clone());
+        let _subscription = match client.place_order(order_id, &contract, &ib_order) {
+            Ok(subscription) => subscription,
+            Err(err) => {
+                self.order_tracker.lock().await.remove(order_id);
+                return Err(BrokerError::PlaceOrder(err.to_string()));
+            }
+        };
 
+        Ok(order_id.to_string())
+    }
+    /// The cash is released once Interactive Brokers reports the order as cancelled
+    async fn cancel_order(&self, order_id: &str) -> Result<(), BrokerError> {
+        let id = order_id
+            .parse()
+            .map_err(|_| BrokerError::UnknownOrder(order_id.to_string()))?;
+        if !self.connection.is_connected() {
+            return Err(BrokerError::Disconnected(
+                self.connection.name().to_string(),
+            ));
+        }
+        let client = self.connection.client();
+        let _subscription = client
+            .cancel_order(id, "")
+            .map_err(|err| BrokerError::CancelOrder(err.to_string()))?;
         Ok(())
     }
+    async fn order_status(&self, order_id: &str) -> Option<OrderStatus> {
+        let id = order_id.parse().ok()?;
+        self.order_tracker.lock().await.statuses.get(&id).copied()
+    }
     fn portfolio_manager(&self) -> &PortfolioManager {
         &self.portfolio_manager
     }
+    fn allows_short_selling(&self) -> bool {
+        self.allow_short
+    }
 }
 
 impl Ib {
-    pub fn new(name: String, client: Arc<Client>) -> Result<Self, IbError> {
+    pub fn new(name: String, connection: Arc<IbConnection>) -> Result<Self, IbError> {
+        let client = connection.client();
         let mut cash = 0.0;
         let reserved_cash = 0.0;
         let mut positions = HashMap::new();
@@ -89,7 +140,7 @@ impl Ib {
                         // TODO: Decide what to do with position's currency. The account might be in EUR and the avg_cost here in USD
                         let position = Position {
                             symbol: contract.symbol.clone(),
-                            qty: position as u32, // TODO: Consider using f64 for qty of positions
+                            qty: position as i64, // TODO: Consider using f64 for qty of positions
                             avg_price: average_cost,
                         };
                         positions.insert(contract.symbol, position);
@@ -107,61 +158,258 @@ impl Ib {
         let portfolio = Portfolio::new(cash, 0.0, positions);
         let portfolio_manager = Arc::new(PortfolioManager::new(portfolio));
 
-        let client_clone = client.clone();
-        let open_orders = Arc::new(Mutex::new(HashMap::<i32, Order>::new()));
-        let open_orders_clone = open_orders.clone();
+        let connection_clone = connection.clone();
+        let order_tracker = Arc::new(Mutex::new(IbOrderTracker::default()));
+        let order_tracker_clone = order_tracker.clone();
         let portfolio_manager_clone = portfolio_manager.clone();
-        tokio::spawn(async move {
+        // The update stream blocks its thread until the next update
+        spawn_ib_task(async move {
             // TODO: Consider shutting down this task
-            // TODO: When that fails we should get notified and probably stop using that broker
-            let updates = client_clone.order_update_stream().unwrap();
-            for update in updates {
-                match update {
-                    OrderUpdate::OrderStatus(status) => {
-                        info!(
-                            "Interactive Brokers order {} status: {} - filled: {}/{}",
-                            status.order_id, status.status, status.filled, status.remaining
-                        );
-
-                        if status.status == "Filled" {
-                            let order_id = status.order_id;
-                            if let Some(order) = open_orders_clone.lock().await.remove(&order_id) {
-                                let fill = Fill {
-                                    order_id: order_id.to_string(),
-                                    symbol: order.symbol,
-                                    qty: order.qty,
-                                    price: status.average_fill_price,
-                                    side: order.side,
-                                    timestamp: Local::now().naive_local(),
-                                };
-                                info!("Apply fill: {:?}", fill);
-                                portfolio_manager_clone.apply_fill(fill).await;
-                            }
+            let mut client = connection_clone.client();
+            loop {
+                match client.order_update_stream() {
+                    Ok(updates) => {
+                        for update in updates {
+                            handle_order_update(
+                                update,
+                                &order_tracker_clone,
+                                &portfolio_manager_clone,
+                            )
+                            .await;
                         }
+                        warn!("Interactive Brokers order update stream ended");
                     }
-                    OrderUpdate::OpenOrder(order_data) => {
-                        info!(
-                            "Interactive Brokers open order {}: {} {} @ {}",
-                            order_data.order.order_id,
-                            order_data.order.action,
-                            order_data.order.total_quantity,
-                            order_data.order.limit_price.unwrap_or(0.0)
-                        );
+                    Err(err) => {
+                        error!("Failed to subscribe to Interactive Brokers order updates: {err}")
                     }
-                    OrderUpdate::Message(notice) => {
-                        info!("Interactive Brokers order message: {}", notice.message);
-                    }
-                    _ => {}
                 }
+                // Executions that are re-sent after the reconnection are de-duplicated by the tracker
+                client = connection_clone.wait_for_new_client(&client).await;
+                info!("Re-subscribing to Interactive Brokers order updates");
             }
         });
         Ok(Self {
             name,
-            client,
+            connection,
             portfolio_manager,
-            open_orders,
+            order_tracker,
+            calendar: None,
+            allow_short: false,
         })
     }
+
+    /// Let orders execute outside regular trading hours only when they are placed outside them
+    pub fn with_calendar(mut self, calendar: Arc<ExchangeCalendar>) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    /// Let sell orders open short positions. Whether they execute is up to the margin of the
+    /// account.
+    pub fn with_short_selling(mut self) -> Self {
+        self.allow_short = true;
+        self
+    }
+}
+
+/// An order placed at Interactive Brokers
+struct IbOrder {
+    order: Order,
+    /// Cumulative quantity reported through execution details so far
+    filled_qty: u32,
+}
+
+/// Turns the (possibly partial) executions that Interactive Brokers reports for our orders into
+/// fills and keeps track of their status.
+///
+/// IB sends the execution details first and the commission report for that execution
+/// afterwards. The fill is applied right away, so that the position is up to date, and its
+/// commission is booked once it is reported. Executions may also arrive after the order was
+/// reported cancelled, so orders are never forgotten.
+#[derive(Default)]
+struct IbOrderTracker {
+    /// Every order placed since the start
+    orders: HashMap<i32, IbOrder>,
+    /// Execution ids whose commission was not reported yet
+    uncharged_executions: HashSet<String>,
+    /// Execution ids of our orders that were already recorded. IB may report the same execution
+    /// more than once.
+    seen_executions: HashSet<String>,
+    /// The status of every order placed since the start
+    statuses: HashMap<i32, OrderStatus>,
+}
+
+impl IbOrderTracker {
+    fn insert(&mut self, order_id: i32, order: Order) {
+        self.orders.insert(
+            order_id,
+            IbOrder {
+                order,
+                filled_qty: 0,
+            },
+        );
+        self.statuses
+            .insert(order_id, OrderStatus::Open { filled_qty: 0 });
+    }
+
+    /// Forget an order that could not be placed
+    fn remove(&mut self, order_id: i32) {
+        self.orders.remove(&order_id);
+        self.statuses.remove(&order_id);
+    }
+
+    /// Returns the fill of a new execution of one of our orders, without its commission
+    fn on_execution(
+        &mut self,
+        order_id: i32,
+        execution_id: String,
+        shares: f64,
+        price: f64,
+    ) -> Option<Fill> {
+        let Some(ib_order) = self.orders.get_mut(&order_id) else {
+            debug!(
+                "Ignoring execution {} of unknown order {}",
+                execution_id, order_id
+            );
+            return None;
+        };
+        if !self.seen_executions.insert(execution_id.clone()) {
+            debug!("Ignoring already recorded execution {}", execution_id);
+            return None;
+        }
+        let qty = shares as u32;
+        ib_order.filled_qty += qty;
+        let filled_qty = ib_order.filled_qty;
+        let status = match self.statuses.get(&order_id) {
+            Some(OrderStatus::Cancelled { .. }) => OrderStatus::Cancelled { filled_qty },
+            _ if filled_qty >= ib_order.order.qty => OrderStatus::Filled,
+            _ => OrderStatus::Open { filled_qty },
+        };
+        self.statuses.insert(order_id, status);
+        self.uncharged_executions.insert(execution_id.clone());
+        Some(Fill {
+            order_id: order_id.to_string(),
+            symbol: ib_order.order.symbol.clone(),
+            qty,
+            price,
+            side: ib_order.order.side,
+            timestamp: Local::now().naive_local(),
+            execution_id: Some(execution_id),
+            commission: 0.0,
+            strategy_name: ib_order.order.strategy_name.clone(),
+            // Orders without a price did not reserve anything
+            reservation_price: ib_order.order.price.unwrap_or(price),
+        })
+    }
+
+    /// Whether the commission of `execution_id` is due, i.e. the execution is one of our fills
+    /// and its commission was not booked yet
+    fn on_commission_report(&mut self, execution_id: &str) -> bool {
+        self.uncharged_executions.remove(execution_id)
+    }
+
+    /// Returns the order and its unfilled quantity, whose reservation should be released, if
+    /// the order reached a terminal state without being completely filled.
+    fn on_order_status(
+        &mut self,
+        order_id: i32,
+        status: &str,
+        filled: f64,
+    ) -> Option<(Order, u32)> {
+        if !matches!(status, "Cancelled" | "ApiCancelled" | "Inactive") {
+            return None;
+        }
+        // Only the first terminal status releases the reservation
+        if !matches!(self.statuses.get(&order_id), Some(OrderStatus::Open { .. })) {
+            return None;
+        }
+        let ib_order = self.orders.get(&order_id)?;
+        // The executions of the filled part may still be on their way
+        let filled_qty = ib_order.filled_qty.max(filled as u32);
+        self.statuses
+            .insert(order_id, OrderStatus::Cancelled { filled_qty });
+        let unfilled_qty = ib_order.order.qty.saturating_sub(filled_qty);
+        (unfilled_qty > 0).then(|| (ib_order.order.clone(), unfilled_qty))
+    }
+}
+
+async fn handle_order_update(
+    update: OrderUpdate,
+    order_tracker: &Mutex<IbOrderTracker>,
+    portfolio_manager: &PortfolioManager,
+) {
+    match update {
+        OrderUpdate::OrderStatus(status) => {
+            info!(
+                "Interactive Brokers order {} status: {} - filled: {}/{}",
+                status.order_id, status.status, status.filled, status.remaining
+            );
+            let release = order_tracker.lock().await.on_order_status(
+                status.order_id,
+                &status.status,
+                status.filled,
+            );
+            // Orders are released at the price they reserved at, market orders included.
+            // Orders without a price did not reserve anything.
+            if let Some((order, qty)) = release
+                && let Some(price) = order.price
+            {
+                info!(
+                    "Release the reservation for {} unfilled units of order {} at {}",
+                    qty, status.order_id, price
+                );
+                portfolio_manager
+                    .release_reservation(order.side, &order.symbol, qty, price)
+                    .await;
+            }
+        }
+        OrderUpdate::ExecutionData(execution_data) => {
+            let execution = execution_data.execution;
+            info!(
+                "Interactive Brokers execution {} for order {}: {} {} @ {}",
+                execution.execution_id,
+                execution.order_id,
+                execution.side,
+                execution.shares,
+                execution.price
+            );
+            let fill = order_tracker.lock().await.on_execution(
+                execution.order_id,
+                execution.execution_id,
+                execution.shares,
+                execution.price,
+            );
+            if let Some(fill) = fill {
+                info!("Apply fill: {:?}", fill);
+                portfolio_manager.apply_fill(fill).await;
+            }
+        }
+        OrderUpdate::CommissionReport(report) => {
+            info!(
+                "Interactive Brokers commission report for execution {}: {} {}",
+                report.execution_id, report.commission, report.currency
+            );
+            let due = order_tracker
+                .lock()
+                .await
+                .on_commission_report(&report.execution_id);
+            if due {
+                portfolio_manager.apply_commission(report.commission).await;
+            }
+        }
+        OrderUpdate::OpenOrder(order_data) => {
+            info!(
+                "Interactive Brokers open order {}: {} {} @ {}",
+                order_data.order.order_id,
+                order_data.order.action,
+                order_data.order.total_quantity,
+                order_data.order.limit_price.unwrap_or(0.0)
+            );
+        }
+        OrderUpdate::Message(notice) => {
+            info!("Interactive Brokers order message: {}", notice.message);
+        }
+    }
 }
 
 impl From<OrderSide> for Action {
@@ -178,3 +426,124 @@ pub enum IbError {
     #[error("Interactive Broker initiazlization failed: {0}")]
     Init(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_order(side: OrderSide, qty: u32, price: f64) -> Order {
+        Order {
+            symbol: "AAPL".into(),
+            side,
+            qty,
+            price: Some(price),
+            order_type: OrderType::Market,
+            strategy_name: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_order_tracker_partial_fills_with_commission() {
+        let mut tracker = IbOrderTracker::default();
+        tracker.insert(1, make_order(OrderSide::Buy, 10, 100.0));
+
+        let fill = tracker.on_execution(1, "exec-1".into(), 4.0, 99.5).unwrap();
+        assert_eq!(fill.qty, 4);
+        assert_eq!(fill.price, 99.5);
+        assert_eq!(fill.commission, 0.0);
+        assert_eq!(fill.execution_id.as_deref(), Some("exec-1"));
+        assert_eq!(tracker.statuses[&1], OrderStatus::Open { filled_qty: 4 });
+        // The commission is due once, and only for our executions
+        assert!(!tracker.on_commission_report("exec-2"));
+        assert!(tracker.on_commission_report("exec-1"));
+        assert!(!tracker.on_commission_report("exec-1"));
+
+        let fill = tracker
+            .on_execution(1, "exec-2".into(), 6.0, 100.5)
+            .unwrap();
+        assert_eq!(fill.qty, 6);
+        assert_eq!(fill.price, 100.5);
+        assert_eq!(tracker.statuses[&1], OrderStatus::Filled);
+        assert!(tracker.on_commission_report("exec-2"));
+    }
+
+    #[test]
+    fn test_order_tracker_ignores_duplicate_executions() {
+        let mut tracker = IbOrderTracker::default();
+        tracker.insert(1, make_order(OrderSide::Buy, 10, 100.0));
+
+        assert!(
+            tracker
+                .on_execution(1, "exec-1".into(), 4.0, 100.0)
+                .is_some()
+        );
+        assert!(
+            tracker
+                .on_execution(1, "exec-1".into(), 4.0, 100.0)
+                .is_none()
+        );
+        assert_eq!(tracker.orders[&1].filled_qty, 4);
+        // Unknown orders are ignored, without marking their executions as recorded
+        assert!(
+            tracker
+                .on_execution(2, "exec-2".into(), 4.0, 100.0)
+                .is_none()
+        );
+        tracker.insert(2, make_order(OrderSide::Buy, 10, 100.0));
+        assert!(
+            tracker
+                .on_execution(2, "exec-2".into(), 4.0, 100.0)
+                .is_some()
+        );
+        // Orders that could not be placed are forgotten
+        tracker.remove(2);
+        assert!(!tracker.statuses.contains_key(&2));
+        assert!(
+            tracker
+                .on_execution(2, "exec-3".into(), 4.0, 100.0)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_order_tracker_cancel_releases_unfilled_reservation() {
+        let mut tracker = IbOrderTracker::default();
+        tracker.insert(1, make_order(OrderSide::Buy, 10, 100.0));
+        tracker.insert(2, make_order(OrderSide::Sell, 10, 100.0));
+
+        tracker.on_execution(1, "exec-1".into(), 3.0, 100.0);
+        let release = |tracker: &mut IbOrderTracker, id, status, filled| {
+            tracker
+                .on_order_status(id, status, filled)
+                .map(|(order, qty)| (order.side, qty))
+        };
+        assert_eq!(release(&mut tracker, 1, "PreSubmitted", 3.0), None);
+        // IB reports 5 filled, 2 of which did not arrive as executions yet
+        assert_eq!(
+            release(&mut tracker, 1, "Cancelled", 5.0),
+            Some((OrderSide::Buy, 5))
+        );
+        assert_eq!(
+            tracker.statuses[&1],
+            OrderStatus::Cancelled { filled_qty: 5 }
+        );
+        // Released only once
+        assert_eq!(release(&mut tracker, 1, "Cancelled", 5.0), None);
+        // The late execution is still applied
+        let fill = tracker
+            .on_execution(1, "exec-2".into(), 2.0, 100.0)
+            .unwrap();
+        assert_eq!(fill.qty, 2);
+        assert_eq!(
+            tracker.statuses[&1],
+            OrderStatus::Cancelled { filled_qty: 5 }
+        );
+        // The margin of short sales is released as well
+        assert_eq!(
+            release(&mut tracker, 2, "Inactive", 0.0),
+            Some((OrderSide::Sell, 10))
+        );
+        // Unknown orders are ignored
+        assert!(tracker.on_order_status(3, "Cancelled", 0.0).is_none());
+    }
+}
@@ -0,0 +1,421 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+
+/// The trading session an instant falls in
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub enum Session {
+    PreMarket,
+    Regular,
+    AfterHours,
+    Closed,
+}
+
+impl Session {
+    /// Pre-market and after-hours trading
+    pub fn is_extended(self) -> bool {
+        matches!(self, Self::PreMarket | Self::AfterHours)
+    }
+}
+
+/// When daylight saving time applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DstRule {
+    /// From the second Sunday of March to the first Sunday of November, at 2:00 local time
+    UnitedStates,
+    /// From the last Sunday of March to the last Sunday of October, at 1:00 UTC
+    Europe,
+}
+
+/// A time zone with a fixed standard offset and an optional daylight saving time rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeZoneRule {
+    pub standard_offset_minutes: i32,
+    pub dst: Option<DstRule>,
+}
+
+impl TimeZoneRule {
+    pub const US_EASTERN: Self = Self {
+        standard_offset_minutes: -5 * 60,
+        dst: Some(DstRule::UnitedStates),
+    };
+    pub const CENTRAL_EUROPE: Self = Self {
+        standard_offset_minutes: 60,
+        dst: Some(DstRule::Europe),
+    };
+
+    pub fn to_local(&self, timestamp: DateTime<Utc>) -> NaiveDateTime {
+        timestamp.naive_utc() + self.offset(timestamp)
+    }
+
+    /// The instant of a local time. Local times that are skipped or repeated when the clocks
+    /// change are resolved with the offset before the change.
+    pub fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        let standard = local - Duration::minutes(self.standard_offset_minutes.into());
+        let before_change = (standard - Duration::hours(1)).and_utc();
+        (local - self.offset(before_change)).and_utc()
+    }
+
+    fn offset(&self, timestamp: DateTime<Utc>) -> Duration {
+        let dst = self.dst.is_some_and(|rule| rule.applies(timestamp));
+        Duration::minutes(i64::from(self.standard_offset_minutes) + if dst { 60 } else { 0 })
+    }
+}
+
+impl DstRule {
+    fn applies(self, timestamp: DateTime<Utc>) -> bool {
+        let year = timestamp.year();
+        let utc = |date: NaiveDate, hour: u32| date.and_hms_opt(hour, 0, 0).unwrap().and_utc();
+        let (start, end) = match self {
+            // 2:00 EST is 7:00 UTC, 2:00 EDT is 6:00 UTC
+            Self::UnitedStates => (
+                utc(nth_weekday(year, 3, Weekday::Sun, 2), 7),
+                utc(nth_weekday(year, 11, Weekday::Sun, 1), 6),
+            ),
+            Self::Europe => (
+                utc(last_weekday(year, 3, Weekday::Sun), 1),
+                utc(last_weekday(year, 10, Weekday::Sun), 1),
+            ),
+        };
+        start <= timestamp && timestamp < end
+    }
+}
+
+/// The holidays (and early closes) of an exchange that follow from rules, on top of the ones
+/// that are listed explicitly
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq, Eq)]
+pub enum Exchange {
+    /// New York Stock Exchange (and Nasdaq, which has the same hours and holidays)
+    Nyse,
+    /// Deutsche Börse Xetra
+    Xetra,
+}
+
+/// Trading hours, holidays and early closes of an exchange
+#[derive(Debug, Clone)]
+pub struct ExchangeCalendar {
+    exchange: Exchange,
+    time_zone: TimeZoneRule,
+    pre_market_open: NaiveTime,
+    regular_open: NaiveTime,
+    regular_close: NaiveTime,
+    after_hours_close: NaiveTime,
+    /// Close of the regular session on the early closes given by the rules
+    early_close: NaiveTime,
+    holidays: HashSet<NaiveDate>,
+    early_closes: HashMap<NaiveDate, NaiveTime>,
+}
+
+impl ExchangeCalendar {
+    pub fn new(exchange: Exchange) -> Self {
+        let time = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap();
+        let (time_zone, pre_market_open, regular_open, regular_close, after_hours_close) =
+            match exchange {
+                Exchange::Nyse => (
+                    TimeZoneRule::US_EASTERN,
+                    time(4, 0),
+                    time(9, 30),
+                    time(16, 0),
+                    time(20, 0),
+                ),
+                Exchange::Xetra => (
+                    TimeZoneRule::CENTRAL_EUROPE,
+                    time(8, 0),
+                    time(9, 0),
+                    time(17, 30),
+                    time(20, 0),
+                ),
+            };
+        Self {
+            exchange,
+            time_zone,
+            pre_market_open,
+            regular_open,
+            regular_close,
+            after_hours_close,
+            early_close: time(13, 0),
+            holidays: HashSet::new(),
+            early_closes: HashMap::new(),
+        }
+    }
+
+    /// Add holidays that the rules of the exchange do not know, e.g. national days of mourning
+    pub fn with_holidays(mut self, holidays: impl IntoIterator<Item = NaiveDate>) -> Self {
+        self.holidays.extend(holidays);
+        self
+    }
+
+    /// Close the regular session of `date` early, at `close`
+    pub fn with_early_close(mut self, date: NaiveDate, close: NaiveTime) -> Self {
+        self.early_closes.insert(date, close);
+        self
+    }
+
+    pub fn time_zone(&self) -> TimeZoneRule {
+        self.time_zone
+    }
+
+    /// The date of `timestamp` at the exchange
+    pub fn local_date(&self, timestamp: DateTime<Utc>) -> NaiveDate {
+        self.time_zone.to_local(timestamp).date()
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun)
+            && !self.holidays.contains(&date)
+            && !match self.exchange {
+                Exchange::Nyse => is_nyse_holiday(date),
+                Exchange::Xetra => is_xetra_holiday(date),
+            }
+    }
+
+    pub fn session(&self, timestamp: DateTime<Utc>) -> Session {
+        let local = self.time_zone.to_local(timestamp);
+        if !self.is_trading_day(local.date()) {
+            return Session::Closed;
+        }
+        let time = local.time();
+        if time < self.pre_market_open {
+            Session::Closed
+        } else if time < self.regular_open {
+            Session::PreMarket
+        } else if time < self.regular_close_on(local.date()) {
+            Session::Regular
+        } else if time < self.after_hours_close {
+            Session::AfterHours
+        } else {
+            Session::Closed
+        }
+    }
+
+    pub fn is_regular(&self, timestamp: DateTime<Utc>) -> bool {
+        self.session(timestamp) == Session::Regular
+    }
+
+    /// The end of the regular session of the (local) day of `timestamp`. None if the exchange
+    /// does not trade that day.
+    pub fn regular_close(&self, timestamp: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let date = self.local_date(timestamp);
+        self.is_trading_day(date).then(|| {
+            self.time_zone
+                .to_utc(date.and_time(self.regular_close_on(date)))
+        })
+    }
+
+    /// The start of the next regular session after `timestamp`
+    pub fn next_regular_open(&self, timestamp: DateTime<Utc>) -> DateTime<Utc> {
+        let mut date = self.local_date(timestamp);
+        loop {
+            if self.is_trading_day(date) {
+                let open = self.time_zone.to_utc(date.and_time(self.regular_open));
+                if open > timestamp {
+                    return open;
+                }
+            }
+            date = date.succ_opt().unwrap();
+        }
+    }
+
+    fn regular_close_on(&self, date: NaiveDate) -> NaiveTime {
+        if let Some(close) = self.early_closes.get(&date) {
+            return *close;
+        }
+        match self.exchange {
+            Exchange::Nyse if is_nyse_early_close(date) => self.early_close,
+            _ => self.regular_close,
+        }
+    }
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap()
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5)
+        .unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+
+/// Easter Sunday of the Gregorian calendar (anonymous Gregorian algorithm)
+fn easter(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+/// A fixed date holiday that is observed on Friday when it falls on a Saturday and on Monday
+/// when it falls on a Sunday
+fn observed(year: i32, month: u32, day: u32) -> NaiveDate {
+    let date = NaiveDate::from_ymd_opt(year, month, day).unwrap();
+    match date.weekday() {
+        Weekday::Sat => date.pred_opt().unwrap(),
+        Weekday::Sun => date.succ_opt().unwrap(),
+        _ => date,
+    }
+}
+
+fn is_nyse_holiday(date: NaiveDate) -> bool {
+    let year = date.year();
+    // New Year's Day is not moved to the last trading day of the previous year
+    let new_year = NaiveDate::from_ymd_opt(year, 1, 1).unwrap();
+    let new_year = if new_year.weekday() == Weekday::Sun {
+        new_year.succ_opt().unwrap()
+    } else {
+        new_year
+    };
+    let mut holidays = vec![
+        new_year,
+        nth_weekday(year, 1, Weekday::Mon, 3),
+        nth_weekday(year, 2, Weekday::Mon, 3),
+        easter(year) - Duration::days(2),
+        last_weekday(year, 5, Weekday::Mon),
+        observed(year, 7, 4),
+        nth_weekday(year, 9, Weekday::Mon, 1),
+        nth_weekday(year, 11, Weekday::Thu, 4),
+        observed(year, 12, 25),
+    ];
+    if year >= 2022 {
+        holidays.push(observed(year, 6, 19));
+    }
+    holidays.contains(&date)
+}
+
+/// The regular session closes at 13:00 before Independence Day, after Thanksgiving and on
+/// Christmas Eve
+fn is_nyse_early_close(date: NaiveDate) -> bool {
+    let year = date.year();
+    let early_closes = [
+        NaiveDate::from_ymd_opt(year, 7, 3).unwrap(),
+        nth_weekday(year, 11, Weekday::Thu, 4).succ_opt().unwrap(),
+        NaiveDate::from_ymd_opt(year, 12, 24).unwrap(),
+    ];
+    early_closes.contains(&date) && !is_nyse_holiday(date)
+}
+
+fn is_xetra_holiday(date: NaiveDate) -> bool {
+    let year = date.year();
+    let easter = easter(year);
+    let fixed = [(1, 1), (5, 1), (12, 24), (12, 25), (12, 26), (12, 31)];
+    date == easter - Duration::days(2)
+        || date == easter + Duration::days(1)
+        || fixed.contains(&(date.month(), date.day()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(datetime: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(datetime).unwrap().into()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_daylight_saving_time() {
+        let eastern = TimeZoneRule::US_EASTERN;
+        assert_eq!(
+            eastern.to_local(utc("2024-03-10T06:59:00Z")).to_string(),
+            "2024-03-10 01:59:00"
+        );
+        assert_eq!(
+            eastern.to_local(utc("2024-03-10T07:00:00Z")).to_string(),
+            "2024-03-10 03:00:00"
+        );
+        let open = date("2024-07-01").and_hms_opt(9, 30, 0).unwrap();
+        assert_eq!(eastern.to_utc(open), utc("2024-07-01T13:30:00Z"));
+
+        let europe = TimeZoneRule::CENTRAL_EUROPE;
+        assert_eq!(
+            europe.to_local(utc("2024-10-27T00:59:00Z")).to_string(),
+            "2024-10-27 02:59:00"
+        );
+        assert_eq!(
+            europe.to_local(utc("2024-10-27T01:00:00Z")).to_string(),
+            "2024-10-27 02:00:00"
+        );
+    }
+
+    #[test]
+    fn test_nyse_holidays_and_early_closes() {
+        let nyse = ExchangeCalendar::new(Exchange::Nyse);
+        for holiday in [
+            "2024-01-01",
+            "2024-01-15",
+            "2024-02-19",
+            "2024-03-29",
+            "2024-05-27",
+            "2024-06-19",
+            "2024-07-04",
+            "2024-09-02",
+            "2024-11-28",
+            "2024-12-25",
+            // Independence Day on a Saturday
+            "2026-07-03",
+        ] {
+            assert!(!nyse.is_trading_day(date(holiday)), "{holiday}");
+        }
+        assert!(nyse.is_trading_day(date("2024-07-05")));
+        // New Year's Day 2022 was a Saturday
+        assert!(nyse.is_trading_day(date("2021-12-31")));
+
+        assert_eq!(
+            nyse.regular_close(utc("2024-11-29T15:00:00Z")),
+            Some(utc("2024-11-29T18:00:00Z"))
+        );
+        assert_eq!(nyse.regular_close(utc("2024-12-25T15:00:00Z")), None);
+    }
+
+    #[test]
+    fn test_sessions() {
+        let nyse = ExchangeCalendar::new(Exchange::Nyse)
+            .with_holidays([date("2025-01-09")])
+            .with_early_close(
+                date("2024-07-02"),
+                NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+            );
+        assert_eq!(nyse.session(utc("2024-07-01T07:59:00Z")), Session::Closed);
+        assert_eq!(
+            nyse.session(utc("2024-07-01T08:00:00Z")),
+            Session::PreMarket
+        );
+        assert_eq!(nyse.session(utc("2024-07-01T13:30:00Z")), Session::Regular);
+        assert_eq!(
+            nyse.session(utc("2024-07-01T20:00:00Z")),
+            Session::AfterHours
+        );
+        assert_eq!(
+            nyse.session(utc("2024-07-02T16:00:00Z")),
+            Session::AfterHours
+        );
+        assert_eq!(nyse.session(utc("2025-01-09T15:00:00Z")), Session::Closed);
+        // In winter the session starts an hour later in UTC
+        assert_eq!(
+            nyse.session(utc("2025-01-08T14:00:00Z")),
+            Session::PreMarket
+        );
+
+        assert_eq!(
+            nyse.next_regular_open(utc("2024-07-03T20:00:00Z")),
+            utc("2024-07-05T13:30:00Z")
+        );
+
+        let xetra = ExchangeCalendar::new(Exchange::Xetra);
+        assert_eq!(xetra.session(utc("2024-07-01T07:00:00Z")), Session::Regular);
+        assert!(!xetra.is_trading_day(date("2024-04-01")));
+    }
+}
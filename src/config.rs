@@ -4,6 +4,13 @@ use anyhow::Result;
 use config::Value;
 use serde::Deserialize;
 
+use crate::calendar::{Exchange, Session};
+use crate::data_feed::market_data_hub::{BackpressurePolicy, DEFAULT_SUBSCRIPTION_CAPACITY};
+use crate::ib_connection::{
+    DEFAULT_HEARTBEAT_INTERVAL_SECS, DEFAULT_RECONNECT_INITIAL_BACKOFF_SECS,
+    DEFAULT_RECONNECT_MAX_BACKOFF_SECS,
+};
+
 #[derive(Debug, Deserialize)]
 pub struct BotConfig {
     /// Connections to Interactive Broker instances
@@ -13,6 +20,26 @@ pub struct BotConfig {
     pub strategies: Vec<StrategyConfig>,
     pub data_feeds: Vec<DataFeedConfig>,
     pub position_sizers: Vec<PositionSizerConfig>,
+    /// Exchange calendars that strategies, brokers and feeds refer to by name
+    #[serde(default)]
+    pub calendars: Vec<CalendarConfig>,
+    /// Dispatch the events of all strategies' data feeds in chronological order, so strategies
+    /// sharing a broker see a consistent history. Meant for backtests.
+    #[serde(default)]
+    pub backtest_clock: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CalendarConfig {
+    pub name: String,
+    /// The exchange whose hours, holidays and early closes apply
+    pub exchange: Exchange,
+    /// Additional holidays ("YYYY-MM-DD")
+    #[serde(default)]
+    pub holidays: Vec<String>,
+    /// Additional early closes: the local close ("HH:MM") by date ("YYYY-MM-DD")
+    #[serde(default)]
+    pub early_closes: HashMap<String, String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,6 +47,49 @@ pub struct IbConnectionConfig {
     pub name: String,
     pub address: String,
     pub client_id: i32,
+    /// Seconds between two health checks of the connection, at least 1
+    #[serde(
+        default = "default_heartbeat_interval_secs",
+        deserialize_with = "deserialize_positive_secs"
+    )]
+    pub heartbeat_interval_secs: u64,
+    /// Seconds to wait after the first failed reconnection attempt, at least 1. Doubles after
+    /// every failure.
+    #[serde(
+        default = "default_reconnect_initial_backoff_secs",
+        deserialize_with = "deserialize_positive_secs"
+    )]
+    pub reconnect_initial_backoff_secs: u64,
+    /// Upper limit (in seconds) of the wait between two reconnection attempts, at least
+    /// `reconnect_initial_backoff_secs`
+    #[serde(
+        default = "default_reconnect_max_backoff_secs",
+        deserialize_with = "deserialize_positive_secs"
+    )]
+    pub reconnect_max_backoff_secs: u64,
+}
+
+/// Intervals of 0 seconds would make the connection monitor spin
+fn deserialize_positive_secs<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<u64, D::Error> {
+    let secs = u64::deserialize(deserializer)?;
+    if secs == 0 {
+        return Err(serde::de::Error::custom("an interval of 0 seconds"));
+    }
+    Ok(secs)
+}
+
+fn default_heartbeat_interval_secs() -> u64 {
+    DEFAULT_HEARTBEAT_INTERVAL_SECS
+}
+
+fn default_reconnect_initial_backoff_secs() -> u64 {
+    DEFAULT_RECONNECT_INITIAL_BACKOFF_SECS
+}
+
+fn default_reconnect_max_backoff_secs() -> u64 {
+    DEFAULT_RECONNECT_MAX_BACKOFF_SECS
 }
 
 #[derive(Debug, Deserialize)]
@@ -43,33 +113,169 @@ pub struct StrategyConfig {
     pub position_sizer: String,
     /// Extra optional parameters that might be needed for the specific strategy
     pub params: Option<HashMap<String, Value>>,
+    /// How the strategy subscribes to its data feed, if that is a `MarketDataHub`
+    #[serde(default)]
+    pub subscription: SubscriptionConfig,
+    /// Restrict the strategy to sessions of an exchange calendar. Trades at any time without.
+    pub trading_hours: Option<TradingHoursConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TradingHoursConfig {
+    /// The name of the calendar
+    pub calendar: String,
+    /// The sessions in which orders may be placed
+    #[serde(default = "default_sessions")]
+    pub sessions: Vec<Session>,
+    /// Close all positions this many minutes before the regular session closes
+    pub flatten_before_close_mins: Option<i64>,
+}
+
+fn default_sessions() -> Vec<Session> {
+    vec![Session::Regular]
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubscriptionConfig {
+    /// What happens when the strategy does not keep up with the hub
+    #[serde(default)]
+    pub backpressure: BackpressurePolicy,
+    /// The number of pending events before the backpressure policy applies. Unlimited for
+    /// the Block policy under a backtest clock, which a full subscription would deadlock.
+    #[serde(default = "default_subscription_capacity")]
+    pub capacity: usize,
+    /// Only receive these symbols. All symbols of the hub when empty.
+    #[serde(default)]
+    pub symbols: Vec<String>,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        Self {
+            backpressure: BackpressurePolicy::default(),
+            capacity: DEFAULT_SUBSCRIPTION_CAPACITY,
+            symbols: Vec::new(),
+        }
+    }
+}
+
+fn default_subscription_capacity() -> usize {
+    DEFAULT_SUBSCRIPTION_CAPACITY
 }
 
 #[derive(Debug, Deserialize)]
 pub enum BrokerType {
+    /// Optional params: `corporate_actions`, a file of splits and dividends that are applied
+    /// to the positions when the market data reaches their ex-dates, and `allow_short`
     DummyBroker,
+    /// Optional params: `calendar`, to let orders execute outside regular trading hours only
+    /// when they are placed outside them, and `allow_short`, to let sell orders open short
+    /// positions
     IbBroker,
 }
 
 #[derive(Debug, Deserialize)]
 pub enum StrategyType {
     PrintStrategy,
+    /// Params: `fast_window` and `slow_window`
     SmaCrossStrategy,
+    /// Params: `period`, the `oversold` RSI to buy below, the `exit` RSI to sell at and an
+    /// optional `trend_window`, the SMA the price has to be above to buy
+    RsiMeanReversionStrategy,
+    /// Params: `entry_window` and `exit_window` of the Donchian channels, `atr_period`,
+    /// `stop_atr`, `pyramid_atr`, `max_units` and, to only enter out of a Bollinger squeeze,
+    /// `squeeze` with optional `period`, `multiplier` and `max_bandwidth`
+    BreakoutStrategy,
+    /// Params: `symbol_y` and `symbol_x` of the pair, `hedge_ratio` (`type: Ols` with a
+    /// `window`, or `type: Kalman` with `delta` and `observation_variance`), `z_window`,
+    /// `entry_z`, `exit_z` and an optional `stop_z`. The data feed has to deliver both
+    /// symbols, and the broker has to allow short selling.
+    PairsTradingStrategy,
+    /// Params: the target `weights` of the basket by symbol, the `schedule` (`type: Daily`,
+    /// `type: Weekly` with a `weekday` or `type: Monthly` with a `day`) and the `amount` to
+    /// invest and/or the `drift_band` to rebalance beyond on every scheduled date. The data
+    /// feed has to deliver every symbol of the basket, the position sizer is not used.
+    DcaRebalanceStrategy,
+    /// Params: the number of buy `levels` below the reference price, their `spacing` as a
+    /// fraction of it and an optional `max_inventory` per symbol
+    GridStrategy,
+    /// Params: the `lookback` of the trailing returns, `rebalance_days`, the number of `top`
+    /// symbols held, the `weighting` (`Equal` or `InverseVolatility` over the
+    /// `volatility_window`), an optional `min_return` to hold a symbol and `max_stale_days`
+    /// after which symbols without prices are dropped. The data feed has to deliver the whole
+    /// universe, the position sizer is not used.
+    MomentumRotationStrategy,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct DataFeedConfig {
     pub name: String,
     pub r#type: DataFeedType,
+    /// Not needed by feeds that wrap another feed or replay recordings
+    #[serde(default)]
     pub symbol: String,
+    /// IB streaming feeds subscribe to all of these (in addition to `symbol`, if set) and
+    /// multiplex their events
+    #[serde(default)]
+    pub symbols: Vec<String>,
     pub params: HashMap<String, Value>,
 }
 
+impl DataFeedConfig {
+    /// `symbol` followed by `symbols`, without duplicates
+    pub fn all_symbols(&self) -> Vec<String> {
+        let mut all_symbols = Vec::new();
+        for symbol in std::iter::once(&self.symbol).chain(&self.symbols) {
+            if !symbol.is_empty() && !all_symbols.contains(symbol) {
+                all_symbols.push(symbol.clone());
+            }
+        }
+        all_symbols
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub enum DataFeedType {
+    /// Reads the CSV file in `path`. Optional params: `corporate_actions`, a file of splits
+    /// and dividends the prices are back-adjusted for, and `speed`/`paused` to pace it
     CsvDataFeed,
+    /// Streams trades and top of the book quotes. Optional params: `data_type`,
+    /// `generic_ticks` (list of generic tick types), `snapshot` and `regulatory_snapshot`
     IbMarketDataFeed,
+    /// Downloads IB historical bars. Optional params: `duration`, `bar_size`, `end_datetime`,
+    /// `what_to_show` (Trades, MidPoint, Bid, Ask, BidAsk or AdjustedLast), `use_rth` and
+    /// `cache_dir`, a directory that keeps the downloaded bars. The cache only serves IB
+    /// historical downloads (this feed and `export-historical`), not the other feeds.
     IbHistoricalDataFeed,
+    /// Streams IB 5 seconds real-time bars. Optional params: `what_to_show` (Trades, MidPoint,
+    /// Bid or Ask) and `use_rth`
+    IbRealtimeBarsFeed,
+    /// Streams IB tick-by-tick data. Optional param: `tick_type` (Last, AllLast, BidAsk or
+    /// MidPoint)
+    IbTickByTickFeed,
+    /// Records the ticks of the feed given in the `data_feed` param to daily files in `dir`
+    RecordingDataFeed,
+    /// Replays a recording (file or directory in `path`) at the given `speed`. Optional param:
+    /// `recording`, the name of the recording feed whose files of the directory are replayed
+    ReplayDataFeed,
+    /// Consumes the feed given in the `data_feed` param once and shares it between all the
+    /// strategies (and wrapping feeds) that use this feed
+    MarketDataHub,
+    /// Aggregates the feed given in the `data_feed` param into bars of `bar_type`
+    /// (Time, Tick, Volume or Dollar) and `bar_size` (e.g. "5m", 100, 1000000). Optional
+    /// param for live feeds: `close_delay_secs`, emit time bars that many seconds after their
+    /// period ended instead of with the first tick of the next period
+    ResamplingDataFeed,
+    /// Generates reproducible prices for `symbol`/`symbols`. Params: `model` (with a `type`
+    /// of Gbm, OrnsteinUhlenbeck, RegimeSwitching or JumpDiffusion and its parameters), and
+    /// optionally `seed`, `initial_price`, `steps`, `interval_secs` and `start`
+    SyntheticDataFeed,
+    /// Checks the feed given in the `data_feed` param for invalid prices, timestamp
+    /// regressions, duplicate bars, outliers and gaps. Optional params: a policy (Drop,
+    /// ForwardFill, Halt or Warn) per issue (`invalid_price`, `timestamp_regression`,
+    /// `duplicate_bar`, `outlier`, `gap`), `max_price_change`, `bar_interval_secs` and the
+    /// `calendar` that gaps are checked against, which bars shorter than a day require
+    ValidatingDataFeed,
 }
 
 #[derive(Debug, Deserialize)]
@@ -85,6 +291,44 @@ pub enum PositionSizerType {
     PercentOfEquitySizer,
 }
 
+/// Configuration of the `export-historical` command
+#[derive(Debug, Deserialize)]
+pub struct ExportConfig {
+    /// The connection the bars are downloaded through
+    pub ib_connection: IbConnectionConfig,
+    /// The directory the CSV files are written to
+    pub output_dir: String,
+    /// Optional cache of downloaded bars (see `cache_dir` of the IB Historical Data Feed)
+    pub cache_dir: Option<String>,
+    pub exports: Vec<HistoricalExportConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoricalExportConfig {
+    pub symbols: Vec<String>,
+    /// IB bar sizes, named like the variants of `BarSize`, e.g. "Day", "Min" or "Min5"
+    pub bar_sizes: Vec<String>,
+    /// IB duration, e.g. "2 Y"
+    pub duration: String,
+    /// End of the exported range ("YYYYMMDD HH:MM:SS", "YYYYMMDD" or "now")
+    #[serde(default = "default_end_datetime")]
+    pub end_datetime: String,
+}
+
+fn default_end_datetime() -> String {
+    "now".to_string()
+}
+
+impl ExportConfig {
+    pub fn deserialize_from_file(path: &str) -> Result<Self> {
+        let config = config::Config::builder()
+            .add_source(config::File::with_name(path))
+            .build()?;
+        let config = config.try_deserialize()?;
+        Ok(config)
+    }
+}
+
 impl BotConfig {
     pub fn deserialize_from_file(path: &str) -> Result<Self> {
         let config = config::Config::builder()
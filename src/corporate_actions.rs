@@ -0,0 +1,210 @@
+use crate::data_feed::MarketData;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::{collections::HashMap, path::Path};
+use thiserror::Error;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CorporateActionKind {
+    /// New shares per old share, e.g. 4.0 for a 4-for-1 split or 0.1 for a 1-for-10 reverse split
+    Split(f64),
+    /// Cash paid per share
+    Dividend(f64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorporateAction {
+    pub symbol: String,
+    /// The first day the shares trade without the action (at the split price or without the
+    /// dividend)
+    pub ex_date: NaiveDate,
+    pub kind: CorporateActionKind,
+}
+
+/// A row of a corporate actions file: `ex_date,symbol,type,value`, with a type of `split` or
+/// `dividend`
+#[derive(Debug, Deserialize)]
+struct CorporateActionRow {
+    ex_date: String,
+    symbol: String,
+    #[serde(rename = "type")]
+    action_type: String,
+    value: f64,
+}
+
+/// Splits and cash dividends, ordered by ex-date
+#[derive(Debug, Clone, Default)]
+pub struct CorporateActions {
+    actions: Vec<CorporateAction>,
+}
+
+impl CorporateActions {
+    pub fn new(mut actions: Vec<CorporateAction>) -> Self {
+        actions.sort_by_key(|action| action.ex_date);
+        Self { actions }
+    }
+
+    pub fn from_csv(path: impl AsRef<Path>) -> Result<Self, CorporateActionsError> {
+        let path = path.as_ref().display().to_string();
+        let invalid = |err: &dyn std::fmt::Display| {
+            CorporateActionsError::Read(path.clone(), err.to_string())
+        };
+        let mut reader = csv::Reader::from_path(&path).map_err(|err| invalid(&err))?;
+        let mut actions = Vec::new();
+        for row in reader.deserialize::<CorporateActionRow>() {
+            let row = row.map_err(|err| invalid(&err))?;
+            let ex_date =
+                NaiveDate::parse_from_str(&row.ex_date, "%Y-%m-%d").map_err(|err| invalid(&err))?;
+            if !(row.value.is_finite() && row.value > 0.0) {
+                return Err(invalid(&format!("invalid value {}", row.value)));
+            }
+            let kind = match row.action_type.to_lowercase().as_str() {
+                "split" => CorporateActionKind::Split(row.value),
+                "dividend" => CorporateActionKind::Dividend(row.value),
+                other => return Err(invalid(&format!("unknown action type `{other}`"))),
+            };
+            actions.push(CorporateAction {
+                symbol: row.symbol,
+                ex_date,
+                kind,
+            });
+        }
+        Ok(Self::new(actions))
+    }
+
+    pub fn actions(&self) -> &[CorporateAction] {
+        &self.actions
+    }
+
+    /// Back-adjust historical bars (or ticks) for splits and dividends, so the prices before
+    /// an ex-date are comparable to the ones after it. Splits divide prices by the split ratio
+    /// and multiply volumes by it. Dividends multiply prices by `1 - dividend / close`, with
+    /// the last close before the ex-date.
+    pub fn adjust(&self, data: &mut [MarketData]) {
+        // The factors of every action, calculated on the unadjusted prices
+        let mut factors: HashMap<&str, Vec<(NaiveDate, f64, f64)>> = HashMap::new();
+        for action in &self.actions {
+            let (price_factor, volume_factor) = match action.kind {
+                CorporateActionKind::Split(ratio) => (1.0 / ratio, ratio),
+                CorporateActionKind::Dividend(dividend) => {
+                    let last_close = data
+                        .iter()
+                        .filter(|d| d.symbol == action.symbol)
+                        .filter(|d| d.timestamp.date_naive() < action.ex_date)
+                        .max_by_key(|d| d.timestamp)
+                        .map(|d| d.price);
+                    match last_close {
+                        Some(close) if close > dividend => (1.0 - dividend / close, 1.0),
+                        Some(close) => {
+                            warn!(
+                                "Ignoring dividend of {dividend} of {} on {}: not below the \
+                                close of {close}",
+                                action.symbol, action.ex_date
+                            );
+                            continue;
+                        }
+                        // Nothing to adjust before the ex-date
+                        None => continue,
+                    }
+                }
+            };
+            factors.entry(&action.symbol).or_default().push((
+                action.ex_date,
+                price_factor,
+                volume_factor,
+            ));
+        }
+
+        for data in data.iter_mut() {
+            let Some(factors) = factors.get(data.symbol.as_str()) else {
+                continue;
+            };
+            let date = data.timestamp.date_naive();
+            let (price_factor, volume_factor) = factors
+                .iter()
+                .filter(|(ex_date, _, _)| date < *ex_date)
+                .fold((1.0, 1.0), |(price, volume), (_, p, v)| {
+                    (price * p, volume * v)
+                });
+            data.price *= price_factor;
+            data.open *= price_factor;
+            data.high *= price_factor;
+            data.low *= price_factor;
+            data.volume *= volume_factor;
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum CorporateActionsError {
+    #[error("Failed to read corporate actions ({0}): {1}")]
+    Read(String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use std::io::Write;
+
+    fn tick(day: i64, price: f64) -> MarketData {
+        // 2024-01-01
+        let timestamp = DateTime::from_timestamp(1_704_067_200 + day * 86_400, 0).unwrap();
+        MarketData::tick("AAPL".into(), price, 100.0, timestamp)
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, day).unwrap()
+    }
+
+    #[test]
+    fn test_adjust_for_split_and_dividend() {
+        let actions = CorporateActions::new(vec![
+            CorporateAction {
+                symbol: "AAPL".into(),
+                ex_date: date(4),
+                kind: CorporateActionKind::Dividend(2.0),
+            },
+            CorporateAction {
+                symbol: "AAPL".into(),
+                ex_date: date(3),
+                kind: CorporateActionKind::Split(2.0),
+            },
+            CorporateAction {
+                symbol: "MSFT".into(),
+                ex_date: date(3),
+                kind: CorporateActionKind::Split(10.0),
+            },
+        ]);
+        // Raw prices: split from 200 to 100, then 2.0 dividend from 100 to 98
+        let mut data = vec![
+            tick(0, 200.0),
+            tick(1, 200.0),
+            tick(2, 100.0),
+            tick(3, 98.0),
+        ];
+        actions.adjust(&mut data);
+
+        assert!(data.iter().all(|d| (d.price - 98.0).abs() < 1e-9));
+        assert_eq!(data[0].volume, 200.0);
+        assert_eq!(data[2].volume, 100.0);
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "ex_date,symbol,type,value").unwrap();
+        writeln!(file, "2020-08-31,AAPL,split,4").unwrap();
+        writeln!(file, "2020-08-07,AAPL,Dividend,0.82").unwrap();
+        let actions = CorporateActions::from_csv(file.path()).unwrap();
+        assert_eq!(
+            actions.actions()[0].kind,
+            CorporateActionKind::Dividend(0.82)
+        );
+        assert_eq!(actions.actions()[1].kind, CorporateActionKind::Split(4.0));
+
+        writeln!(file, "2020-09-01,AAPL,spinoff,1").unwrap();
+        assert!(CorporateActions::from_csv(file.path()).is_err());
+    }
+}
@@ -1,19 +1,222 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use time::OffsetDateTime;
 
 pub mod csv_data_feed;
+pub mod historical_cache;
 pub mod ib_historical_data_feed;
 pub mod ib_market_data_feed;
+pub mod ib_realtime_bars_feed;
+pub mod ib_tick_by_tick_feed;
+pub mod market_data_hub;
+pub mod recording_data_feed;
+pub mod replay_data_feed;
+pub mod replay_pacer;
+pub mod resampling_data_feed;
+pub mod synthetic_data_feed;
+pub mod validating_data_feed;
 
 // TODO: Is this the right place for MarketData declaration?
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct MarketData {
     pub symbol: String,
+    /// The last price. For bars this is the close.
     pub price: f64,
-    // pub timestamp: DateTime<Utc>,
+    /// The time of the tick. For bars this is the start of the bar.
+    pub timestamp: DateTime<Utc>,
+    /// Open, high and low of a bar. Equal to `price` for single ticks.
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    /// Traded volume. 0 when unknown.
+    pub volume: f64,
+    pub kind: MarketDataKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MarketDataKind {
+    /// A trade (or a single price of a price series)
+    Trade,
+    /// An aggregate of trades over a period
+    Bar,
+    /// A change of the top of the book. `price` is the midpoint (or the last price, or the
+    /// known side, while one side of the book is unknown), so it should not be mistaken for a
+    /// traded price.
+    Quote(Quote),
+}
+
+/// Top of the book of an instrument
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Quote {
+    pub bid: f64,
+    pub ask: f64,
+    pub bid_size: f64,
+    pub ask_size: f64,
+    pub last: f64,
+    pub last_size: f64,
+    /// Volume traded during the day
+    pub volume: f64,
+}
+
+impl Quote {
+    pub fn midpoint(&self) -> Option<f64> {
+        (self.bid > 0.0 && self.ask > 0.0).then(|| (self.bid + self.ask) / 2.0)
+    }
+
+    /// The midpoint, or the last price or the known side of the book while there is none
+    pub fn reference_price(&self) -> Option<f64> {
+        self.midpoint().or_else(|| {
+            [self.last, self.bid, self.ask]
+                .into_iter()
+                .find(|p| *p > 0.0)
+        })
+    }
+}
+
+impl MarketData {
+    pub fn tick(symbol: String, price: f64, volume: f64, timestamp: DateTime<Utc>) -> Self {
+        Self {
+            symbol,
+            price,
+            timestamp,
+            open: price,
+            high: price,
+            low: price,
+            volume,
+            kind: MarketDataKind::Trade,
+        }
+    }
+
+    /// `None` while the quote has no price at all
+    pub fn quote(symbol: String, quote: Quote, timestamp: DateTime<Utc>) -> Option<Self> {
+        let price = quote.reference_price()?;
+        Some(Self {
+            kind: MarketDataKind::Quote(quote),
+            ..Self::tick(symbol, price, 0.0, timestamp)
+        })
+    }
+
+    pub fn is_quote(&self) -> bool {
+        matches!(self.kind, MarketDataKind::Quote(_))
+    }
+}
+
+pub(crate) fn utc_from_offset_datetime(datetime: OffsetDateTime) -> DateTime<Utc> {
+    DateTime::from_timestamp(datetime.unix_timestamp(), datetime.nanosecond()).unwrap_or_default()
+}
+
+/// Runs a task that iterates ibapi subscriptions on a thread of its own. The subscriptions
+/// block their thread until the next event, which would stall a worker of the runtime.
+pub(crate) fn spawn_ib_task<F>(task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let runtime = tokio::runtime::Handle::current();
+    std::thread::spawn(move || runtime.block_on(task));
 }
 
 #[async_trait]
 pub trait DataFeed: Send + Sync {
     fn name(&self) -> &str;
-    async fn next_tick(&mut self) -> Option<MarketData>;
+    /// Returns the next event of the feed or `None` once the feed is exhausted.
+    /// Errors do not end the feed by themselves: the consumer decides whether to keep
+    /// waiting for data (see [`DataFeedError::is_fatal`]) or to stop.
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>>;
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum DataFeedError {
+    #[error("Pacing violation: {0}")]
+    PacingViolation(String),
+    #[error("No market data permissions: {0}")]
+    NoMarketDataPermissions(String),
+    #[error("Unknown contract: {0}")]
+    UnknownContract(String),
+    #[error("Invalid market data: {0}")]
+    InvalidData(String),
+    #[error("Connection lost: {0}")]
+    ConnectionLost(String),
+    #[error("Data feed error: {0}")]
+    Other(String),
+}
+
+impl DataFeedError {
+    /// Fatal errors will not resolve by waiting, so there is no point in consuming the feed further
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Self::NoMarketDataPermissions(_) | Self::UnknownContract(_) | Self::InvalidData(_)
+        )
+    }
+
+    /// Map an Interactive Brokers error/notice code to a feed error.
+    /// Returns `None` for codes that are informational (e.g. "Displaying delayed market data").
+    pub fn from_ib_code(code: i32, message: &str) -> Option<Self> {
+        let message = format!("[{code}] {message}");
+        match code {
+            100 | 420 => Some(Self::PacingViolation(message)),
+            162 if message.to_lowercase().contains("pacing") => {
+                Some(Self::PacingViolation(message))
+            }
+            200 => Some(Self::UnknownContract(message)),
+            354 | 10089 | 10090 | 10168 => Some(Self::NoMarketDataPermissions(message)),
+            1100 | 2110 => Some(Self::ConnectionLost(message)),
+            // Warnings and informational messages
+            2100..=2199 | 10167 => None,
+            _ => Some(Self::Other(message)),
+        }
+    }
+}
+
+impl From<ibapi::Error> for DataFeedError {
+    fn from(err: ibapi::Error) -> Self {
+        match err {
+            ibapi::Error::Message(code, message) => {
+                Self::from_ib_code(code, &message).unwrap_or(Self::Other(message))
+            }
+            ibapi::Error::ConnectionFailed | ibapi::Error::ConnectionReset => {
+                Self::ConnectionLost(err.to_string())
+            }
+            _ => Self::Other(err.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_data_feed_error_from_ib_code() {
+        assert!(matches!(
+            DataFeedError::from_ib_code(
+                162,
+                "Historical Market Data Service error message:API historical data query cancelled: pacing violation"
+            ),
+            Some(DataFeedError::PacingViolation(_))
+        ));
+        assert!(matches!(
+            DataFeedError::from_ib_code(162, "HMDS query returned no data"),
+            Some(DataFeedError::Other(_))
+        ));
+        assert!(matches!(
+            DataFeedError::from_ib_code(200, "No security definition has been found"),
+            Some(DataFeedError::UnknownContract(_))
+        ));
+        assert!(matches!(
+            DataFeedError::from_ib_code(354, "Requested market data is not subscribed"),
+            Some(DataFeedError::NoMarketDataPermissions(_))
+        ));
+        assert!(DataFeedError::from_ib_code(10167, "Displaying delayed market data").is_none());
+    }
+
+    #[test]
+    fn test_data_feed_error_is_fatal() {
+        assert!(DataFeedError::UnknownContract("AAPL".into()).is_fatal());
+        assert!(DataFeedError::NoMarketDataPermissions("AAPL".into()).is_fatal());
+        assert!(DataFeedError::InvalidData("AAPL".into()).is_fatal());
+        assert!(!DataFeedError::PacingViolation("AAPL".into()).is_fatal());
+        assert!(!DataFeedError::ConnectionLost("AAPL".into()).is_fatal());
+    }
 }
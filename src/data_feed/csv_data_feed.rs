@@ -1,13 +1,22 @@
 use super::DataFeed;
-use crate::data_feed::MarketData;
+use crate::{
+    corporate_actions::CorporateActions,
+    data_feed::{DataFeedError, MarketData, MarketDataKind, replay_pacer::ReplayPacer},
+};
 use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use csv::ReaderBuilder;
 use std::{collections::VecDeque, fs::File};
 use thiserror::Error;
 
+/// Reads `timestamp,price[,volume[,symbol]]` rows. Timestamps are either RFC 3339 or
+/// `YYYY-MM-DD HH:MM:SS` in UTC. Files with a symbol column can hold several symbols.
+/// With `high` and `low` columns (and optionally `open`), found by their header, the rows are
+/// bars with `price` as the close. Rows are replayed at once, unless the feed has a pacer.
 pub struct CsvDataFeed {
     name: String,
     data: VecDeque<MarketData>,
+    pacer: Option<ReplayPacer>,
 }
 
 impl CsvDataFeed {
@@ -15,28 +24,102 @@ impl CsvDataFeed {
         let file = File::open(&path)
             .map_err(|err| CsvDataFeedError::FileOpenError(path.clone(), err.to_string()))?;
         let mut rdr = ReaderBuilder::new().from_reader(file);
+        let parse_error = |err: &dyn std::fmt::Display| {
+            CsvDataFeedError::ParseError(path.clone(), err.to_string())
+        };
+        let headers = rdr.headers().map_err(|err| parse_error(&err))?.clone();
+        let column = |name: &str| {
+            headers
+                .iter()
+                .position(|header| header.trim().eq_ignore_ascii_case(name))
+        };
+        let (open_column, high_column, low_column) =
+            (column("open"), column("high"), column("low"));
+        // Files without named columns have the symbol in the fourth one
+        let symbol_column = column("symbol").or_else(|| {
+            [open_column, high_column, low_column]
+                .iter()
+                .all(Option::is_none)
+                .then_some(3)
+        });
         let mut data = VecDeque::new();
-        for result in rdr.deserialize() {
-            // TODO: Handle error
-            let record: (String, f64) = result.unwrap();
-            let md = MarketData {
-                symbol: "AAPL".to_string(),
-                price: record.1,
-                // timestamp:
+        for result in rdr.records() {
+            let mut row = result.map_err(|err| parse_error(&err))?;
+            // TODO: Read the default symbol from the config
+            let symbol = symbol_column
+                .and_then(|column| row.get(column))
+                .filter(|symbol| !symbol.is_empty())
+                .unwrap_or("AAPL")
+                .to_string();
+            let price_in = |column: Option<usize>| {
+                column
+                    .and_then(|column| row.get(column))
+                    .filter(|value| !value.is_empty())
+                    .map(|value| value.trim().parse::<f64>().map_err(|err| parse_error(&err)))
+                    .transpose()
+            };
+            let (open, high, low) = (
+                price_in(open_column)?,
+                price_in(high_column)?,
+                price_in(low_column)?,
+            );
+            row.truncate(3);
+            let record: (String, f64, Option<f64>) =
+                row.deserialize(None).map_err(|err| parse_error(&err))?;
+            let timestamp = parse_timestamp(&record.0).ok_or_else(|| parse_error(&record.0))?;
+            let tick = MarketData::tick(symbol, record.1, record.2.unwrap_or_default(), timestamp);
+            let md = match (high, low) {
+                (Some(high), Some(low)) => MarketData {
+                    open: open.unwrap_or(tick.price),
+                    high,
+                    low,
+                    kind: MarketDataKind::Bar,
+                    ..tick
+                },
+                _ => tick,
             };
             data.push_back(md);
         }
-        Ok(Self { name, data })
+        Ok(Self {
+            name,
+            data,
+            pacer: None,
+        })
+    }
+
+    /// Back-adjust the prices for splits and dividends
+    pub fn with_corporate_actions(mut self, corporate_actions: &CorporateActions) -> Self {
+        corporate_actions.adjust(self.data.make_contiguous());
+        self
+    }
+
+    /// Replay the rows according to their timestamps
+    pub fn with_pacer(mut self, pacer: ReplayPacer) -> Self {
+        self.pacer = Some(pacer);
+        self
     }
 }
 
+fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(s) {
+        return Some(datetime.with_timezone(&Utc));
+    }
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|datetime| datetime.and_utc())
+}
+
 #[async_trait]
 impl DataFeed for CsvDataFeed {
     fn name(&self) -> &str {
         &self.name
     }
-    async fn next_tick(&mut self) -> Option<MarketData> {
-        self.data.pop_front()
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        let data = self.data.pop_front()?;
+        if let Some(pacer) = &mut self.pacer {
+            pacer.wait(data.timestamp).await;
+        }
+        Some(Ok(data))
     }
 }
 
@@ -44,4 +127,6 @@ impl DataFeed for CsvDataFeed {
 pub enum CsvDataFeedError {
     #[error("Failed to open CSV file ({0}): {1}")]
     FileOpenError(String, String),
+    #[error("Failed to parse CSV file ({0}): {1}")]
+    ParseError(String, String),
 }
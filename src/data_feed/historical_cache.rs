@@ -0,0 +1,346 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use time::OffsetDateTime;
+
+/// Identifies a series of bars in the cache
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoricalCacheKey {
+    pub symbol: String,
+    pub bar_size: String,
+    pub what_to_show: String,
+    pub use_rth: bool,
+}
+
+impl HistoricalCacheKey {
+    fn file_stem(&self) -> String {
+        let rth = if self.use_rth { "rth" } else { "all" };
+        format!(
+            "{}_{}_{}_{}",
+            self.symbol, self.bar_size, self.what_to_show, rth
+        )
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CachedBar {
+    /// Unix timestamp (seconds) of the start of the bar
+    pub timestamp: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+/// A time range of a series for which all bars are in the cache.
+/// A range may contain no bars at all (e.g. weekends), so coverage is tracked apart from bars.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct CoveredRange {
+    start: i64,
+    end: i64,
+}
+
+/// A part of a requested interval that is either served from the cache or has to be fetched
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheSegment {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub cached: bool,
+}
+
+/// Stores downloaded bars on disk, one CSV file per [`HistoricalCacheKey`], so that later runs
+/// only need to fetch the parts of an interval that were never downloaded.
+pub struct HistoricalDataCache {
+    dir: PathBuf,
+}
+
+impl HistoricalDataCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Result<Self, HistoricalCacheError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .map_err(|err| HistoricalCacheError::Io(dir.display().to_string(), err.to_string()))?;
+        Ok(Self { dir })
+    }
+
+    /// Splits `start..end` in consecutive segments that are cached or missing
+    pub fn segments(
+        &self,
+        key: &HistoricalCacheKey,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<Vec<CacheSegment>, HistoricalCacheError> {
+        let covered = self.load_coverage(key)?;
+        let (start, end) = (start.unix_timestamp(), end.unix_timestamp());
+        let mut segments = Vec::new();
+        let mut cursor = start;
+        for range in covered.iter().filter(|r| r.end > start && r.start < end) {
+            if range.start > cursor {
+                segments.push((cursor, range.start, false));
+            }
+            segments.push((cursor.max(range.start), range.end.min(end), true));
+            cursor = range.end.min(end);
+        }
+        if cursor < end {
+            segments.push((cursor, end, false));
+        }
+        segments
+            .into_iter()
+            .map(|(start, end, cached)| {
+                Ok(CacheSegment {
+                    start: from_unix(start)?,
+                    end: from_unix(end)?,
+                    cached,
+                })
+            })
+            .collect()
+    }
+
+    /// All cached bars of a series with `start <= timestamp <= end`, oldest first
+    pub fn load_bars(
+        &self,
+        key: &HistoricalCacheKey,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) -> Result<Vec<CachedBar>, HistoricalCacheError> {
+        let (start, end) = (start.unix_timestamp(), end.unix_timestamp());
+        let bars = read_csv::<CachedBar>(&self.bars_path(key))?;
+        Ok(bars
+            .into_iter()
+            .filter(|bar| bar.timestamp >= start && bar.timestamp <= end)
+            .collect())
+    }
+
+    /// Merge `bars` into the cache and, if `covered` is given, mark that range as complete
+    pub fn store(
+        &self,
+        key: &HistoricalCacheKey,
+        bars: &[CachedBar],
+        covered: Option<(OffsetDateTime, OffsetDateTime)>,
+    ) -> Result<(), HistoricalCacheError> {
+        let bars_path = self.bars_path(key);
+        let mut merged: BTreeMap<i64, CachedBar> = read_csv::<CachedBar>(&bars_path)?
+            .into_iter()
+            .map(|bar| (bar.timestamp, bar))
+            .collect();
+        merged.extend(bars.iter().map(|bar| (bar.timestamp, *bar)));
+        write_csv(&bars_path, merged.values())?;
+
+        if let Some((start, end)) = covered {
+            let mut coverage = self.load_coverage(key)?;
+            coverage.push(CoveredRange {
+                start: start.unix_timestamp(),
+                end: end.unix_timestamp(),
+            });
+            write_csv(&self.coverage_path(key), merge_ranges(coverage).iter())?;
+        }
+        Ok(())
+    }
+
+    fn load_coverage(
+        &self,
+        key: &HistoricalCacheKey,
+    ) -> Result<Vec<CoveredRange>, HistoricalCacheError> {
+        Ok(merge_ranges(read_csv(&self.coverage_path(key))?))
+    }
+
+    fn bars_path(&self, key: &HistoricalCacheKey) -> PathBuf {
+        self.dir.join(format!("{}.csv", key.file_stem()))
+    }
+
+    fn coverage_path(&self, key: &HistoricalCacheKey) -> PathBuf {
+        self.dir.join(format!("{}.coverage.csv", key.file_stem()))
+    }
+}
+
+/// Sort ranges and merge the ones that overlap or touch
+fn merge_ranges(mut ranges: Vec<CoveredRange>) -> Vec<CoveredRange> {
+    ranges.sort_by_key(|r| r.start);
+    let mut merged: Vec<CoveredRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+fn from_unix(timestamp: i64) -> Result<OffsetDateTime, HistoricalCacheError> {
+    OffsetDateTime::from_unix_timestamp(timestamp)
+        .map_err(|err| HistoricalCacheError::Corrupted(err.to_string()))
+}
+
+fn read_csv<T: for<'de> Deserialize<'de>>(path: &Path) -> Result<Vec<T>, HistoricalCacheError> {
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+    let file = File::open(path)
+        .map_err(|err| HistoricalCacheError::Io(path.display().to_string(), err.to_string()))?;
+    csv::Reader::from_reader(file)
+        .deserialize()
+        .collect::<Result<Vec<T>, _>>()
+        .map_err(|err| HistoricalCacheError::Corrupted(format!("{}: {}", path.display(), err)))
+}
+
+/// Write through a temporary file, so that an interrupted write does not corrupt the cache
+fn write_csv<'a, T: Serialize + 'a>(
+    path: &Path,
+    rows: impl Iterator<Item = &'a T>,
+) -> Result<(), HistoricalCacheError> {
+    let io_err = |err: &dyn std::fmt::Display| {
+        HistoricalCacheError::Io(path.display().to_string(), err.to_string())
+    };
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = csv::Writer::from_path(&tmp_path).map_err(|err| io_err(&err))?;
+    for row in rows {
+        writer.serialize(row).map_err(|err| io_err(&err))?;
+    }
+    writer.flush().map_err(|err| io_err(&err))?;
+    fs::rename(&tmp_path, path).map_err(|err| io_err(&err))
+}
+
+#[derive(Debug, Error)]
+pub enum HistoricalCacheError {
+    #[error("Historical data cache I/O error ({0}): {1}")]
+    Io(String, String),
+    #[error("Historical data cache is corrupted: {0}")]
+    Corrupted(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn make_key() -> HistoricalCacheKey {
+        HistoricalCacheKey {
+            symbol: "AAPL".into(),
+            bar_size: "Day".into(),
+            what_to_show: "Trades".into(),
+            use_rth: true,
+        }
+    }
+
+    fn make_bar(datetime: OffsetDateTime, close: f64) -> CachedBar {
+        CachedBar {
+            timestamp: datetime.unix_timestamp(),
+            open: close,
+            high: close,
+            low: close,
+            close,
+            volume: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_historical_cache_empty_cache_is_one_missing_segment() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HistoricalDataCache::new(dir.path()).unwrap();
+        let start = datetime!(2025-01-01 00:00:00 UTC);
+        let end = datetime!(2025-01-10 00:00:00 UTC);
+
+        let segments = cache.segments(&make_key(), start, end).unwrap();
+
+        assert_eq!(
+            segments,
+            vec![CacheSegment {
+                start,
+                end,
+                cached: false
+            }]
+        );
+    }
+
+    #[test]
+    fn test_historical_cache_serves_overlap_and_reports_gaps() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HistoricalDataCache::new(dir.path()).unwrap();
+        let key = make_key();
+        let bars = vec![
+            make_bar(datetime!(2025-01-03 00:00:00 UTC), 101.0),
+            make_bar(datetime!(2025-01-04 00:00:00 UTC), 102.0),
+        ];
+        cache
+            .store(
+                &key,
+                &bars,
+                Some((
+                    datetime!(2025-01-03 00:00:00 UTC),
+                    datetime!(2025-01-05 00:00:00 UTC),
+                )),
+            )
+            .unwrap();
+
+        let segments = cache
+            .segments(
+                &key,
+                datetime!(2025-01-01 00:00:00 UTC),
+                datetime!(2025-01-10 00:00:00 UTC),
+            )
+            .unwrap();
+        let summary: Vec<(u8, u8, bool)> = segments
+            .iter()
+            .map(|s| (s.start.day(), s.end.day(), s.cached))
+            .collect();
+        assert_eq!(summary, vec![(1, 3, false), (3, 5, true), (5, 10, false)]);
+
+        let loaded = cache
+            .load_bars(
+                &key,
+                datetime!(2025-01-01 00:00:00 UTC),
+                datetime!(2025-01-10 00:00:00 UTC),
+            )
+            .unwrap();
+        assert_eq!(loaded, bars);
+    }
+
+    #[test]
+    fn test_historical_cache_store_merges_bars_and_coverage() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = HistoricalDataCache::new(dir.path()).unwrap();
+        let key = make_key();
+        let day = |d: u8| datetime!(2025-01-01 00:00:00 UTC).replace_day(d).unwrap();
+        cache
+            .store(&key, &[make_bar(day(2), 1.0)], Some((day(1), day(3))))
+            .unwrap();
+        // Overlapping bar replaces the stored one
+        cache
+            .store(
+                &key,
+                &[make_bar(day(2), 2.0), make_bar(day(3), 3.0)],
+                Some((day(2), day(4))),
+            )
+            .unwrap();
+
+        let segments = cache.segments(&key, day(1), day(4)).unwrap();
+        assert_eq!(
+            segments,
+            vec![CacheSegment {
+                start: day(1),
+                end: day(4),
+                cached: true
+            }]
+        );
+        let closes: Vec<f64> = cache
+            .load_bars(&key, day(1), day(4))
+            .unwrap()
+            .iter()
+            .map(|bar| bar.close)
+            .collect();
+        assert_eq!(closes, vec![2.0, 3.0]);
+    }
+}
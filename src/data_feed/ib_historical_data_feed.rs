@@ -1,69 +1,468 @@
-use crate::data_feed::{DataFeed, MarketData};
+use crate::{
+    data_feed::{
+        DataFeed, DataFeedError, MarketData, MarketDataKind,
+        historical_cache::{CachedBar, HistoricalCacheKey, HistoricalDataCache},
+        replay_pacer::ReplayPacer,
+        spawn_ib_task,
+    },
+    ib_connection::IbConnection,
+};
 use async_trait::async_trait;
+use chrono::DateTime;
 use ibapi::{
-    Client,
     contracts::Contract,
-    market_data::historical::{BarSize, Duration, WhatToShow},
+    market_data::historical::{BarSize, Duration, ToDuration, WhatToShow},
 };
 use std::sync::Arc;
-use time::OffsetDateTime;
+use time::{Duration as TimeDuration, OffsetDateTime};
 use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+/// IB rejects identical requests within 15 seconds, so a request that hit a pacing violation
+/// is retried after this delay.
+const PACING_VIOLATION_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(16);
+
+/// The bars to download: `duration` of `bar_size` bars ending at `interval_end`
+pub struct HistoricalBarsRequest {
+    pub symbol: String,
+    pub interval_end: OffsetDateTime,
+    pub duration: Duration,
+    pub bar_size: BarSize,
+    pub what_to_show: WhatToShow,
+    /// Only download the bars of the regular trading hours
+    pub use_rth: bool,
+}
 
 pub struct IbHistoricalDataFeed {
     name: String,
-    rx: mpsc::UnboundedReceiver<MarketData>,
+    symbol: String,
+    rx: mpsc::UnboundedReceiver<Result<CachedBar, DataFeedError>>,
+    pacer: Option<ReplayPacer>,
 }
 
 impl IbHistoricalDataFeed {
     pub fn new(
         name: String,
-        client: Arc<Client>,
-        symbol: String,
-        interval_end: OffsetDateTime,
-        duration: Duration,
-        bar_size: BarSize,
+        connection: Arc<IbConnection>,
+        request: HistoricalBarsRequest,
+        cache: Option<HistoricalDataCache>,
     ) -> Self {
-        let (tx, rx) = mpsc::unbounded_channel();
-        let contract = Contract::stock(&symbol);
-        let what_to_show = WhatToShow::Trades;
-        // Use regular trading hours
-        let use_rth = true;
-        tokio::spawn(async move {
-            // TODO: Handle error by passing it to tx
-            let historical_data = client
-                .historical_data(
-                    &contract,
-                    Some(interval_end),
-                    duration,
-                    bar_size,
-                    what_to_show,
-                    use_rth,
-                )
-                .expect("Failed to retrieve data");
-            for bar in &historical_data.bars {
-                let md = MarketData {
-                    symbol: symbol.clone(),
-                    price: bar.close,
-                };
-                let _ = tx.send(md);
-            }
-        });
-        Self { name, rx }
+        let symbol = request.symbol.clone();
+        let rx = download_historical_bars(connection, request, cache);
+        Self {
+            name,
+            symbol,
+            rx,
+            pacer: None,
+        }
+    }
+
+    /// Replay the bars according to their timestamps instead of as soon as they are downloaded
+    pub fn with_pacer(mut self, pacer: ReplayPacer) -> Self {
+        self.pacer = Some(pacer);
+        self
     }
 }
 
+/// Downloads the bars of the `request`. Durations longer than what IB
+/// serves for `bar_size` in one request are split in consecutive chunks that are requested
+/// oldest first, so bars are streamed in chronological order while the download goes on.
+///
+/// With a `cache`, bars that were downloaded by earlier runs are read from disk and only the
+/// missing parts of the interval are requested from IB.
+pub fn download_historical_bars(
+    connection: Arc<IbConnection>,
+    request: HistoricalBarsRequest,
+    cache: Option<HistoricalDataCache>,
+) -> mpsc::UnboundedReceiver<Result<CachedBar, DataFeedError>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let HistoricalBarsRequest {
+        symbol,
+        interval_end,
+        duration,
+        bar_size,
+        what_to_show,
+        use_rth,
+    } = request;
+    let mut downloader = HistoricalDownloader {
+        connection,
+        contract: Contract::stock(&symbol),
+        symbol,
+        bar_size,
+        what_to_show,
+        use_rth,
+        tx,
+        last_bar_timestamp: None,
+    };
+    spawn_ib_task(async move {
+        match (cache, duration_span(&duration)) {
+            (Some(cache), Some(span)) => {
+                downloader
+                    .run_cached(&cache, interval_end - span, interval_end)
+                    .await
+            }
+            (None, Some(span)) if span > max_request_span(bar_size).0 => {
+                downloader.download(interval_end - span, interval_end).await;
+            }
+            // A single request is enough, or the duration can not be split
+            _ => {
+                downloader
+                    .download_chunks(None, vec![(interval_end, duration)])
+                    .await;
+            }
+        }
+    });
+    rx
+}
+
 #[async_trait]
 impl DataFeed for IbHistoricalDataFeed {
     fn name(&self) -> &str {
         &self.name
     }
-    async fn next_tick(&mut self) -> Option<MarketData> {
-        self.rx.recv().await
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        let event = self.rx.recv().await?;
+        if let (Some(pacer), Ok(bar)) = (&mut self.pacer, &event) {
+            pacer
+                .wait(DateTime::from_timestamp(bar.timestamp, 0).unwrap_or_default())
+                .await;
+        }
+        Some(event.map(|bar| MarketData {
+            symbol: self.symbol.clone(),
+            price: bar.close,
+            timestamp: DateTime::from_timestamp(bar.timestamp, 0).unwrap_or_default(),
+            open: bar.open,
+            high: bar.high,
+            low: bar.low,
+            volume: bar.volume,
+            kind: MarketDataKind::Bar,
+        }))
     }
 }
 
-// #[derive(Debug, Error)]
-// pub enum IbHistoricalDataFeedError {
-//     #[error("Failed to retrieve historical data: {0}")]
-//     HistoricalDataRetrieval(String)
-// }
+/// The outcome of downloading a range of bars
+struct Download {
+    bars: Vec<CachedBar>,
+    /// Whether every chunk of the range was retrieved
+    complete: bool,
+    /// Whether the feed should stop (consumer gone or fatal error)
+    stop: bool,
+}
+
+struct HistoricalDownloader {
+    connection: Arc<IbConnection>,
+    contract: Contract,
+    symbol: String,
+    bar_size: BarSize,
+    what_to_show: WhatToShow,
+    use_rth: bool,
+    tx: mpsc::UnboundedSender<Result<CachedBar, DataFeedError>>,
+    /// Timestamp of the last bar sent, used to skip bars of overlapping requests
+    last_bar_timestamp: Option<i64>,
+}
+
+impl HistoricalDownloader {
+    async fn run_cached(
+        &mut self,
+        cache: &HistoricalDataCache,
+        start: OffsetDateTime,
+        end: OffsetDateTime,
+    ) {
+        let key = HistoricalCacheKey {
+            symbol: self.symbol.clone(),
+            bar_size: format!("{:?}", self.bar_size),
+            what_to_show: format!("{:?}", self.what_to_show),
+            use_rth: self.use_rth,
+        };
+        let segments = match cache.segments(&key, start, end) {
+            Ok(segments) => segments,
+            Err(err) => {
+                warn!("Ignoring historical data cache: {}", err);
+                self.download(start, end).await;
+                return;
+            }
+        };
+        for segment in segments {
+            if segment.cached {
+                match cache.load_bars(&key, segment.start, segment.end) {
+                    Ok(bars) => {
+                        debug!(
+                            "Serving {} bars of {} from the cache ({} - {})",
+                            bars.len(),
+                            self.symbol,
+                            segment.start,
+                            segment.end
+                        );
+                        for bar in bars {
+                            if !self.send(&bar) {
+                                return;
+                            }
+                        }
+                    }
+                    Err(err) => {
+                        warn!("Ignoring historical data cache: {}", err);
+                        if self.download(segment.start, segment.end).await.stop {
+                            return;
+                        }
+                    }
+                }
+            } else {
+                let download = self.download(segment.start, segment.end).await;
+                // The bar in progress changes until it completes, so only completed bars are
+                // cached
+                let completed =
+                    completed_until(segment.end, OffsetDateTime::now_utc(), self.bar_size);
+                let bars: Vec<_> = download
+                    .bars
+                    .iter()
+                    .filter(|bar| bar.timestamp <= completed.unix_timestamp())
+                    .copied()
+                    .collect();
+                let covered = (download.complete && completed > segment.start)
+                    .then_some((segment.start, completed));
+                if let Err(err) = cache.store(&key, &bars, covered) {
+                    warn!("Failed to store historical data in the cache: {}", err);
+                }
+                if download.stop {
+                    return;
+                }
+            }
+        }
+    }
+
+    async fn download(&mut self, start: OffsetDateTime, end: OffsetDateTime) -> Download {
+        let (chunk_span, chunk_duration) = max_request_span(self.bar_size);
+        let chunks = chunk_ends(start, end, chunk_span)
+            .into_iter()
+            .map(|chunk_end| (chunk_end, chunk_duration))
+            .collect();
+        self.download_chunks(Some(start), chunks).await
+    }
+
+    /// Request the given (end, duration) chunks in order and send the new bars of `start..`
+    async fn download_chunks(
+        &mut self,
+        start: Option<OffsetDateTime>,
+        chunks: Vec<(OffsetDateTime, Duration)>,
+    ) -> Download {
+        debug!(
+            "Downloading historical data for {} in {} request(s)",
+            self.symbol,
+            chunks.len()
+        );
+        let mut download = Download {
+            bars: Vec::new(),
+            complete: true,
+            stop: false,
+        };
+        let mut client = self.connection.client();
+        let mut chunks = chunks.into_iter().peekable();
+        while let Some((chunk_end, chunk_duration)) = chunks.peek().copied() {
+            self.connection
+                .historical_data_pacer()
+                .acquire(&self.symbol)
+                .await;
+            match client.historical_data(
+                &self.contract,
+                Some(chunk_end),
+                chunk_duration,
+                self.bar_size,
+                self.what_to_show,
+                self.use_rth,
+            ) {
+                Ok(historical_data) => {
+                    for bar in &historical_data.bars {
+                        if start.is_some_and(|start| bar.date < start) {
+                            continue;
+                        }
+                        let bar = CachedBar {
+                            timestamp: bar.date.unix_timestamp(),
+                            open: bar.open,
+                            high: bar.high,
+                            low: bar.low,
+                            close: bar.close,
+                            volume: bar.volume,
+                        };
+                        download.bars.push(bar);
+                        if !self.send(&bar) {
+                            download.stop = true;
+                            return download;
+                        }
+                    }
+                }
+                Err(err) => {
+                    let err = DataFeedError::from(err);
+                    warn!(
+                        "Historical data request for {} ending at {} failed: {}",
+                        self.symbol, chunk_end, err
+                    );
+                    let pacing_violation = matches!(err, DataFeedError::PacingViolation(_));
+                    let connection_lost = matches!(err, DataFeedError::ConnectionLost(_));
+                    let fatal = err.is_fatal();
+                    if self.tx.send(Err(err)).is_err() || fatal {
+                        download.stop = true;
+                        return download;
+                    }
+                    // Retry the same chunk once IB accepts requests again
+                    if pacing_violation {
+                        tokio::time::sleep(PACING_VIOLATION_RETRY_DELAY).await;
+                        continue;
+                    }
+                    if connection_lost {
+                        client = self.connection.wait_for_new_client(&client).await;
+                        continue;
+                    }
+                    download.complete = false;
+                }
+            }
+            chunks.next();
+        }
+        download
+    }
+
+    /// Send a bar unless it was already sent. Returns false if nobody consumes the feed anymore.
+    fn send(&mut self, bar: &CachedBar) -> bool {
+        // Consecutive chunks may overlap (e.g. over weekends)
+        if self
+            .last_bar_timestamp
+            .is_some_and(|last| bar.timestamp <= last)
+        {
+            return true;
+        }
+        self.last_bar_timestamp = Some(bar.timestamp);
+        self.tx.send(Ok(*bar)).is_ok()
+    }
+}
+
+/// The end of the range up to `end` whose bars all completed by `now`. Bars are timestamped
+/// at their start, so a bar that started within one bar span before `now` may be in progress.
+fn completed_until(end: OffsetDateTime, now: OffsetDateTime, bar_size: BarSize) -> OffsetDateTime {
+    end.min(now - bar_span(bar_size))
+}
+
+/// The time one bar spans, taking months as 31 days
+fn bar_span(bar_size: BarSize) -> TimeDuration {
+    match bar_size {
+        BarSize::Sec => TimeDuration::seconds(1),
+        BarSize::Sec5 => TimeDuration::seconds(5),
+        BarSize::Sec15 => TimeDuration::seconds(15),
+        BarSize::Sec30 => TimeDuration::seconds(30),
+        BarSize::Min => TimeDuration::minutes(1),
+        BarSize::Min2 => TimeDuration::minutes(2),
+        BarSize::Min3 => TimeDuration::minutes(3),
+        BarSize::Min5 => TimeDuration::minutes(5),
+        BarSize::Min15 => TimeDuration::minutes(15),
+        BarSize::Min20 => TimeDuration::minutes(20),
+        BarSize::Min30 => TimeDuration::minutes(30),
+        BarSize::Hour => TimeDuration::hours(1),
+        BarSize::Hour2 => TimeDuration::hours(2),
+        BarSize::Hour3 => TimeDuration::hours(3),
+        BarSize::Hour4 => TimeDuration::hours(4),
+        BarSize::Hour8 => TimeDuration::hours(8),
+        BarSize::Day => TimeDuration::days(1),
+        BarSize::Week => TimeDuration::weeks(1),
+        BarSize::Month => TimeDuration::days(31),
+    }
+}
+
+/// The longest duration IB serves in a single request for the given bar size
+/// (see "Valid Duration and Bar Size Settings" of the TWS API documentation)
+fn max_request_span(bar_size: BarSize) -> (TimeDuration, Duration) {
+    let seconds = |s: i32| (TimeDuration::seconds(s as i64), s.seconds());
+    let days = |d: i32| (TimeDuration::days(d as i64), d.days());
+    match bar_size {
+        BarSize::Sec => seconds(1800),
+        BarSize::Sec5 => seconds(7200),
+        BarSize::Sec15 => seconds(14400),
+        BarSize::Sec30 => seconds(28800),
+        BarSize::Min => days(1),
+        BarSize::Min2 => days(2),
+        BarSize::Min3 | BarSize::Min5 => days(7),
+        BarSize::Min15 => days(14),
+        BarSize::Min20
+        | BarSize::Min30
+        | BarSize::Hour
+        | BarSize::Hour2
+        | BarSize::Hour3
+        | BarSize::Hour4
+        | BarSize::Hour8 => days(30),
+        BarSize::Day | BarSize::Week | BarSize::Month => days(365),
+    }
+}
+
+/// Converts an IB duration (e.g. "2 Y") to a time span. Months and years are approximated.
+fn duration_span(duration: &Duration) -> Option<TimeDuration> {
+    let duration = duration.to_string();
+    let (value, unit) = duration.trim().split_once(' ')?;
+    let value: i64 = value.parse().ok()?;
+    match unit.trim() {
+        "S" => Some(TimeDuration::seconds(value)),
+        "D" => Some(TimeDuration::days(value)),
+        "W" => Some(TimeDuration::weeks(value)),
+        "M" => Some(TimeDuration::days(value * 30)),
+        "Y" => Some(TimeDuration::days(value * 365)),
+        _ => None,
+    }
+}
+
+/// The end times of consecutive windows of `span` that cover `start..end`, oldest first
+fn chunk_ends(
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    span: TimeDuration,
+) -> Vec<OffsetDateTime> {
+    let mut chunk_ends = Vec::new();
+    let mut chunk_end = start + span;
+    while chunk_end < end {
+        chunk_ends.push(chunk_end);
+        chunk_end += span;
+    }
+    chunk_ends.push(end);
+    chunk_ends
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_duration_span() {
+        assert_eq!(duration_span(&3600.seconds()), Some(TimeDuration::hours(1)));
+        assert_eq!(duration_span(&7.days()), Some(TimeDuration::days(7)));
+        assert_eq!(duration_span(&2.weeks()), Some(TimeDuration::days(14)));
+        assert_eq!(duration_span(&2.years()), Some(TimeDuration::days(730)));
+    }
+
+    #[test]
+    fn test_chunk_ends_cover_whole_interval() {
+        let start = datetime!(2025-01-01 00:00:00 UTC);
+        let end = datetime!(2025-01-03 12:00:00 UTC);
+        assert_eq!(
+            chunk_ends(start, end, TimeDuration::days(1)),
+            vec![
+                datetime!(2025-01-02 00:00:00 UTC),
+                datetime!(2025-01-03 00:00:00 UTC),
+                datetime!(2025-01-03 12:00:00 UTC),
+            ]
+        );
+        // A span longer than the interval results in a single request
+        assert_eq!(chunk_ends(start, end, TimeDuration::days(7)), vec![end]);
+    }
+
+    #[test]
+    fn test_completed_until_excludes_the_bar_in_progress() {
+        let now = datetime!(2025-01-03 15:32:10 UTC);
+        assert_eq!(
+            completed_until(now, now, BarSize::Min5),
+            datetime!(2025-01-03 15:27:10 UTC)
+        );
+        assert_eq!(
+            completed_until(now, now, BarSize::Day),
+            datetime!(2025-01-02 15:32:10 UTC)
+        );
+        // Ranges that ended long ago are complete
+        let end = datetime!(2025-01-01 00:00:00 UTC);
+        assert_eq!(completed_until(end, now, BarSize::Day), end);
+    }
+}
@@ -1,57 +1,164 @@
 use async_trait::async_trait;
+use chrono::{DateTime, Utc};
 use ibapi::market_data::MarketDataType;
-use ibapi::{Client, contracts::Contract, market_data::realtime::TickTypes};
+use ibapi::{
+    contracts::{Contract, tick_types::TickType},
+    market_data::realtime::TickTypes,
+};
 use std::sync::Arc;
 use thiserror::Error;
 use tokio::sync::mpsc;
+use tracing::{error, info, warn};
 
-use crate::data_feed::{DataFeed, MarketData};
+use crate::{
+    data_feed::{DataFeed, DataFeedError, MarketData, Quote, spawn_ib_task},
+    ib_connection::IbConnection,
+};
+
+pub const DEFAULT_GENERIC_TICKS: &[&str] = &["233", "293"];
 
 pub struct IbMarketDataFeed {
     name: String,
-    rx: mpsc::UnboundedReceiver<MarketData>,
+    rx: mpsc::UnboundedReceiver<Result<MarketData, DataFeedError>>,
+}
+
+/// Options of an IB market data subscription
+#[derive(Debug, Clone)]
+pub struct IbMarketDataOptions {
+    pub market_data_type: MarketDataType,
+    /// Generic tick types requested in addition to the default ones (e.g. "233" for RT volume)
+    pub generic_ticks: Vec<String>,
+    /// Request a single snapshot instead of streaming. The feed ends once it is received.
+    pub snapshot: bool,
+    /// Request a (paid) regulatory snapshot
+    pub regulatory_snapshot: bool,
 }
 
 impl IbMarketDataFeed {
+    /// Subscribes to every symbol and multiplexes their events into this feed
     pub fn new(
         name: String,
-        client: Arc<Client>,
-        symbol: String,
-        market_data_type: MarketDataType,
+        connection: Arc<IbConnection>,
+        symbols: Vec<String>,
+        options: IbMarketDataOptions,
     ) -> Result<Self, IbMarketDataFeedError> {
         let (tx, rx) = mpsc::unbounded_channel();
-        client
-            .switch_market_data_type(market_data_type)
+        connection
+            .client()
+            .switch_market_data_type(options.market_data_type)
             .map_err(|err| IbMarketDataFeedError::Init(err.to_string()))?;
-        let contract = Contract::stock(&symbol);
-        // TODO: Fix generic ticks, snapshot and regulatory snapshot
-        let generic_ticks = &["233", "293"];
-        let snapshot = false;
-        let regulatory_snapshot = false;
-        tokio::spawn(async move {
-            // TODO: Handle error by passing it to tx
-            let subscription = client
-                .market_data(&contract, generic_ticks, snapshot, regulatory_snapshot)
-                .expect("Failed to retireve market data");
-            for tick in &subscription {
-                match tick {
-                    TickTypes::Price(tick_price) => {
-                        let md = MarketData {
-                            symbol: symbol.clone(),
-                            price: tick_price.price,
-                            // timestamp:
+        for symbol in symbols {
+            spawn_subscription(connection.clone(), symbol, options.clone(), tx.clone());
+        }
+        Ok(Self { name, rx })
+    }
+}
+
+/// Streams the events of one symbol until nobody consumes them or the subscription can not
+/// recover. The feed ends once the subscriptions of all its symbols ended.
+fn spawn_subscription(
+    connection: Arc<IbConnection>,
+    symbol: String,
+    options: IbMarketDataOptions,
+    tx: mpsc::UnboundedSender<Result<MarketData, DataFeedError>>,
+) {
+    let market_data_type = options.market_data_type;
+    let contract = Contract::stock(&symbol);
+    spawn_ib_task(async move {
+        let mut client = connection.client();
+        let generic_ticks: Vec<&str> = options.generic_ticks.iter().map(|t| t.as_str()).collect();
+        // Kept across re-subscriptions, IB resends the whole book anyway
+        let mut book = QuoteBook::default();
+        loop {
+            let err = match client.market_data(
+                &contract,
+                &generic_ticks,
+                options.snapshot,
+                options.regulatory_snapshot,
+            ) {
+                Ok(subscription) => {
+                    for tick in &subscription {
+                        let event = match tick {
+                            TickTypes::Price(tick) => {
+                                match book.on_price(
+                                    &symbol,
+                                    &tick.tick_type,
+                                    tick.price,
+                                    None,
+                                    Utc::now(),
+                                ) {
+                                    Some(data) => Ok(data),
+                                    None => continue,
+                                }
+                            }
+                            TickTypes::Size(tick) => {
+                                match book.on_size(&symbol, &tick.tick_type, tick.size, Utc::now())
+                                {
+                                    Some(data) => Ok(data),
+                                    None => continue,
+                                }
+                            }
+                            TickTypes::PriceSize(tick) => {
+                                book.on_size(&symbol, &tick.size_tick_type, tick.size, Utc::now());
+                                match book.on_price(
+                                    &symbol,
+                                    &tick.price_tick_type,
+                                    tick.price,
+                                    Some(tick.size),
+                                    Utc::now(),
+                                ) {
+                                    Some(data) => Ok(data),
+                                    None => continue,
+                                }
+                            }
+                            TickTypes::Notice(notice) => {
+                                match DataFeedError::from_ib_code(
+                                    notice.code,
+                                    &format!("{symbol}: {}", notice.message),
+                                ) {
+                                    Some(err) => Err(err),
+                                    None => {
+                                        info!(
+                                            "Market data notice for {}: {}",
+                                            symbol, notice.message
+                                        );
+                                        continue;
+                                    }
+                                }
+                            }
+                            TickTypes::SnapshotEnd => {
+                                subscription.cancel();
+                                return;
+                            }
+                            _ => continue,
                         };
-                        let _ = tx.send(md);
+                        let fatal = matches!(&event, Err(err) if err.is_fatal());
+                        if tx.send(event).is_err() || fatal {
+                            // Nobody consumes this feed anymore or it can not recover
+                            subscription.cancel();
+                            return;
+                        }
                     }
-                    TickTypes::SnapshotEnd => {
-                        subscription.cancel();
-                    }
-                    _ => {}
+                    subscription.error().map(DataFeedError::from).unwrap_or(
+                        DataFeedError::ConnectionLost(format!(
+                            "Market data subscription for {symbol} ended"
+                        )),
+                    )
                 }
+                Err(err) => DataFeedError::from(err),
+            };
+            let fatal = err.is_fatal();
+            warn!("Market data for {} interrupted: {}", symbol, err);
+            if tx.send(Err(err)).is_err() || fatal {
+                return;
             }
-        });
-        Ok(Self { name, rx })
-    }
+            client = connection.wait_for_new_client(&client).await;
+            info!("Re-subscribing to market data for {}", symbol);
+            if let Err(err) = client.switch_market_data_type(market_data_type) {
+                error!("Failed to switch market data type: {}", err);
+            }
+        }
+    });
 }
 
 #[async_trait]
@@ -59,13 +166,174 @@ impl DataFeed for IbMarketDataFeed {
     fn name(&self) -> &str {
         &self.name
     }
-    async fn next_tick(&mut self) -> Option<MarketData> {
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
         self.rx.recv().await
     }
 }
 
+/// Top of the book of a symbol, built from the individual price and size ticks
+#[derive(Debug, Default)]
+struct QuoteBook {
+    quote: Quote,
+}
+
+impl QuoteBook {
+    /// Apply a price tick. Returns a trade for last prices and a quote when the book changed.
+    /// `size` is set when IB sent the price together with its size.
+    fn on_price(
+        &mut self,
+        symbol: &str,
+        tick_type: &TickType,
+        price: f64,
+        size: Option<f64>,
+        timestamp: DateTime<Utc>,
+    ) -> Option<MarketData> {
+        let before = self.quote;
+        // IB sends -1 when a side of the book is empty
+        let price = price.max(0.0);
+        match tick_type {
+            TickType::Bid | TickType::DelayedBid => {
+                self.quote.bid = price;
+                self.quote.bid_size = size.unwrap_or(self.quote.bid_size);
+            }
+            TickType::Ask | TickType::DelayedAsk => {
+                self.quote.ask = price;
+                self.quote.ask_size = size.unwrap_or(self.quote.ask_size);
+            }
+            TickType::Last | TickType::DelayedLast if price > 0.0 => {
+                self.quote.last = price;
+                self.quote.last_size = size.unwrap_or(self.quote.last_size);
+                return Some(MarketData::tick(
+                    symbol.to_string(),
+                    price,
+                    size.unwrap_or(0.0),
+                    timestamp,
+                ));
+            }
+            _ => return None,
+        }
+        self.quote_if_changed(symbol, before, timestamp)
+    }
+
+    /// Apply a size tick. Returns a quote when the book changed.
+    fn on_size(
+        &mut self,
+        symbol: &str,
+        tick_type: &TickType,
+        size: f64,
+        timestamp: DateTime<Utc>,
+    ) -> Option<MarketData> {
+        let before = self.quote;
+        match tick_type {
+            TickType::BidSize | TickType::DelayedBidSize => self.quote.bid_size = size,
+            TickType::AskSize | TickType::DelayedAskSize => self.quote.ask_size = size,
+            // Neither changes the book
+            TickType::LastSize | TickType::DelayedLastSize => {
+                self.quote.last_size = size;
+                return None;
+            }
+            TickType::Volume | TickType::DelayedVolume => {
+                self.quote.volume = size;
+                return None;
+            }
+            _ => return None,
+        }
+        self.quote_if_changed(symbol, before, timestamp)
+    }
+
+    fn quote_if_changed(
+        &self,
+        symbol: &str,
+        before: Quote,
+        timestamp: DateTime<Utc>,
+    ) -> Option<MarketData> {
+        let changed = before.bid != self.quote.bid
+            || before.ask != self.quote.ask
+            || before.bid_size != self.quote.bid_size
+            || before.ask_size != self.quote.ask_size;
+        if !changed {
+            return None;
+        }
+        MarketData::quote(symbol.to_string(), self.quote, timestamp)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum IbMarketDataFeedError {
     #[error("Interactive Broker data feed initialization failed: {0}")]
     Init(String),
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_feed::MarketDataKind;
+
+    #[test]
+    fn test_quote_book_emits_quotes_on_book_changes() {
+        let mut book = QuoteBook::default();
+        let now = Utc::now();
+
+        // Nothing to price the book at yet
+        assert!(
+            book.on_size("AAPL", &TickType::BidSize, 100.0, now)
+                .is_none()
+        );
+        let bid = book
+            .on_price("AAPL", &TickType::Bid, 99.0, Some(200.0), now)
+            .unwrap();
+        // Only one side is known, there is no midpoint nor last price yet
+        assert_eq!(bid.price, 99.0);
+        let ask = book
+            .on_price("AAPL", &TickType::Ask, 101.0, None, now)
+            .unwrap();
+        assert_eq!(ask.price, 100.0);
+        let MarketDataKind::Quote(quote) = ask.kind else {
+            panic!("expected a quote, got {:?}", ask.kind);
+        };
+        assert_eq!((quote.bid, quote.ask, quote.bid_size), (99.0, 101.0, 200.0));
+
+        assert!(
+            book.on_size("AAPL", &TickType::AskSize, 300.0, now)
+                .is_some()
+        );
+        // Same size again does not change the book
+        assert!(
+            book.on_size("AAPL", &TickType::AskSize, 300.0, now)
+                .is_none()
+        );
+        assert!(
+            book.on_size("AAPL", &TickType::Volume, 5000.0, now)
+                .is_none()
+        );
+        assert_eq!(book.quote.volume, 5000.0);
+    }
+
+    #[test]
+    fn test_quote_book_emits_trades_on_last_price() {
+        let mut book = QuoteBook::default();
+        let now = Utc::now();
+
+        let trade = book
+            .on_price("AAPL", &TickType::DelayedLast, 100.5, Some(10.0), now)
+            .unwrap();
+        assert_eq!(trade.kind, MarketDataKind::Trade);
+        assert_eq!((trade.price, trade.volume), (100.5, 10.0));
+        assert_eq!(book.quote.last, 100.5);
+
+        assert!(
+            book.on_size("AAPL", &TickType::LastSize, 20.0, now)
+                .is_none()
+        );
+        assert_eq!(book.quote.last_size, 20.0);
+        // Unavailable last price
+        assert!(
+            book.on_price("AAPL", &TickType::Last, -1.0, None, now)
+                .is_none()
+        );
+        assert!(
+            book.on_price("AAPL", &TickType::High, 102.0, None, now)
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use async_trait::async_trait;
+use ibapi::{
+    contracts::Contract,
+    market_data::realtime::{Bar, BarSize, WhatToShow},
+};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    data_feed::{
+        DataFeed, DataFeedError, MarketData, MarketDataKind, spawn_ib_task,
+        utc_from_offset_datetime,
+    },
+    ib_connection::IbConnection,
+};
+
+/// Streams the 5 seconds bars IB builds in real time
+pub struct IbRealtimeBarsFeed {
+    name: String,
+    rx: mpsc::UnboundedReceiver<Result<MarketData, DataFeedError>>,
+}
+
+impl IbRealtimeBarsFeed {
+    /// Subscribes to every symbol and multiplexes their bars into this feed
+    pub fn new(
+        name: String,
+        connection: Arc<IbConnection>,
+        symbols: Vec<String>,
+        what_to_show: WhatToShow,
+        use_rth: bool,
+    ) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        for symbol in symbols {
+            spawn_subscription(
+                connection.clone(),
+                symbol,
+                what_to_show,
+                use_rth,
+                tx.clone(),
+            );
+        }
+        Self { name, rx }
+    }
+}
+
+fn spawn_subscription(
+    connection: Arc<IbConnection>,
+    symbol: String,
+    what_to_show: WhatToShow,
+    use_rth: bool,
+    tx: mpsc::UnboundedSender<Result<MarketData, DataFeedError>>,
+) {
+    let contract = Contract::stock(&symbol);
+    spawn_ib_task(async move {
+        let mut client = connection.client();
+        loop {
+            let err = match client.realtime_bars(&contract, BarSize::Sec5, what_to_show, use_rth) {
+                Ok(subscription) => {
+                    for bar in &subscription {
+                        if tx.send(Ok(market_data(&symbol, &bar))).is_err() {
+                            // Nobody consumes this feed anymore
+                            subscription.cancel();
+                            return;
+                        }
+                    }
+                    subscription.error().map(DataFeedError::from).unwrap_or(
+                        DataFeedError::ConnectionLost(format!(
+                            "Real-time bars subscription for {symbol} ended"
+                        )),
+                    )
+                }
+                Err(err) => DataFeedError::from(err),
+            };
+            let fatal = err.is_fatal();
+            warn!("Real-time bars for {} interrupted: {}", symbol, err);
+            if tx.send(Err(err)).is_err() || fatal {
+                return;
+            }
+            client = connection.wait_for_new_client(&client).await;
+            info!("Re-subscribing to real-time bars for {}", symbol);
+        }
+    });
+}
+
+fn market_data(symbol: &str, bar: &Bar) -> MarketData {
+    MarketData {
+        symbol: symbol.to_string(),
+        price: bar.close,
+        timestamp: utc_from_offset_datetime(bar.date),
+        open: bar.open,
+        high: bar.high,
+        low: bar.low,
+        volume: bar.volume,
+        kind: MarketDataKind::Bar,
+    }
+}
+
+#[async_trait]
+impl DataFeed for IbRealtimeBarsFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        self.rx.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_market_data_from_realtime_bar() {
+        let bar = Bar {
+            date: datetime!(2024-05-02 14:30:05 UTC),
+            open: 100.0,
+            high: 101.0,
+            low: 99.5,
+            close: 100.5,
+            volume: 1200.0,
+            wap: 100.2,
+            count: 12,
+        };
+
+        let data = market_data("AAPL", &bar);
+
+        assert_eq!(data.price, 100.5);
+        assert_eq!((data.open, data.high, data.low), (100.0, 101.0, 99.5));
+        assert_eq!(data.volume, 1200.0);
+        assert_eq!(data.kind, MarketDataKind::Bar);
+        assert_eq!(data.timestamp.to_rfc3339(), "2024-05-02T14:30:05+00:00");
+    }
+}
@@ -0,0 +1,235 @@
+use async_trait::async_trait;
+use ibapi::{
+    Client, Error,
+    contracts::Contract,
+    market_data::realtime::{BidAsk, MidPoint, Trade},
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    data_feed::{
+        DataFeed, DataFeedError, MarketData, Quote, spawn_ib_task, utc_from_offset_datetime,
+    },
+    ib_connection::IbConnection,
+};
+
+/// The tick-by-tick stream to subscribe to
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum TickByTickType {
+    /// Trades, without the ones reported with special conditions (e.g. odd lots)
+    Last,
+    /// All trades
+    AllLast,
+    BidAsk,
+    MidPoint,
+}
+
+/// Streams every trade or quote change as reported by IB, without the aggregation that
+/// `market_data` applies
+pub struct IbTickByTickFeed {
+    name: String,
+    rx: mpsc::UnboundedReceiver<Result<MarketData, DataFeedError>>,
+}
+
+type Sender = mpsc::UnboundedSender<Result<MarketData, DataFeedError>>;
+
+impl IbTickByTickFeed {
+    /// Subscribes to every symbol and multiplexes their ticks into this feed
+    pub fn new(
+        name: String,
+        connection: Arc<IbConnection>,
+        symbols: Vec<String>,
+        tick_type: TickByTickType,
+    ) -> Self {
+        let (tx, rx) = mpsc::unbounded_channel();
+        for symbol in symbols {
+            spawn_subscription(connection.clone(), symbol, tick_type, tx.clone());
+        }
+        Self { name, rx }
+    }
+}
+
+fn spawn_subscription(
+    connection: Arc<IbConnection>,
+    symbol: String,
+    tick_type: TickByTickType,
+    tx: Sender,
+) {
+    let contract = Contract::stock(&symbol);
+    spawn_ib_task(async move {
+        let mut client = connection.client();
+        loop {
+            let err = match stream(&client, &contract, &symbol, tick_type, &tx) {
+                Ok(None) => return,
+                Ok(Some(err)) => err,
+                Err(err) => DataFeedError::from(err),
+            };
+            let fatal = err.is_fatal();
+            warn!("Tick-by-tick data for {} interrupted: {}", symbol, err);
+            if tx.send(Err(err)).is_err() || fatal {
+                return;
+            }
+            client = connection.wait_for_new_client(&client).await;
+            info!("Re-subscribing to tick-by-tick data for {}", symbol);
+        }
+    });
+}
+
+/// Forwards the ticks of a subscription until it ends. Returns the error that ended it, or
+/// `None` when nobody consumes the feed anymore.
+fn stream(
+    client: &Client,
+    contract: &Contract,
+    symbol: &str,
+    tick_type: TickByTickType,
+    tx: &Sender,
+) -> Result<Option<DataFeedError>, Error> {
+    // Each stream has its own item type, so the subscriptions can not share a variable
+    macro_rules! forward {
+        ($subscription:expr, $map:expr) => {{
+            let subscription = $subscription;
+            for tick in &subscription {
+                // Quotes without any price are skipped
+                let data: Option<MarketData> = $map(symbol, &tick).into();
+                let Some(data) = data else {
+                    continue;
+                };
+                if tx.send(Ok(data)).is_err() {
+                    subscription.cancel();
+                    return Ok(None);
+                }
+            }
+            subscription.error().map(DataFeedError::from)
+        }};
+    }
+    let err = match tick_type {
+        TickByTickType::Last => forward!(client.tick_by_tick_last(contract, 0, false)?, trade),
+        TickByTickType::AllLast => {
+            forward!(client.tick_by_tick_all_last(contract, 0, false)?, trade)
+        }
+        TickByTickType::BidAsk => {
+            forward!(client.tick_by_tick_bid_ask(contract, 0, false)?, bid_ask)
+        }
+        TickByTickType::MidPoint => {
+            forward!(client.tick_by_tick_midpoint(contract, 0, false)?, midpoint)
+        }
+    };
+    Ok(Some(err.unwrap_or(DataFeedError::ConnectionLost(format!(
+        "Tick-by-tick subscription for {symbol} ended"
+    )))))
+}
+
+fn trade(symbol: &str, trade: &Trade) -> MarketData {
+    MarketData::tick(
+        symbol.to_string(),
+        trade.price,
+        trade.size,
+        utc_from_offset_datetime(trade.time),
+    )
+}
+
+fn bid_ask(symbol: &str, bid_ask: &BidAsk) -> Option<MarketData> {
+    let quote = Quote {
+        bid: bid_ask.bid_price,
+        ask: bid_ask.ask_price,
+        bid_size: bid_ask.bid_size,
+        ask_size: bid_ask.ask_size,
+        ..Quote::default()
+    };
+    MarketData::quote(
+        symbol.to_string(),
+        quote,
+        utc_from_offset_datetime(bid_ask.time),
+    )
+}
+
+/// A midpoint is reported as a quote with both sides at the midpoint
+fn midpoint(symbol: &str, midpoint: &MidPoint) -> Option<MarketData> {
+    let quote = Quote {
+        bid: midpoint.mid_point,
+        ask: midpoint.mid_point,
+        ..Quote::default()
+    };
+    MarketData::quote(
+        symbol.to_string(),
+        quote,
+        utc_from_offset_datetime(midpoint.time),
+    )
+}
+
+#[async_trait]
+impl DataFeed for IbTickByTickFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        self.rx.recv().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_feed::MarketDataKind;
+    use ibapi::market_data::realtime::{BidAskAttribute, TradeAttribute};
+    use time::macros::datetime;
+
+    #[test]
+    fn test_trade_is_a_tick() {
+        let tick = Trade {
+            tick_type: "AllLast".to_string(),
+            time: datetime!(2024-05-02 14:30:05 UTC),
+            price: 100.5,
+            size: 300.0,
+            trade_attribute: TradeAttribute {
+                past_limit: false,
+                unreported: false,
+            },
+            exchange: "ISLAND".to_string(),
+            special_conditions: String::new(),
+        };
+
+        let data = trade("AAPL", &tick);
+
+        assert_eq!(data.kind, MarketDataKind::Trade);
+        assert_eq!((data.price, data.volume), (100.5, 300.0));
+        assert_eq!(data.timestamp.to_rfc3339(), "2024-05-02T14:30:05+00:00");
+    }
+
+    #[test]
+    fn test_bid_ask_and_midpoint_are_quotes() {
+        let time = datetime!(2024-05-02 14:30:05 UTC);
+        let tick = BidAsk {
+            time,
+            bid_price: 99.0,
+            ask_price: 101.0,
+            bid_size: 100.0,
+            ask_size: 200.0,
+            bid_ask_attribute: BidAskAttribute {
+                bid_past_low: false,
+                ask_past_high: false,
+            },
+        };
+
+        let data = bid_ask("AAPL", &tick).unwrap();
+        assert_eq!(data.price, 100.0);
+        let MarketDataKind::Quote(quote) = data.kind else {
+            panic!("expected a quote, got {:?}", data.kind);
+        };
+        assert_eq!((quote.bid_size, quote.ask_size), (100.0, 200.0));
+
+        let data = midpoint(
+            "AAPL",
+            &MidPoint {
+                time,
+                mid_point: 100.25,
+            },
+        )
+        .unwrap();
+        assert!(data.is_quote());
+        assert_eq!(data.price, 100.25);
+    }
+}
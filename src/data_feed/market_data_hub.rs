@@ -0,0 +1,314 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::data_feed::{DataFeed, DataFeedError, MarketData};
+
+pub const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 1024;
+
+type Event = Result<MarketData, DataFeedError>;
+
+/// What a subscription does when its strategy does not keep up with the hub
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq)]
+pub enum BackpressurePolicy {
+    /// Drop the oldest pending event to make room for the new one
+    DropOldest,
+    /// Keep only the latest pending event per symbol
+    Conflate,
+    /// Wait until there is room. This slows down the hub and so all the other subscribers.
+    #[default]
+    Block,
+}
+
+/// Consumes a data feed once and broadcasts its events to any number of subscriptions, so
+/// several strategies can watch the same instruments without duplicate IB subscriptions.
+pub struct MarketDataHub {
+    name: String,
+    source: Box<dyn DataFeed>,
+    subscribers: Vec<Arc<SubscriberQueue>>,
+}
+
+impl MarketDataHub {
+    pub fn new(name: String, source: Box<dyn DataFeed>) -> Self {
+        Self {
+            name,
+            source,
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Add a subscription to the events of `symbols` (all symbols when `None`). Errors of the
+    /// source are delivered to every subscription.
+    pub fn subscribe(
+        &mut self,
+        name: String,
+        symbols: Option<HashSet<String>>,
+        policy: BackpressurePolicy,
+        capacity: usize,
+    ) -> HubSubscription {
+        let queue = Arc::new(SubscriberQueue {
+            symbols,
+            policy,
+            capacity: capacity.max(1),
+            state: Mutex::new(QueueState::default()),
+            readable: Notify::new(),
+            writable: Notify::new(),
+        });
+        self.subscribers.push(queue.clone());
+        HubSubscription { name, queue }
+    }
+
+    /// Start broadcasting. Subscriptions must be added before, so none of them misses events.
+    pub fn start(self) {
+        let Self {
+            name,
+            mut source,
+            mut subscribers,
+        } = self;
+        if subscribers.is_empty() {
+            warn!("Market data hub {} has no subscribers", name);
+            return;
+        }
+        tokio::spawn(async move {
+            while let Some(event) = source.next_tick().await {
+                let mut active = Vec::with_capacity(subscribers.len());
+                for subscriber in subscribers.drain(..) {
+                    if !subscriber.wants(&event) || subscriber.push(event.clone()).await {
+                        active.push(subscriber);
+                    }
+                }
+                subscribers = active;
+                if subscribers.is_empty() {
+                    info!("All subscriptions of market data hub {} ended", name);
+                    return;
+                }
+            }
+            for subscriber in &subscribers {
+                subscriber.close();
+            }
+        });
+    }
+}
+
+struct SubscriberQueue {
+    symbols: Option<HashSet<String>>,
+    policy: BackpressurePolicy,
+    capacity: usize,
+    state: Mutex<QueueState>,
+    /// Signals the subscription that there are events (or that the hub ended)
+    readable: Notify,
+    /// Signals a blocked hub that there is room (or that the subscription was dropped)
+    writable: Notify,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    events: VecDeque<Event>,
+    /// The source of the hub ended
+    closed: bool,
+    /// The subscription was dropped
+    unsubscribed: bool,
+    dropped_events: u64,
+}
+
+impl SubscriberQueue {
+    fn wants(&self, event: &Event) -> bool {
+        match (event, &self.symbols) {
+            (Ok(data), Some(symbols)) => symbols.contains(&data.symbol),
+            _ => true,
+        }
+    }
+
+    /// Returns false when the subscription was dropped
+    async fn push(&self, event: Event) -> bool {
+        let mut event = Some(event);
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.unsubscribed {
+                    return false;
+                }
+                if self.policy != BackpressurePolicy::Block || state.events.len() < self.capacity {
+                    state.push(event.take().unwrap(), self.policy, self.capacity);
+                    drop(state);
+                    self.readable.notify_one();
+                    return true;
+                }
+            }
+            self.writable.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        self.state.lock().unwrap().closed = true;
+        self.readable.notify_one();
+    }
+}
+
+impl QueueState {
+    fn push(&mut self, event: Event, policy: BackpressurePolicy, capacity: usize) {
+        if policy == BackpressurePolicy::Conflate
+            && let Ok(data) = &event
+            && let Some(pending) = self
+                .events
+                .iter_mut()
+                .find(|pending| matches!(pending, Ok(pending) if pending.symbol == data.symbol))
+        {
+            *pending = event;
+            self.dropped_events += 1;
+            return;
+        }
+        if self.events.len() >= capacity {
+            self.events.pop_front();
+            self.dropped_events += 1;
+        }
+        self.events.push_back(event);
+    }
+}
+
+/// A strategy's view on a `MarketDataHub`
+pub struct HubSubscription {
+    name: String,
+    queue: Arc<SubscriberQueue>,
+}
+
+impl HubSubscription {
+    /// Events dropped so far because of the backpressure policy
+    pub fn dropped_events(&self) -> u64 {
+        self.queue.state.lock().unwrap().dropped_events
+    }
+}
+
+#[async_trait]
+impl DataFeed for HubSubscription {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        loop {
+            {
+                let mut state = self.queue.state.lock().unwrap();
+                if let Some(event) = state.events.pop_front() {
+                    drop(state);
+                    self.queue.writable.notify_one();
+                    return Some(event);
+                }
+                if state.closed {
+                    return None;
+                }
+            }
+            self.queue.readable.notified().await;
+        }
+    }
+}
+
+impl Drop for HubSubscription {
+    fn drop(&mut self) {
+        self.queue.state.lock().unwrap().unsubscribed = true;
+        self.queue.writable.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    struct VecDataFeed(VecDeque<MarketData>);
+
+    #[async_trait]
+    impl DataFeed for VecDataFeed {
+        fn name(&self) -> &str {
+            "vec"
+        }
+        async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+            self.0.pop_front().map(Ok)
+        }
+    }
+
+    fn tick(symbol: &str, price: f64) -> MarketData {
+        let timestamp: DateTime<Utc> = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        MarketData::tick(symbol.to_string(), price, 0.0, timestamp)
+    }
+
+    fn prices(events: &VecDeque<Event>) -> Vec<f64> {
+        events
+            .iter()
+            .map(|event| event.as_ref().unwrap().price)
+            .collect()
+    }
+
+    #[test]
+    fn test_drop_oldest_keeps_the_latest_events() {
+        let mut state = QueueState::default();
+        for price in [1.0, 2.0, 3.0] {
+            state.push(Ok(tick("AAPL", price)), BackpressurePolicy::DropOldest, 2);
+        }
+        assert_eq!(prices(&state.events), vec![2.0, 3.0]);
+        assert_eq!(state.dropped_events, 1);
+    }
+
+    #[test]
+    fn test_conflate_keeps_the_latest_event_per_symbol() {
+        let mut state = QueueState::default();
+        state.push(Ok(tick("AAPL", 1.0)), BackpressurePolicy::Conflate, 10);
+        state.push(Ok(tick("MSFT", 2.0)), BackpressurePolicy::Conflate, 10);
+        state.push(Ok(tick("AAPL", 3.0)), BackpressurePolicy::Conflate, 10);
+        assert_eq!(prices(&state.events), vec![3.0, 2.0]);
+        assert_eq!(state.dropped_events, 1);
+    }
+
+    #[tokio::test]
+    async fn test_hub_broadcasts_to_all_subscriptions() {
+        let source = VecDataFeed((1..=100).map(|i| tick("AAPL", i as f64)).collect());
+        let mut hub = MarketDataHub::new("hub".to_string(), Box::new(source));
+        // Blocking subscriptions with a tiny capacity still receive everything
+        let mut first = hub.subscribe("first".to_string(), None, BackpressurePolicy::Block, 1);
+        let mut second = hub.subscribe("second".to_string(), None, BackpressurePolicy::Block, 4);
+        let symbols = Some(HashSet::from(["MSFT".to_string()]));
+        let mut other = hub.subscribe("other".to_string(), symbols, BackpressurePolicy::Block, 1);
+        hub.start();
+
+        let mut received = (0, 0);
+        loop {
+            tokio::select! {
+                Some(event) = first.next_tick() => {
+                    assert_eq!(event.unwrap().price, (received.0 + 1) as f64);
+                    received.0 += 1;
+                }
+                Some(event) = second.next_tick() => {
+                    assert_eq!(event.unwrap().price, (received.1 + 1) as f64);
+                    received.1 += 1;
+                }
+                else => break,
+            }
+        }
+        assert_eq!(received, (100, 100));
+        assert!(other.next_tick().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_dropped_subscription_does_not_block_the_hub() {
+        let source = VecDataFeed((1..=10).map(|i| tick("AAPL", i as f64)).collect());
+        let mut hub = MarketDataHub::new("hub".to_string(), Box::new(source));
+        let blocked = hub.subscribe("blocked".to_string(), None, BackpressurePolicy::Block, 1);
+        let mut active = hub.subscribe("active".to_string(), None, BackpressurePolicy::Block, 1);
+        hub.start();
+        drop(blocked);
+
+        let mut count = 0;
+        while active.next_tick().await.is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 10);
+    }
+}
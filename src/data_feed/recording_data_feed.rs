@@ -0,0 +1,342 @@
+use crate::data_feed::{DataFeed, DataFeedError, MarketData, MarketDataKind, Quote};
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDate, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File, OpenOptions},
+    path::PathBuf,
+};
+use thiserror::Error;
+use tracing::error;
+
+/// A row of a recording. The columns after `price` are empty in recordings made before they
+/// were added, which replay as trades received at their market time.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct RecordedTick {
+    /// RFC 3339 time (UTC) at which the tick was received
+    pub timestamp: String,
+    pub symbol: String,
+    pub price: f64,
+    /// RFC 3339 time (UTC) of the tick given by the feed
+    #[serde(default)]
+    pub market_timestamp: Option<String>,
+    #[serde(default)]
+    pub kind: Option<RecordedKind>,
+    #[serde(default)]
+    pub volume: Option<f64>,
+    #[serde(default)]
+    pub open: Option<f64>,
+    #[serde(default)]
+    pub high: Option<f64>,
+    #[serde(default)]
+    pub low: Option<f64>,
+    /// Top of the book of quotes
+    #[serde(default)]
+    pub bid: Option<f64>,
+    #[serde(default)]
+    pub ask: Option<f64>,
+    #[serde(default)]
+    pub bid_size: Option<f64>,
+    #[serde(default)]
+    pub ask_size: Option<f64>,
+    #[serde(default)]
+    pub last: Option<f64>,
+    #[serde(default)]
+    pub last_size: Option<f64>,
+    #[serde(default)]
+    pub quote_volume: Option<f64>,
+}
+
+/// The [`MarketDataKind`] of a recorded tick
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub(crate) enum RecordedKind {
+    Trade,
+    Bar,
+    Quote,
+}
+
+impl RecordedTick {
+    pub(crate) fn new(data: &MarketData, received_at: DateTime<Utc>) -> Self {
+        let rfc3339 =
+            |timestamp: DateTime<Utc>| timestamp.to_rfc3339_opts(SecondsFormat::Micros, true);
+        let (kind, quote) = match data.kind {
+            MarketDataKind::Trade => (RecordedKind::Trade, None),
+            MarketDataKind::Bar => (RecordedKind::Bar, None),
+            MarketDataKind::Quote(quote) => (RecordedKind::Quote, Some(quote)),
+        };
+        let quote_field = |field: fn(&Quote) -> f64| quote.as_ref().map(field);
+        Self {
+            timestamp: rfc3339(received_at),
+            symbol: data.symbol.clone(),
+            price: data.price,
+            market_timestamp: Some(rfc3339(data.timestamp)),
+            kind: Some(kind),
+            volume: Some(data.volume),
+            open: Some(data.open),
+            high: Some(data.high),
+            low: Some(data.low),
+            bid: quote_field(|quote| quote.bid),
+            ask: quote_field(|quote| quote.ask),
+            bid_size: quote_field(|quote| quote.bid_size),
+            ask_size: quote_field(|quote| quote.ask_size),
+            last: quote_field(|quote| quote.last),
+            last_size: quote_field(|quote| quote.last_size),
+            quote_volume: quote_field(|quote| quote.volume),
+        }
+    }
+
+    /// The time the tick was received and the recorded market data
+    pub(crate) fn into_market_data(
+        self,
+    ) -> Result<(DateTime<Utc>, MarketData), chrono::ParseError> {
+        let parse = |timestamp: &str| {
+            DateTime::parse_from_rfc3339(timestamp).map(|timestamp| timestamp.with_timezone(&Utc))
+        };
+        let received_at = parse(&self.timestamp)?;
+        let timestamp = match &self.market_timestamp {
+            Some(market_timestamp) => parse(market_timestamp)?,
+            None => received_at,
+        };
+        let kind = match self.kind.unwrap_or(RecordedKind::Trade) {
+            RecordedKind::Trade => MarketDataKind::Trade,
+            RecordedKind::Bar => MarketDataKind::Bar,
+            RecordedKind::Quote => MarketDataKind::Quote(Quote {
+                bid: self.bid.unwrap_or_default(),
+                ask: self.ask.unwrap_or_default(),
+                bid_size: self.bid_size.unwrap_or_default(),
+                ask_size: self.ask_size.unwrap_or_default(),
+                last: self.last.unwrap_or_default(),
+                last_size: self.last_size.unwrap_or_default(),
+                volume: self.quote_volume.unwrap_or_default(),
+            }),
+        };
+        let data = MarketData {
+            symbol: self.symbol,
+            price: self.price,
+            timestamp,
+            open: self.open.unwrap_or(self.price),
+            high: self.high.unwrap_or(self.price),
+            low: self.low.unwrap_or(self.price),
+            volume: self.volume.unwrap_or_default(),
+            kind,
+        };
+        Ok((received_at, data))
+    }
+}
+
+/// Passes through the ticks of another feed and appends them to an on-disk log, so that the
+/// session can be replayed later with a [`super::replay_data_feed::ReplayDataFeed`].
+/// One file per day is written: `<dir>/<feed name>_<YYYY-MM-DD>.csv`.
+pub struct RecordingDataFeed {
+    name: String,
+    inner: Box<dyn DataFeed>,
+    dir: PathBuf,
+    writer: Option<(NaiveDate, csv::Writer<File>)>,
+}
+
+impl RecordingDataFeed {
+    pub fn new(
+        name: String,
+        inner: Box<dyn DataFeed>,
+        dir: impl Into<PathBuf>,
+    ) -> Result<Self, RecordingDataFeedError> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir).map_err(|err| {
+            RecordingDataFeedError::Io(dir.display().to_string(), err.to_string())
+        })?;
+        Ok(Self {
+            name,
+            inner,
+            dir,
+            writer: None,
+        })
+    }
+
+    fn record(
+        &mut self,
+        data: &MarketData,
+        received_at: DateTime<Utc>,
+    ) -> Result<(), RecordingDataFeedError> {
+        let date = received_at.date_naive();
+        let writer = match &mut self.writer {
+            Some((writer_date, writer)) if *writer_date == date => writer,
+            _ => {
+                let writer = self.open_log(date)?;
+                &mut self.writer.insert((date, writer)).1
+            }
+        };
+        let tick = RecordedTick::new(data, received_at);
+        let io_err = |err: &dyn std::fmt::Display| {
+            RecordingDataFeedError::Io(date.to_string(), err.to_string())
+        };
+        writer.serialize(tick).map_err(|err| io_err(&err))?;
+        writer.flush().map_err(|err| io_err(&err))
+    }
+
+    fn open_log(&self, date: NaiveDate) -> Result<csv::Writer<File>, RecordingDataFeedError> {
+        let path = self.dir.join(format!("{}_{}.csv", self.name, date));
+        let io_err = |err: &dyn std::fmt::Display| {
+            RecordingDataFeedError::Io(path.display().to_string(), err.to_string())
+        };
+        let is_new = !path.exists();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|err| io_err(&err))?;
+        // Only write the header once, when the file is created
+        Ok(csv::WriterBuilder::new()
+            .has_headers(is_new)
+            .from_writer(file))
+    }
+}
+
+#[async_trait]
+impl DataFeed for RecordingDataFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        let event = self.inner.next_tick().await?;
+        // Quotes are recorded too, consumers that only want traded prices skip them on replay
+        if let Ok(data) = &event
+            && let Err(err) = self.record(data, Utc::now())
+        {
+            // Recording is best effort. Do not stop the strategy for it.
+            error!("Failed to record tick of {}: {}", self.name, err);
+        }
+        Some(event)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum RecordingDataFeedError {
+    #[error("Failed to write recording ({0}): {1}")]
+    Io(String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_feed::replay_data_feed::ReplayDataFeed;
+    use chrono::TimeZone;
+    use std::collections::VecDeque;
+
+    struct VecFeed(VecDeque<MarketData>);
+
+    #[async_trait]
+    impl DataFeed for VecFeed {
+        fn name(&self) -> &str {
+            "vec"
+        }
+        async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+            self.0.pop_front().map(Ok)
+        }
+    }
+
+    struct NoFeed;
+
+    #[async_trait]
+    impl DataFeed for NoFeed {
+        fn name(&self) -> &str {
+            "none"
+        }
+        async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_recording_rotates_daily() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut feed = RecordingDataFeed::new("rec".into(), Box::new(NoFeed), dir.path()).unwrap();
+        let data = MarketData::tick("AAPL".into(), 100.0, 0.0, Utc::now());
+        let day_1 = Utc.with_ymd_and_hms(2025, 1, 2, 23, 59, 0).unwrap();
+        let day_2 = Utc.with_ymd_and_hms(2025, 1, 3, 0, 1, 0).unwrap();
+        feed.record(&data, day_1).unwrap();
+        feed.record(&data, day_1).unwrap();
+        feed.record(&data, day_2).unwrap();
+
+        let read = |file: &str| {
+            let path = dir.path().join(file);
+            let mut reader = csv::Reader::from_path(path).unwrap();
+            reader
+                .deserialize()
+                .collect::<Result<Vec<RecordedTick>, _>>()
+                .unwrap()
+        };
+        let ticks = read("rec_2025-01-02.csv");
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks[0].timestamp, "2025-01-02T23:59:00.000000Z");
+        assert_eq!(read("rec_2025-01-03.csv").len(), 1);
+    }
+
+    #[test]
+    fn test_recording_keeps_the_market_data_of_bars() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut feed = RecordingDataFeed::new("rec".into(), Box::new(NoFeed), dir.path()).unwrap();
+        let bar_start = Utc.with_ymd_and_hms(2025, 1, 2, 9, 30, 0).unwrap();
+        let bar = MarketData {
+            open: 99.0,
+            high: 102.0,
+            low: 98.5,
+            kind: MarketDataKind::Bar,
+            ..MarketData::tick("AAPL".into(), 101.0, 1500.0, bar_start)
+        };
+        let received_at = Utc.with_ymd_and_hms(2025, 1, 2, 9, 35, 0).unwrap();
+        feed.record(&bar, received_at).unwrap();
+
+        let mut reader = csv::Reader::from_path(dir.path().join("rec_2025-01-02.csv")).unwrap();
+        let tick: RecordedTick = reader.deserialize().next().unwrap().unwrap();
+        assert_eq!(tick.into_market_data().unwrap(), (received_at, bar));
+    }
+
+    #[tokio::test]
+    async fn test_recorded_quotes_replay_as_quotes() {
+        let dir = tempfile::tempdir().unwrap();
+        let quote = Quote {
+            bid: 99.5,
+            ask: 100.5,
+            bid_size: 200.0,
+            ask_size: 300.0,
+            ..Default::default()
+        };
+        // Recordings keep times to the microsecond, so `Utc::now()` would not round trip
+        let now = Utc.with_ymd_and_hms(2025, 1, 2, 10, 0, 0).unwrap();
+        let ticks = vec![
+            MarketData::quote("AAPL".into(), quote, now).unwrap(),
+            MarketData::tick("AAPL".into(), 100.25, 10.0, now),
+        ];
+        let mut feed = RecordingDataFeed::new(
+            "rec".into(),
+            Box::new(VecFeed(ticks.clone().into())),
+            dir.path(),
+        )
+        .unwrap();
+        while feed.next_tick().await.is_some() {}
+
+        let mut replay = ReplayDataFeed::new("replay".into(), dir.path(), None, None).unwrap();
+        let mut replayed = Vec::new();
+        while let Some(Ok(data)) = replay.next_tick().await {
+            replayed.push(data);
+        }
+        assert!(replayed[0].is_quote());
+        assert_eq!(replayed, ticks);
+    }
+
+    #[test]
+    fn test_recording_appends_to_existing_log() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = MarketData::tick("AAPL".into(), 100.0, 0.0, Utc::now());
+        let now = Utc.with_ymd_and_hms(2025, 1, 2, 10, 0, 0).unwrap();
+        for _ in 0..2 {
+            // A new feed instance, e.g. after a restart of the bot
+            let mut feed =
+                RecordingDataFeed::new("rec".into(), Box::new(NoFeed), dir.path()).unwrap();
+            feed.record(&data, now).unwrap();
+        }
+        let mut reader = csv::Reader::from_path(dir.path().join("rec_2025-01-02.csv")).unwrap();
+        let ticks: Vec<RecordedTick> = reader.deserialize().map(|t| t.unwrap()).collect();
+        assert_eq!(ticks.len(), 2);
+    }
+}
@@ -0,0 +1,174 @@
+use crate::data_feed::{
+    DataFeed, DataFeedError, MarketData,
+    recording_data_feed::RecordedTick,
+    replay_pacer::{ReplayControl, ReplayPacer},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::{collections::VecDeque, fs, path::Path};
+use thiserror::Error;
+
+/// Plays back a recording of a [`super::recording_data_feed::RecordingDataFeed`].
+///
+/// `path` is either a single recording file or a directory. Of a directory, the daily files
+/// of the recording feed named `recording` are replayed, or all `.csv` files without one. The
+/// ticks of all files are merged in time order.
+/// Ticks are replayed in the order they were received, with their original market data.
+/// With a `speed` the original time between ticks is reproduced, divided by `speed`
+/// (1.0 = real time, 10.0 = ten times faster). Without one, ticks are replayed at once.
+pub struct ReplayDataFeed {
+    name: String,
+    ticks: VecDeque<(DateTime<Utc>, MarketData)>,
+    pacer: ReplayPacer,
+}
+
+impl ReplayDataFeed {
+    pub fn new(
+        name: String,
+        path: impl AsRef<Path>,
+        recording: Option<&str>,
+        speed: Option<f64>,
+    ) -> Result<Self, ReplayDataFeedError> {
+        let path = path.as_ref();
+        let files = if path.is_dir() {
+            let mut files: Vec<_> = fs::read_dir(path)
+                .map_err(|err| {
+                    ReplayDataFeedError::Read(path.display().to_string(), err.to_string())
+                })?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "csv"))
+                .filter(|path| recording.is_none_or(|recording| is_recorded_by(path, recording)))
+                .collect();
+            files.sort();
+            files
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let mut ticks = Vec::new();
+        for file in files {
+            let read_err = |err: &dyn std::fmt::Display| {
+                ReplayDataFeedError::Read(file.display().to_string(), err.to_string())
+            };
+            let mut reader = csv::Reader::from_path(&file).map_err(|err| read_err(&err))?;
+            for tick in reader.deserialize::<RecordedTick>() {
+                let tick = tick.map_err(|err| read_err(&err))?;
+                ticks.push(tick.into_market_data().map_err(|err| read_err(&err))?);
+            }
+        }
+        // Stable, so ticks of the same time keep the order of their files
+        ticks.sort_by_key(|(timestamp, _)| *timestamp);
+        Ok(Self {
+            name,
+            ticks: ticks.into(),
+            pacer: ReplayPacer::new(ReplayControl::new(speed, false)),
+        })
+    }
+
+    /// Replace the pacing given by `speed`, e.g. by one that is shared with other feeds
+    pub fn with_pacer(mut self, pacer: ReplayPacer) -> Self {
+        self.pacer = pacer;
+        self
+    }
+
+    pub fn control(&self) -> ReplayControl {
+        self.pacer.control()
+    }
+}
+
+/// Whether `path` is a daily file (`<recording>_<date>.csv`) of the feed named `recording`
+fn is_recorded_by(path: &Path, recording: &str) -> bool {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| stem.rsplit_once('_'))
+        .is_some_and(|(name, _date)| name == recording)
+}
+
+#[async_trait]
+impl DataFeed for ReplayDataFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        let (timestamp, data) = self.ticks.pop_front()?;
+        self.pacer.wait(timestamp).await;
+        Some(Ok(data))
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayDataFeedError {
+    #[error("Failed to read recording ({0}): {1}")]
+    Read(String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn write_recording(dir: &Path, file: &str, rows: &[(&str, f64)]) {
+        let mut file = fs::File::create(dir.join(file)).unwrap();
+        writeln!(file, "timestamp,symbol,price").unwrap();
+        for (timestamp, price) in rows {
+            writeln!(file, "{timestamp},AAPL,{price}").unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_reads_directory_in_date_order() {
+        let dir = tempfile::tempdir().unwrap();
+        write_recording(
+            dir.path(),
+            "rec_2025-01-03.csv",
+            &[("2025-01-03T09:30:00.000000Z", 3.0)],
+        );
+        write_recording(
+            dir.path(),
+            "rec_2025-01-02.csv",
+            &[
+                ("2025-01-02T09:30:00.000000Z", 1.0),
+                ("2025-01-02T09:30:01.000000Z", 2.0),
+            ],
+        );
+
+        let mut feed = ReplayDataFeed::new("replay".into(), dir.path(), None, None).unwrap();
+        let mut prices = Vec::new();
+        while let Some(Ok(data)) = feed.next_tick().await {
+            prices.push(data.price);
+        }
+        assert_eq!(prices, vec![1.0, 2.0, 3.0]);
+    }
+
+    #[tokio::test]
+    async fn test_replay_reads_only_the_given_recording() {
+        let dir = tempfile::tempdir().unwrap();
+        write_recording(
+            dir.path(),
+            "ticks_2025-01-02.csv",
+            &[
+                ("2025-01-02T09:30:00.000000Z", 1.0),
+                ("2025-01-02T09:30:02.000000Z", 3.0),
+            ],
+        );
+        write_recording(
+            dir.path(),
+            "other_ticks_2025-01-02.csv",
+            &[("2025-01-02T09:30:01.000000Z", 2.0)],
+        );
+        let path = dir.path();
+        let replay = |recording| async move {
+            let mut feed = ReplayDataFeed::new("replay".into(), path, recording, None).unwrap();
+            let mut prices = Vec::new();
+            while let Some(Ok(data)) = feed.next_tick().await {
+                prices.push(data.price);
+            }
+            prices
+        };
+
+        assert_eq!(replay(Some("ticks")).await, vec![1.0, 3.0]);
+        assert_eq!(replay(Some("other_ticks")).await, vec![2.0]);
+        // Without a recording, the files are merged in time order
+        assert_eq!(replay(None).await, vec![1.0, 2.0, 3.0]);
+    }
+}
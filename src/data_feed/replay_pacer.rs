@@ -0,0 +1,225 @@
+use chrono::{DateTime, Utc};
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::watch, time::Instant};
+
+/// Slower speeds are raised to this one
+const MIN_REPLAY_SPEED: f64 = 0.001;
+
+/// Remote control of one or more paced replays, e.g. for a UI
+#[derive(Debug, Clone)]
+pub struct ReplayControl {
+    state: Arc<watch::Sender<ControlState>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ControlState {
+    /// 1.0 = real time, 10.0 = ten times faster. `None` replays as fast as possible.
+    speed: Option<f64>,
+    paused: bool,
+    /// Number of `step` calls so far
+    steps: u64,
+}
+
+impl ReplayControl {
+    pub fn new(speed: Option<f64>, paused: bool) -> Self {
+        let (state, _) = watch::channel(ControlState {
+            speed: normalize_speed(speed),
+            paused,
+            steps: 0,
+        });
+        Self {
+            state: Arc::new(state),
+        }
+    }
+
+    pub fn pause(&self) {
+        self.state.send_modify(|state| state.paused = true);
+    }
+
+    pub fn resume(&self) {
+        self.state.send_modify(|state| state.paused = false);
+    }
+
+    /// Release a single event while paused
+    pub fn step(&self) {
+        self.state.send_modify(|state| state.steps += 1);
+    }
+
+    /// Change the speed. `None` (or a speed of 0) replays as fast as possible.
+    pub fn set_speed(&self, speed: Option<f64>) {
+        self.state
+            .send_modify(|state| state.speed = normalize_speed(speed));
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.borrow().paused
+    }
+
+    pub fn speed(&self) -> Option<f64> {
+        self.state.borrow().speed
+    }
+}
+
+/// Speeds that are not positive (or not a number) replay as fast as possible, tiny ones are
+/// raised to [`MIN_REPLAY_SPEED`]
+fn normalize_speed(speed: Option<f64>) -> Option<f64> {
+    speed
+        .filter(|speed| *speed > 0.0)
+        .map(|speed| speed.max(MIN_REPLAY_SPEED))
+}
+
+/// Delays the events of a historical feed according to their timestamps, following a
+/// `ReplayControl`
+#[derive(Debug)]
+pub struct ReplayPacer {
+    control: ReplayControl,
+    changes: watch::Receiver<ControlState>,
+    last_timestamp: Option<DateTime<Utc>>,
+    /// Steps that were already used (or issued while not paused)
+    steps_used: u64,
+}
+
+impl ReplayPacer {
+    pub fn new(control: ReplayControl) -> Self {
+        let changes = control.state.subscribe();
+        let steps_used = changes.borrow().steps;
+        Self {
+            control,
+            changes,
+            last_timestamp: None,
+            steps_used,
+        }
+    }
+
+    pub fn control(&self) -> ReplayControl {
+        self.control.clone()
+    }
+
+    /// Wait until the event at `timestamp` is due
+    pub async fn wait(&mut self, timestamp: DateTime<Utc>) {
+        let started = Instant::now();
+        loop {
+            let state = *self.changes.borrow_and_update();
+            if state.paused {
+                if state.steps > self.steps_used {
+                    self.steps_used += 1;
+                    break;
+                }
+            } else {
+                self.steps_used = state.steps;
+                let Some(delay) = delay(state.speed, self.last_timestamp, timestamp) else {
+                    break;
+                };
+                let deadline = started + delay;
+                if deadline <= Instant::now() {
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep_until(deadline) => break,
+                    // Pausing or changing the speed while waiting
+                    _ = self.changes.changed() => continue,
+                }
+            }
+            // The control lives as long as this pacer, so this can not fail
+            let _ = self.changes.changed().await;
+        }
+        self.last_timestamp = Some(timestamp);
+    }
+}
+
+/// How long to wait between events at `last_timestamp` and `timestamp`
+fn delay(
+    speed: Option<f64>,
+    last_timestamp: Option<DateTime<Utc>>,
+    timestamp: DateTime<Utc>,
+) -> Option<Duration> {
+    let speed = speed?;
+    let elapsed = (timestamp - last_timestamp?).to_std().ok()?;
+    // Not `div_f64`, which panics on overflow
+    Duration::try_from_secs_f64(elapsed.as_secs_f64() / speed).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timestamp(seconds: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap()
+    }
+
+    #[test]
+    fn test_delay_is_scaled_by_speed() {
+        assert_eq!(delay(Some(4.0), None, timestamp(0)), None);
+        assert_eq!(delay(None, Some(timestamp(0)), timestamp(2)), None);
+        assert_eq!(
+            delay(Some(4.0), Some(timestamp(0)), timestamp(2)),
+            Some(Duration::from_millis(500))
+        );
+        // Out of order events are not delayed
+        assert_eq!(delay(Some(1.0), Some(timestamp(2)), timestamp(0)), None);
+        // Huge gaps at tiny speeds do not overflow
+        assert!(
+            delay(
+                Some(f64::MIN_POSITIVE),
+                Some(timestamp(0)),
+                timestamp(1 << 40)
+            )
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_speeds_are_normalized() {
+        let control = ReplayControl::new(Some(1e-300), false);
+        assert_eq!(control.speed(), Some(MIN_REPLAY_SPEED));
+        control.set_speed(Some(f64::NAN));
+        assert_eq!(control.speed(), None);
+        control.set_speed(Some(-2.0));
+        assert_eq!(control.speed(), None);
+        control.set_speed(Some(2.0));
+        assert_eq!(control.speed(), Some(2.0));
+    }
+
+    #[tokio::test]
+    async fn test_paused_pacer_releases_one_event_per_step() {
+        let control = ReplayControl::new(None, true);
+        let mut pacer = ReplayPacer::new(control.clone());
+        let short = Duration::from_millis(20);
+
+        assert!(
+            tokio::time::timeout(short, pacer.wait(timestamp(0)))
+                .await
+                .is_err()
+        );
+        control.step();
+        tokio::time::timeout(short, pacer.wait(timestamp(1)))
+            .await
+            .unwrap();
+        assert!(
+            tokio::time::timeout(short, pacer.wait(timestamp(2)))
+                .await
+                .is_err()
+        );
+
+        control.resume();
+        tokio::time::timeout(short, pacer.wait(timestamp(3)))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_pacer_follows_speed_changes() {
+        // One day between the events would not pass in this test at real speed
+        let control = ReplayControl::new(Some(1.0), false);
+        let mut pacer = ReplayPacer::new(control.clone());
+        pacer.wait(timestamp(0)).await;
+
+        let waiting = tokio::spawn(async move { pacer.wait(timestamp(86_400)).await });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        control.set_speed(None);
+        tokio::time::timeout(Duration::from_secs(1), waiting)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -0,0 +1,398 @@
+use crate::data_feed::{DataFeed, DataFeedError, MarketData, MarketDataKind};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use std::{collections::HashMap, time::Duration};
+use thiserror::Error;
+use tokio::sync::mpsc;
+
+/// How the ticks (or smaller bars) of the wrapped feed are grouped into bars
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BarSpec {
+    /// Bars of a fixed time period in seconds (1s..1D), aligned to the UTC epoch
+    Time(i64),
+    /// A bar every given number of ticks
+    Tick(u64),
+    /// A bar every time the traded volume reaches the given threshold
+    Volume(f64),
+    /// A bar every time the traded value (price × volume) reaches the given threshold
+    Dollar(f64),
+}
+
+impl BarSpec {
+    /// Parse a bar type (`Time`, `Tick`, `Volume` or `Dollar`) and its size.
+    /// Time bar sizes are given as e.g. "1s", "5m", "1h" or "1D".
+    pub fn parse(bar_type: &str, size: &str) -> Result<Self, ResamplingDataFeedError> {
+        let invalid = || ResamplingDataFeedError::InvalidBarSpec(format!("{bar_type} {size}"));
+        let spec = match bar_type {
+            "Time" => {
+                let mut chars = size.trim().chars();
+                let unit = chars.next_back().ok_or_else(invalid)?;
+                let value: i64 = chars.as_str().trim().parse().map_err(|_| invalid())?;
+                let unit_seconds = match unit {
+                    's' => 1,
+                    'm' => 60,
+                    'h' => 60 * 60,
+                    'D' | 'd' => 24 * 60 * 60,
+                    _ => return Err(invalid()),
+                };
+                Self::Time(value.checked_mul(unit_seconds).ok_or_else(invalid)?)
+            }
+            "Tick" => Self::Tick(size.trim().parse().map_err(|_| invalid())?),
+            "Volume" => Self::Volume(size.trim().parse().map_err(|_| invalid())?),
+            "Dollar" => Self::Dollar(size.trim().parse().map_err(|_| invalid())?),
+            _ => return Err(invalid()),
+        };
+        let valid = match spec {
+            Self::Time(seconds) => (1..=24 * 60 * 60).contains(&seconds),
+            Self::Tick(ticks) => ticks > 0,
+            Self::Volume(threshold) | Self::Dollar(threshold) => threshold > 0.0,
+        };
+        if valid { Ok(spec) } else { Err(invalid()) }
+    }
+}
+
+/// A bar that is still being built
+struct PartialBar {
+    bar: MarketData,
+    ticks: u64,
+    value: f64,
+}
+
+/// Groups market data into bars according to a [`BarSpec`], separately per symbol
+pub struct BarAggregator {
+    spec: BarSpec,
+    partial_bars: HashMap<String, PartialBar>,
+}
+
+impl BarAggregator {
+    pub fn new(spec: BarSpec) -> Self {
+        Self {
+            spec,
+            partial_bars: HashMap::new(),
+        }
+    }
+
+    /// Add a trade or bar (quotes are not aggregated). Returns the bar that it completed, if any.
+    pub fn push(&mut self, data: MarketData) -> Option<MarketData> {
+        let mut completed = None;
+        if let BarSpec::Time(period) = self.spec {
+            let bucket_start = bucket_start(data.timestamp, period);
+            // A tick of a later period completes the bar of the current one
+            if self
+                .partial_bars
+                .get(&data.symbol)
+                .is_some_and(|partial| partial.bar.timestamp != bucket_start)
+            {
+                completed = self.partial_bars.remove(&data.symbol).map(|p| p.bar);
+            }
+        }
+
+        let value = data.price * data.volume;
+        match self.partial_bars.get_mut(&data.symbol) {
+            Some(partial) => {
+                partial.bar.high = partial.bar.high.max(data.high);
+                partial.bar.low = partial.bar.low.min(data.low);
+                partial.bar.price = data.price;
+                partial.bar.volume += data.volume;
+                partial.ticks += 1;
+                partial.value += value;
+            }
+            None => {
+                let mut bar = data;
+                bar.kind = MarketDataKind::Bar;
+                if let BarSpec::Time(period) = self.spec {
+                    bar.timestamp = bucket_start(bar.timestamp, period);
+                }
+                self.partial_bars.insert(
+                    bar.symbol.clone(),
+                    PartialBar {
+                        bar,
+                        ticks: 1,
+                        value,
+                    },
+                );
+            }
+        }
+
+        if completed.is_none() {
+            completed = self.take_if_full();
+        }
+        completed
+    }
+
+    /// Returns one of the bars that are still being built, e.g. once the wrapped feed ended
+    pub fn flush(&mut self) -> Option<MarketData> {
+        let symbol = self.partial_bars.keys().next()?.clone();
+        self.partial_bars.remove(&symbol).map(|p| p.bar)
+    }
+
+    /// Returns one of the time bars whose period ended at `now`, if any
+    pub fn take_closed(&mut self, now: DateTime<Utc>) -> Option<MarketData> {
+        let BarSpec::Time(period) = self.spec else {
+            return None;
+        };
+        let (symbol, _) = self
+            .partial_bars
+            .iter()
+            .find(|(_, partial)| partial.bar.timestamp + TimeDelta::seconds(period) <= now)?;
+        let symbol = symbol.clone();
+        self.partial_bars.remove(&symbol).map(|p| p.bar)
+    }
+
+    /// The earliest end of the periods of the time bars that are still being built
+    pub fn next_close(&self) -> Option<DateTime<Utc>> {
+        let BarSpec::Time(period) = self.spec else {
+            return None;
+        };
+        self.partial_bars
+            .values()
+            .map(|partial| partial.bar.timestamp + TimeDelta::seconds(period))
+            .min()
+    }
+
+    fn take_if_full(&mut self) -> Option<MarketData> {
+        let (symbol, _) = self
+            .partial_bars
+            .iter()
+            .find(|(_, partial)| match self.spec {
+                BarSpec::Time(_) => false,
+                BarSpec::Tick(ticks) => partial.ticks >= ticks,
+                BarSpec::Volume(threshold) => partial.bar.volume >= threshold,
+                BarSpec::Dollar(threshold) => partial.value >= threshold,
+            })?;
+        let symbol = symbol.clone();
+        self.partial_bars.remove(&symbol).map(|p| p.bar)
+    }
+}
+
+fn bucket_start(timestamp: DateTime<Utc>, period: i64) -> DateTime<Utc> {
+    let seconds = timestamp.timestamp();
+    DateTime::from_timestamp(seconds - seconds.rem_euclid(period), 0).unwrap_or(timestamp)
+}
+
+/// Wraps another feed and turns its ticks (or smaller bars) into bars.
+///
+/// By default a time bar is emitted with the first tick of a later period, which suits
+/// historical data. Live feeds should set a close delay, so that bars are also emitted once
+/// their period is over on the wall clock, even if no tick follows.
+pub struct ResamplingDataFeed {
+    name: String,
+    inner: Inner,
+    aggregator: BarAggregator,
+    close_delay: Option<TimeDelta>,
+    inner_ended: bool,
+}
+
+enum Inner {
+    Feed(Box<dyn DataFeed>),
+    /// The events of the feed, read by a task of their own, so that waiting for them can be
+    /// interrupted by the close of a bar without losing any
+    Forwarded(mpsc::UnboundedReceiver<Result<MarketData, DataFeedError>>),
+}
+
+impl ResamplingDataFeed {
+    pub fn new(name: String, inner: Box<dyn DataFeed>, spec: BarSpec) -> Self {
+        Self {
+            name,
+            inner: Inner::Feed(inner),
+            aggregator: BarAggregator::new(spec),
+            close_delay: None,
+            inner_ended: false,
+        }
+    }
+
+    /// Emit time bars `delay` after the end of their period on the wall clock, instead of
+    /// waiting for a tick of the next period. The delay leaves time for late ticks. Ticks
+    /// that arrive after their bar was emitted start a new bar of the same period.
+    /// Only meant for live feeds, whose ticks are timestamped with the current time.
+    pub fn with_close_delay(mut self, delay: Duration) -> Self {
+        self.close_delay = Some(TimeDelta::from_std(delay).unwrap_or(TimeDelta::MAX));
+        if let Inner::Feed(feed) = self.inner {
+            self.inner = Inner::Forwarded(forward(feed));
+        }
+        self
+    }
+
+    /// Waits for the next event of the wrapped feed, or returns `None` once the period of a
+    /// time bar ended (with the close delay)
+    async fn next_event(&mut self) -> Option<Option<Result<MarketData, DataFeedError>>> {
+        let close = self
+            .close_delay
+            .and_then(|delay| Some(self.aggregator.next_close()? + delay));
+        match (&mut self.inner, close) {
+            (Inner::Feed(feed), _) => Some(feed.next_tick().await),
+            (Inner::Forwarded(rx), None) => Some(rx.recv().await),
+            (Inner::Forwarded(rx), Some(close)) => {
+                let wait = (close - Utc::now()).to_std().unwrap_or_default();
+                tokio::select! {
+                    event = rx.recv() => Some(event),
+                    _ = tokio::time::sleep(wait) => None,
+                }
+            }
+        }
+    }
+}
+
+/// Reads the events of `feed` on a task of their own
+fn forward(
+    mut feed: Box<dyn DataFeed>,
+) -> mpsc::UnboundedReceiver<Result<MarketData, DataFeedError>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Some(event) = feed.next_tick().await {
+            if tx.send(event).is_err() {
+                // The resampling feed was dropped
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[async_trait]
+impl DataFeed for ResamplingDataFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        while !self.inner_ended {
+            if let Some(delay) = self.close_delay
+                && let Some(bar) = self.aggregator.take_closed(Utc::now() - delay)
+            {
+                return Some(Ok(bar));
+            }
+            let Some(event) = self.next_event().await else {
+                // The period of a bar ended
+                continue;
+            };
+            match event {
+                // Quotes are not trades. Pass them through for consumers interested in the book.
+                Some(Ok(data)) if data.is_quote() => return Some(Ok(data)),
+                Some(Ok(data)) => {
+                    if let Some(bar) = self.aggregator.push(data) {
+                        return Some(Ok(bar));
+                    }
+                }
+                Some(Err(err)) => return Some(Err(err)),
+                None => self.inner_ended = true,
+            }
+        }
+        // Emit the incomplete last bars once the wrapped feed ended
+        self.aggregator.flush().map(Ok)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum ResamplingDataFeedError {
+    #[error("Invalid bar specification: {0}")]
+    InvalidBarSpec(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tick(seconds: i64, price: f64, volume: f64) -> MarketData {
+        let timestamp = DateTime::from_timestamp(1_700_000_000 + seconds, 0).unwrap();
+        MarketData::tick("AAPL".into(), price, volume, timestamp)
+    }
+
+    #[test]
+    fn test_bar_spec_parse() {
+        assert_eq!(BarSpec::parse("Time", "1s").unwrap(), BarSpec::Time(1));
+        assert_eq!(BarSpec::parse("Time", "5m").unwrap(), BarSpec::Time(300));
+        assert_eq!(BarSpec::parse("Time", "1D").unwrap(), BarSpec::Time(86400));
+        assert_eq!(BarSpec::parse("Tick", "100").unwrap(), BarSpec::Tick(100));
+        assert_eq!(
+            BarSpec::parse("Dollar", "1000000").unwrap(),
+            BarSpec::Dollar(1_000_000.0)
+        );
+        assert!(BarSpec::parse("Time", "2D").is_err());
+        assert!(BarSpec::parse("Time", "5x").is_err());
+        // Multibyte units and empty or huge sizes are rejected, not panicked on
+        assert!(BarSpec::parse("Time", "5µ").is_err());
+        assert!(BarSpec::parse("Time", "").is_err());
+        assert!(BarSpec::parse("Time", "9223372036854775807D").is_err());
+        assert!(BarSpec::parse("Tick", "0").is_err());
+        assert!(BarSpec::parse("Renko", "1").is_err());
+    }
+
+    #[test]
+    fn test_time_bars() {
+        // 1_700_000_000 is 20 seconds into a minute
+        let mut aggregator = BarAggregator::new(BarSpec::Time(60));
+        assert!(aggregator.push(tick(0, 10.0, 1.0)).is_none());
+        assert!(aggregator.push(tick(10, 12.0, 1.0)).is_none());
+        assert!(aggregator.push(tick(20, 9.0, 1.0)).is_none());
+        let bar = aggregator.push(tick(40, 11.0, 1.0)).unwrap();
+
+        assert_eq!(bar.timestamp.timestamp(), 1_700_000_000 - 20);
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.price, bar.volume),
+            (10.0, 12.0, 9.0, 9.0, 3.0)
+        );
+        let last = aggregator.flush().unwrap();
+        assert_eq!(last.timestamp.timestamp(), 1_700_000_000 + 40);
+        assert_eq!(last.price, 11.0);
+        assert!(aggregator.flush().is_none());
+    }
+
+    #[test]
+    fn test_tick_volume_and_dollar_bars() {
+        let mut aggregator = BarAggregator::new(BarSpec::Tick(2));
+        assert!(aggregator.push(tick(0, 10.0, 1.0)).is_none());
+        assert_eq!(aggregator.push(tick(1, 11.0, 1.0)).unwrap().price, 11.0);
+
+        let mut aggregator = BarAggregator::new(BarSpec::Volume(100.0));
+        assert!(aggregator.push(tick(0, 10.0, 60.0)).is_none());
+        assert_eq!(aggregator.push(tick(1, 11.0, 50.0)).unwrap().volume, 110.0);
+
+        let mut aggregator = BarAggregator::new(BarSpec::Dollar(1000.0));
+        assert!(aggregator.push(tick(0, 10.0, 50.0)).is_none());
+        let bar = aggregator.push(tick(1, 10.0, 50.0)).unwrap();
+        assert_eq!(bar.volume, 100.0);
+    }
+
+    /// A live feed that stays silent after its ticks
+    struct SilentAfter(Vec<MarketData>);
+
+    #[async_trait]
+    impl DataFeed for SilentAfter {
+        fn name(&self) -> &str {
+            "silent"
+        }
+        async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+            match self.0.pop() {
+                Some(data) => Some(Ok(data)),
+                None => std::future::pending().await,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_time_bars_close_on_the_clock_with_a_close_delay() {
+        let now = Utc::now();
+        let inner = SilentAfter(vec![MarketData::tick("AAPL".into(), 10.0, 1.0, now)]);
+        let mut feed = ResamplingDataFeed::new("bars".into(), Box::new(inner), BarSpec::Time(1))
+            .with_close_delay(Duration::from_millis(100));
+
+        let bar = tokio::time::timeout(Duration::from_secs(3), feed.next_tick())
+            .await
+            .expect("the bar is emitted without a tick of the next period")
+            .unwrap()
+            .unwrap();
+        assert_eq!(bar.timestamp, bucket_start(now, 1));
+        assert_eq!(bar.kind, MarketDataKind::Bar);
+        assert!(Utc::now() >= bar.timestamp + TimeDelta::milliseconds(1100));
+    }
+
+    #[test]
+    fn test_bars_are_built_per_symbol() {
+        let mut aggregator = BarAggregator::new(BarSpec::Tick(2));
+        let mut other = tick(0, 50.0, 1.0);
+        other.symbol = "MSFT".into();
+        assert!(aggregator.push(tick(0, 10.0, 1.0)).is_none());
+        assert!(aggregator.push(other).is_none());
+        assert_eq!(aggregator.push(tick(1, 11.0, 1.0)).unwrap().symbol, "AAPL");
+    }
+}
@@ -0,0 +1,406 @@
+use crate::data_feed::{DataFeed, DataFeedError, MarketData, MarketDataKind};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Rates and volatilities are annualized, with years of this many steps of one day
+const TRADING_DAYS_PER_YEAR: f64 = 252.0;
+const SECONDS_PER_DAY: f64 = 86_400.0;
+/// Mean-reverting prices are kept above this floor
+const MIN_PRICE: f64 = 0.01;
+
+/// The stochastic process behind a synthetic price series
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "type")]
+pub enum PriceModel {
+    /// Geometric Brownian motion
+    Gbm { drift: f64, volatility: f64 },
+    /// Ornstein-Uhlenbeck process reverting to `mean` at the speed of `reversion`.
+    /// `volatility` is in price units.
+    OrnsteinUhlenbeck {
+        mean: f64,
+        reversion: f64,
+        volatility: f64,
+    },
+    /// Geometric Brownian motion whose parameters jump between regimes (e.g. bull and bear).
+    /// Every step switches to another, randomly chosen, regime with `switch_probability`.
+    RegimeSwitching {
+        regimes: Vec<Regime>,
+        switch_probability: f64,
+    },
+    /// Merton jump diffusion: geometric Brownian motion plus `jump_intensity` jumps per year,
+    /// with log-normal sizes
+    JumpDiffusion {
+        drift: f64,
+        volatility: f64,
+        jump_intensity: f64,
+        jump_mean: f64,
+        jump_volatility: f64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub struct Regime {
+    pub drift: f64,
+    pub volatility: f64,
+}
+
+/// Configuration of a `SyntheticDataFeed`, as given in the params of the feed
+#[derive(Debug, Clone, Deserialize)]
+pub struct SyntheticDataFeedConfig {
+    pub model: PriceModel,
+    /// The same seed always generates the same series
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(default = "default_initial_price")]
+    pub initial_price: f64,
+    /// Number of bars per symbol
+    #[serde(default = "default_steps")]
+    pub steps: usize,
+    /// Seconds between bars
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: i64,
+    /// Timestamp of the first bar (RFC 3339)
+    #[serde(default = "default_start", deserialize_with = "deserialize_rfc3339")]
+    pub start: DateTime<Utc>,
+}
+
+fn deserialize_rfc3339<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<DateTime<Utc>, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    DateTime::parse_from_rfc3339(&value)
+        .map(|datetime| datetime.with_timezone(&Utc))
+        .map_err(serde::de::Error::custom)
+}
+
+fn default_initial_price() -> f64 {
+    100.0
+}
+
+fn default_steps() -> usize {
+    1000
+}
+
+fn default_interval_secs() -> i64 {
+    SECONDS_PER_DAY as i64
+}
+
+fn default_start() -> DateTime<Utc> {
+    DateTime::from_timestamp(1_577_836_800, 0).unwrap_or_default() // 2020-01-01
+}
+
+/// Generates reproducible price bars, so strategies and sizers can be stress-tested on
+/// scenarios without data files. Each symbol gets its own independent series.
+pub struct SyntheticDataFeed {
+    name: String,
+    generators: Vec<PriceGenerator>,
+    config: SyntheticDataFeedConfig,
+    step: usize,
+    /// The generator of the next bar within the current step
+    next_generator: usize,
+}
+
+impl SyntheticDataFeed {
+    pub fn new(
+        name: String,
+        symbols: Vec<String>,
+        config: SyntheticDataFeedConfig,
+    ) -> Result<Self, SyntheticDataFeedError> {
+        validate(&config)?;
+        let generators = symbols
+            .into_iter()
+            .enumerate()
+            .map(|(index, symbol)| PriceGenerator {
+                symbol,
+                price: config.initial_price,
+                regime: 0,
+                // Different, but still reproducible, series per symbol
+                rng: SplitMix64::new(config.seed.wrapping_add(index as u64)),
+            })
+            .collect();
+        Ok(Self {
+            name,
+            generators,
+            config,
+            step: 0,
+            next_generator: 0,
+        })
+    }
+}
+
+fn validate(config: &SyntheticDataFeedConfig) -> Result<(), SyntheticDataFeedError> {
+    let invalid = |reason: &str| Err(SyntheticDataFeedError::InvalidConfig(reason.to_string()));
+    if config.initial_price <= 0.0 {
+        return invalid("initial_price must be positive");
+    }
+    if config.interval_secs <= 0 {
+        return invalid("interval_secs must be positive");
+    }
+    if let PriceModel::RegimeSwitching { regimes, .. } = &config.model
+        && regimes.is_empty()
+    {
+        return invalid("at least one regime is required");
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl DataFeed for SyntheticDataFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        if self.step >= self.config.steps || self.generators.is_empty() {
+            return None;
+        }
+        let interval = self.config.interval_secs;
+        let timestamp = self.config.start + chrono::Duration::seconds(interval * self.step as i64);
+        let dt = interval as f64 / SECONDS_PER_DAY / TRADING_DAYS_PER_YEAR;
+        let generator = &mut self.generators[self.next_generator];
+        let open = generator.price;
+        let close = generator.next_price(&self.config.model, dt);
+        let bar = MarketData {
+            symbol: generator.symbol.clone(),
+            price: close,
+            timestamp,
+            open,
+            high: open.max(close),
+            low: open.min(close),
+            volume: 0.0,
+            kind: MarketDataKind::Bar,
+        };
+        self.next_generator += 1;
+        if self.next_generator == self.generators.len() {
+            self.next_generator = 0;
+            self.step += 1;
+        }
+        Some(Ok(bar))
+    }
+}
+
+struct PriceGenerator {
+    symbol: String,
+    price: f64,
+    /// The current regime of a `PriceModel::RegimeSwitching`
+    regime: usize,
+    rng: SplitMix64,
+}
+
+impl PriceGenerator {
+    /// Advance the price by `dt` years
+    fn next_price(&mut self, model: &PriceModel, dt: f64) -> f64 {
+        let z = self.rng.next_normal();
+        self.price = match model {
+            PriceModel::Gbm { drift, volatility } => {
+                gbm_step(self.price, *drift, *volatility, dt, z)
+            }
+            PriceModel::OrnsteinUhlenbeck {
+                mean,
+                reversion,
+                volatility,
+            } => {
+                let change = reversion * (mean - self.price) * dt + volatility * dt.sqrt() * z;
+                (self.price + change).max(MIN_PRICE)
+            }
+            PriceModel::RegimeSwitching {
+                regimes,
+                switch_probability,
+            } => {
+                if regimes.len() > 1 && self.rng.next_f64() < *switch_probability {
+                    // Any regime but the current one
+                    let offset = 1 + (self.rng.next_u64() % (regimes.len() as u64 - 1)) as usize;
+                    self.regime = (self.regime + offset) % regimes.len();
+                }
+                let regime = regimes[self.regime];
+                gbm_step(self.price, regime.drift, regime.volatility, dt, z)
+            }
+            PriceModel::JumpDiffusion {
+                drift,
+                volatility,
+                jump_intensity,
+                jump_mean,
+                jump_volatility,
+            } => {
+                let price = gbm_step(self.price, *drift, *volatility, dt, z);
+                // At most one jump per step, which is accurate for small steps
+                if self.rng.next_f64() < jump_intensity * dt {
+                    price * (jump_mean + jump_volatility * self.rng.next_normal()).exp()
+                } else {
+                    price
+                }
+            }
+        };
+        self.price
+    }
+}
+
+fn gbm_step(price: f64, drift: f64, volatility: f64, dt: f64, z: f64) -> f64 {
+    price * ((drift - volatility * volatility / 2.0) * dt + volatility * dt.sqrt() * z).exp()
+}
+
+/// A small PRNG whose output only depends on the seed. General purpose generators do not
+/// guarantee that across versions, which would break reproducible scenarios.
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in [0, 1)
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Standard normal (Box-Muller)
+    fn next_normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64(); // (0, 1], so the log is finite
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SyntheticDataFeedError {
+    #[error("Invalid synthetic data feed config: {0}")]
+    InvalidConfig(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(model: PriceModel, seed: u64, steps: usize) -> SyntheticDataFeedConfig {
+        SyntheticDataFeedConfig {
+            model,
+            seed,
+            initial_price: 100.0,
+            steps,
+            interval_secs: SECONDS_PER_DAY as i64,
+            start: default_start(),
+        }
+    }
+
+    async fn prices(symbols: &[&str], config: SyntheticDataFeedConfig) -> Vec<(String, f64)> {
+        let symbols = symbols.iter().map(|symbol| symbol.to_string()).collect();
+        let mut feed = SyntheticDataFeed::new("synthetic".into(), symbols, config).unwrap();
+        let mut prices = Vec::new();
+        while let Some(Ok(data)) = feed.next_tick().await {
+            prices.push((data.symbol, data.price));
+        }
+        prices
+    }
+
+    const GBM: PriceModel = PriceModel::Gbm {
+        drift: 0.05,
+        volatility: 0.2,
+    };
+
+    #[tokio::test]
+    async fn test_same_seed_generates_same_series() {
+        let first = prices(&["A", "B"], config(GBM, 42, 100)).await;
+        assert_eq!(first.len(), 200);
+        assert_eq!(first, prices(&["A", "B"], config(GBM, 42, 100)).await);
+        assert_ne!(first, prices(&["A", "B"], config(GBM, 43, 100)).await);
+        // Symbols get different series
+        assert_ne!(first[0].1, first[1].1);
+    }
+
+    #[tokio::test]
+    async fn test_gbm_without_volatility_grows_at_drift() {
+        let model = PriceModel::Gbm {
+            drift: 0.1,
+            volatility: 0.0,
+        };
+        let prices = prices(&["A"], config(model, 0, 252)).await;
+        let expected = 100.0 * 0.1f64.exp();
+        assert!((prices.last().unwrap().1 - expected).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_ornstein_uhlenbeck_reverts_to_mean() {
+        let model = PriceModel::OrnsteinUhlenbeck {
+            mean: 50.0,
+            reversion: 20.0,
+            volatility: 1.0,
+        };
+        let prices = prices(&["A"], config(model, 7, 504)).await;
+        let tail = &prices[252..];
+        let average = tail.iter().map(|(_, price)| price).sum::<f64>() / tail.len() as f64;
+        assert!((average - 50.0).abs() < 2.0, "average {average}");
+    }
+
+    #[tokio::test]
+    async fn test_jumps_follow_intensity() {
+        let model = PriceModel::JumpDiffusion {
+            drift: 0.0,
+            volatility: 0.0,
+            jump_intensity: 25.2, // One jump every 10 days on average
+            jump_mean: 0.0,
+            jump_volatility: 0.1,
+        };
+        let prices = prices(&["A"], config(model, 3, 2520)).await;
+        let jumps = prices
+            .windows(2)
+            .filter(|pair| pair[0].1 != pair[1].1)
+            .count();
+        assert!((200..300).contains(&jumps), "{jumps} jumps");
+    }
+
+    #[test]
+    fn test_regime_switching_visits_all_regimes() {
+        let regimes = vec![
+            Regime {
+                drift: 0.3,
+                volatility: 0.1,
+            },
+            Regime {
+                drift: -0.3,
+                volatility: 0.4,
+            },
+        ];
+        let model = PriceModel::RegimeSwitching {
+            regimes,
+            switch_probability: 0.05,
+        };
+        let mut generator = PriceGenerator {
+            symbol: "A".into(),
+            price: 100.0,
+            regime: 0,
+            rng: SplitMix64::new(1),
+        };
+        let mut switches = 0;
+        for _ in 0..1000 {
+            let regime = generator.regime;
+            generator.next_price(&model, 1.0 / 252.0);
+            switches += usize::from(regime != generator.regime);
+        }
+        assert!((25..80).contains(&switches), "{switches} switches");
+    }
+
+    #[test]
+    fn test_config_is_validated() {
+        let mut invalid = config(GBM, 0, 10);
+        invalid.initial_price = 0.0;
+        assert!(SyntheticDataFeed::new("synthetic".into(), vec![], invalid).is_err());
+        let no_regimes = PriceModel::RegimeSwitching {
+            regimes: vec![],
+            switch_probability: 0.1,
+        };
+        assert!(
+            SyntheticDataFeed::new("synthetic".into(), vec![], config(no_regimes, 0, 10)).is_err()
+        );
+    }
+}
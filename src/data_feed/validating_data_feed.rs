@@ -0,0 +1,590 @@
+use crate::{
+    calendar::ExchangeCalendar,
+    data_feed::{DataFeed, DataFeedError, MarketData, MarketDataKind},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, Utc, Weekday};
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+};
+use thiserror::Error;
+use tracing::warn;
+
+const SECONDS_PER_DAY: i64 = 86_400;
+
+/// Gaps of more missing bars are outages: they are reported, but not forward-filled
+const MAX_MISSING_BARS: usize = 1_000;
+
+/// What happens to an event with a data quality issue
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+pub enum IssuePolicy {
+    /// Discard the event
+    Drop,
+    /// Replace the event by the last good price of its symbol. For gaps, the missing bars are
+    /// inserted. Events that are out of order or duplicated are dropped.
+    ForwardFill,
+    /// End the feed with a fatal error
+    Halt,
+    /// Log the issue and pass the event on
+    Warn,
+}
+
+/// Configuration of a `ValidatingDataFeed`, as given in the params of the feed
+#[derive(Debug, Clone, Deserialize)]
+pub struct DataValidationConfig {
+    /// NaN, infinite, zero or negative prices
+    #[serde(default = "default_drop")]
+    pub invalid_price: IssuePolicy,
+    /// Events older than the last one of their symbol
+    #[serde(default = "default_drop")]
+    pub timestamp_regression: IssuePolicy,
+    /// Bars with the same timestamp as the last bar of their symbol
+    #[serde(default = "default_drop")]
+    pub duplicate_bar: IssuePolicy,
+    /// Prices that jump by more than `max_price_change` and do not stay there
+    #[serde(default = "default_warn")]
+    pub outlier: IssuePolicy,
+    /// Relative change (0.2 = 20%) from the last price above which a price is an outlier
+    #[serde(default = "default_max_price_change")]
+    pub max_price_change: f64,
+    /// Missing bars. `Drop` has nothing to drop, so it only ignores gaps.
+    #[serde(default = "default_warn")]
+    pub gap: IssuePolicy,
+    /// The expected seconds between two bars. Gaps are only detected with it. Without a
+    /// calendar (see `ValidatingDataFeed::with_calendar`), only weekends do not count as gaps
+    /// and gaps between bars shorter than a day are not detected, as every night would be one.
+    pub bar_interval_secs: Option<i64>,
+}
+
+impl Default for DataValidationConfig {
+    fn default() -> Self {
+        Self {
+            invalid_price: IssuePolicy::Drop,
+            timestamp_regression: IssuePolicy::Drop,
+            duplicate_bar: IssuePolicy::Drop,
+            outlier: IssuePolicy::Warn,
+            max_price_change: default_max_price_change(),
+            gap: IssuePolicy::Warn,
+            bar_interval_secs: None,
+        }
+    }
+}
+
+fn default_drop() -> IssuePolicy {
+    IssuePolicy::Drop
+}
+
+fn default_warn() -> IssuePolicy {
+    IssuePolicy::Warn
+}
+
+fn default_max_price_change() -> f64 {
+    0.5
+}
+
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum DataQualityIssue {
+    #[error("{0}: invalid price at {1}")]
+    InvalidPrice(String, DateTime<Utc>),
+    #[error("{0}: timestamp {1} is before the last one ({2})")]
+    TimestampRegression(String, DateTime<Utc>, DateTime<Utc>),
+    #[error("{0}: duplicate bar at {1}")]
+    DuplicateBar(String, DateTime<Utc>),
+    #[error("{0}: price {1} at {2} is an outlier (last price {3})")]
+    Outlier(String, f64, DateTime<Utc>, f64),
+    #[error("{0}: {1} bar(s) missing before {2}")]
+    Gap(String, usize, DateTime<Utc>),
+    #[error("{0}: more than {MAX_MISSING_BARS} bars missing between {1} and {2}")]
+    Outage(String, DateTime<Utc>, DateTime<Utc>),
+}
+
+/// The last events of a symbol
+struct SymbolHistory {
+    /// The last event that was passed on
+    last: MarketData,
+    /// The last valid price, including outliers
+    last_price: f64,
+}
+
+/// Checks market data for quality issues and applies the configured `IssuePolicy` to them,
+/// separately per symbol
+pub struct DataValidator {
+    config: DataValidationConfig,
+    symbols: HashMap<String, SymbolHistory>,
+    calendar: Option<Arc<ExchangeCalendar>>,
+}
+
+/// How the validator continues after an issue
+enum Action {
+    Keep,
+    Drop,
+    ForwardFill,
+}
+
+impl DataValidator {
+    pub fn new(config: DataValidationConfig) -> Self {
+        Self {
+            config,
+            symbols: HashMap::new(),
+            calendar: None,
+        }
+    }
+
+    /// Only expect bars while the exchange trades
+    pub fn with_calendar(mut self, calendar: Arc<ExchangeCalendar>) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    /// Returns the events to pass on for `data` (none, itself, a replacement or missing bars
+    /// followed by it), or the issue that halts the feed
+    pub fn validate(&mut self, data: MarketData) -> Result<Vec<MarketData>, DataQualityIssue> {
+        let history = self.symbols.get(&data.symbol);
+        let symbol = data.symbol.clone();
+
+        if !has_valid_prices(&data) {
+            let issue = DataQualityIssue::InvalidPrice(symbol, data.timestamp);
+            match apply(self.config.invalid_price, issue)? {
+                Action::Keep => return Ok(vec![data]),
+                Action::Drop => return Ok(Vec::new()),
+                Action::ForwardFill => {
+                    let Some(history) = history else {
+                        return Ok(Vec::new());
+                    };
+                    let filled = flat(&data, history.last.price, data.timestamp);
+                    return Ok(self.accept(filled, None));
+                }
+            }
+        }
+
+        let Some(history) = history else {
+            let price = data.price;
+            return Ok(self.accept(data, Some(price)));
+        };
+        let last = &history.last;
+
+        if data.timestamp < last.timestamp {
+            let issue =
+                DataQualityIssue::TimestampRegression(symbol, data.timestamp, last.timestamp);
+            match apply(self.config.timestamp_regression, issue)? {
+                // An old event must not move the reference of the following checks back
+                Action::Keep => return Ok(vec![data]),
+                Action::Drop | Action::ForwardFill => return Ok(Vec::new()),
+            }
+        }
+
+        if is_bar(&data) && is_bar(last) && data.timestamp == last.timestamp {
+            let issue = DataQualityIssue::DuplicateBar(symbol.clone(), data.timestamp);
+            match apply(self.config.duplicate_bar, issue)? {
+                Action::Keep => {}
+                Action::Drop | Action::ForwardFill => return Ok(Vec::new()),
+            }
+        }
+
+        let mut data = data;
+        let price = data.price;
+        // A spike differs from both the last passed on and the last seen price. A price that
+        // stays at a new level is accepted from its second event on.
+        let is_outlier =
+            |reference: f64| (price / reference - 1.0).abs() > self.config.max_price_change;
+        if is_outlier(last.price) && is_outlier(history.last_price) {
+            let issue =
+                DataQualityIssue::Outlier(symbol.clone(), price, data.timestamp, last.price);
+            match apply(self.config.outlier, issue)? {
+                Action::Keep => {}
+                Action::Drop => {
+                    self.symbols.get_mut(&symbol).unwrap().last_price = price;
+                    return Ok(Vec::new());
+                }
+                Action::ForwardFill => data = flat(&data, last.price, data.timestamp),
+            }
+        }
+
+        let mut events = Vec::new();
+        if let Some(interval) = self.config.bar_interval_secs.filter(|_| is_bar(&data)) {
+            let missing = missing_bar_timestamps(
+                last.timestamp,
+                data.timestamp,
+                interval,
+                self.calendar.as_deref(),
+            );
+            if missing.len() > MAX_MISSING_BARS {
+                let issue = DataQualityIssue::Outage(symbol, last.timestamp, data.timestamp);
+                // Outages are not filled
+                let policy = match self.config.gap {
+                    IssuePolicy::ForwardFill => IssuePolicy::Warn,
+                    policy => policy,
+                };
+                apply_quietly(policy, issue)?;
+            } else if !missing.is_empty() {
+                let issue = DataQualityIssue::Gap(symbol, missing.len(), data.timestamp);
+                // Dropping does not make sense for gaps, it ignores them
+                if let Action::ForwardFill = apply_quietly(self.config.gap, issue)? {
+                    events.extend(
+                        missing
+                            .into_iter()
+                            .map(|timestamp| flat(&data, last.price, timestamp)),
+                    );
+                }
+            }
+        }
+
+        events.extend(self.accept(data, Some(price)));
+        Ok(events)
+    }
+
+    /// Remembers `data` as the last event of its symbol and returns it
+    fn accept(&mut self, data: MarketData, seen_price: Option<f64>) -> Vec<MarketData> {
+        let last_price = seen_price
+            .or_else(|| self.symbols.get(&data.symbol).map(|h| h.last_price))
+            .unwrap_or(data.price);
+        self.symbols.insert(
+            data.symbol.clone(),
+            SymbolHistory {
+                last: data.clone(),
+                last_price,
+            },
+        );
+        vec![data]
+    }
+}
+
+fn apply(policy: IssuePolicy, issue: DataQualityIssue) -> Result<Action, DataQualityIssue> {
+    match policy {
+        IssuePolicy::Drop => {
+            warn!("Dropping market data: {issue}");
+            Ok(Action::Drop)
+        }
+        IssuePolicy::ForwardFill => {
+            warn!("Forward-filling market data: {issue}");
+            Ok(Action::ForwardFill)
+        }
+        IssuePolicy::Halt => Err(issue),
+        IssuePolicy::Warn => {
+            warn!("Market data quality issue: {issue}");
+            Ok(Action::Keep)
+        }
+    }
+}
+
+/// Like `apply`, but `Drop` ignores the issue without logging it
+fn apply_quietly(policy: IssuePolicy, issue: DataQualityIssue) -> Result<Action, DataQualityIssue> {
+    match policy {
+        IssuePolicy::Drop => Ok(Action::Drop),
+        _ => apply(policy, issue),
+    }
+}
+
+fn has_valid_prices(data: &MarketData) -> bool {
+    [data.price, data.open, data.high, data.low]
+        .iter()
+        .all(|price| price.is_finite() && *price > 0.0)
+}
+
+fn is_bar(data: &MarketData) -> bool {
+    data.kind == MarketDataKind::Bar
+}
+
+/// A bar (or trade) of `data`'s symbol and kind at `price`, without volume
+fn flat(data: &MarketData, price: f64, timestamp: DateTime<Utc>) -> MarketData {
+    let kind = if is_bar(data) {
+        MarketDataKind::Bar
+    } else {
+        MarketDataKind::Trade
+    };
+    MarketData {
+        kind,
+        ..MarketData::tick(data.symbol.clone(), price, 0.0, timestamp)
+    }
+}
+
+/// The timestamps of the bars expected between `last` and `next` (both exclusive), at most
+/// one more than `MAX_MISSING_BARS`. Bars of a day or longer are expected on trading days,
+/// shorter ones during the regular session. Without a calendar, bars are expected on weekdays,
+/// and only if they are a day or longer.
+fn missing_bar_timestamps(
+    last: DateTime<Utc>,
+    next: DateTime<Utc>,
+    interval_secs: i64,
+    calendar: Option<&ExchangeCalendar>,
+) -> Vec<DateTime<Utc>> {
+    let mut missing = Vec::new();
+    if interval_secs <= 0 || (calendar.is_none() && interval_secs < SECONDS_PER_DAY) {
+        return missing;
+    }
+    let interval = Duration::seconds(interval_secs);
+    let mut timestamp = last + interval;
+    while timestamp < next && missing.len() <= MAX_MISSING_BARS {
+        let expected = match calendar {
+            Some(calendar) if interval_secs >= SECONDS_PER_DAY => {
+                calendar.is_trading_day(timestamp.date_naive())
+            }
+            Some(calendar) => calendar.is_regular(timestamp),
+            None => !matches!(timestamp.weekday(), Weekday::Sat | Weekday::Sun),
+        };
+        if expected {
+            missing.push(timestamp);
+        }
+        timestamp += interval;
+    }
+    missing
+}
+
+/// Wraps another feed and checks its events with a [`DataValidator`]
+pub struct ValidatingDataFeed {
+    name: String,
+    inner: Box<dyn DataFeed>,
+    validator: DataValidator,
+    pending: VecDeque<MarketData>,
+    halted: bool,
+}
+
+impl ValidatingDataFeed {
+    pub fn new(name: String, inner: Box<dyn DataFeed>, config: DataValidationConfig) -> Self {
+        Self {
+            name,
+            inner,
+            validator: DataValidator::new(config),
+            pending: VecDeque::new(),
+            halted: false,
+        }
+    }
+
+    pub fn with_calendar(mut self, calendar: Arc<ExchangeCalendar>) -> Self {
+        self.validator = self.validator.with_calendar(calendar);
+        self
+    }
+}
+
+#[async_trait]
+impl DataFeed for ValidatingDataFeed {
+    fn name(&self) -> &str {
+        &self.name
+    }
+    async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+        loop {
+            if let Some(data) = self.pending.pop_front() {
+                return Some(Ok(data));
+            }
+            if self.halted {
+                return None;
+            }
+            match self.inner.next_tick().await? {
+                Ok(data) => match self.validator.validate(data) {
+                    Ok(events) => self.pending.extend(events),
+                    Err(issue) => {
+                        self.halted = true;
+                        return Some(Err(DataFeedError::InvalidData(issue.to_string())));
+                    }
+                },
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::Exchange;
+
+    /// 2024-01-01 is a Monday
+    fn bar(day: i64, price: f64) -> MarketData {
+        let timestamp = DateTime::from_timestamp(1_704_067_200 + day * 86_400, 0).unwrap();
+        MarketData {
+            kind: MarketDataKind::Bar,
+            ..MarketData::tick("AAPL".into(), price, 100.0, timestamp)
+        }
+    }
+
+    fn prices(validator: &mut DataValidator, bars: Vec<MarketData>) -> Vec<f64> {
+        bars.into_iter()
+            .flat_map(|bar| validator.validate(bar).unwrap())
+            .map(|data| data.price)
+            .collect()
+    }
+
+    #[test]
+    fn test_invalid_prices_regressions_and_duplicates_are_dropped() {
+        let mut validator = DataValidator::new(DataValidationConfig::default());
+        let bars = vec![
+            bar(0, f64::NAN),
+            bar(0, 10.0),
+            bar(1, 0.0),
+            bar(1, 11.0),
+            bar(1, 11.5),
+            bar(0, 9.0),
+            bar(2, 12.0),
+        ];
+        assert_eq!(prices(&mut validator, bars), vec![10.0, 11.0, 12.0]);
+    }
+
+    #[test]
+    fn test_invalid_prices_are_forward_filled() {
+        let mut validator = DataValidator::new(DataValidationConfig {
+            invalid_price: IssuePolicy::ForwardFill,
+            ..Default::default()
+        });
+        let filled = validator.validate(bar(1, -1.0)).unwrap();
+        assert!(filled.is_empty());
+        validator.validate(bar(2, 10.0)).unwrap();
+        let filled = validator.validate(bar(3, f64::INFINITY)).unwrap();
+        assert_eq!(
+            filled,
+            vec![MarketData {
+                volume: 0.0,
+                ..bar(3, 10.0)
+            }]
+        );
+    }
+
+    #[test]
+    fn test_spikes_are_outliers_but_new_levels_are_not() {
+        let mut validator = DataValidator::new(DataValidationConfig {
+            outlier: IssuePolicy::Drop,
+            max_price_change: 0.2,
+            ..Default::default()
+        });
+        let bars = vec![
+            bar(0, 10.0),
+            bar(1, 100.0),
+            bar(2, 10.5),
+            bar(3, 20.0),
+            bar(4, 21.0),
+        ];
+        assert_eq!(prices(&mut validator, bars), vec![10.0, 10.5, 21.0]);
+    }
+
+    #[test]
+    fn test_gaps_skip_weekends_and_are_filled() {
+        let mut validator = DataValidator::new(DataValidationConfig {
+            gap: IssuePolicy::ForwardFill,
+            bar_interval_secs: Some(86_400),
+            ..Default::default()
+        });
+        // Friday to Monday is no gap, Monday to Thursday misses two bars
+        let bars = vec![bar(4, 10.0), bar(7, 11.0), bar(10, 12.0)];
+        let events: Vec<_> = bars
+            .into_iter()
+            .flat_map(|bar| validator.validate(bar).unwrap())
+            .collect();
+        let days: Vec<_> = events
+            .iter()
+            .map(|data| (data.timestamp.day(), data.price))
+            .collect();
+        assert_eq!(
+            days,
+            vec![(5, 10.0), (8, 11.0), (9, 11.0), (10, 11.0), (11, 12.0)]
+        );
+    }
+
+    #[test]
+    fn test_gaps_follow_the_calendar() {
+        let calendar = ExchangeCalendar::new(Exchange::Nyse);
+        let mut validator = DataValidator::new(DataValidationConfig {
+            bar_interval_secs: Some(86_400),
+            gap: IssuePolicy::ForwardFill,
+            ..Default::default()
+        })
+        .with_calendar(Arc::new(calendar));
+        // 2024-07-04 is a holiday, so Wednesday to Friday misses no bar
+        let bars = vec![bar(184, 10.0), bar(186, 11.0)];
+        assert_eq!(prices(&mut validator, bars), vec![10.0, 11.0]);
+    }
+
+    #[test]
+    fn test_intraday_gaps_follow_the_regular_session() {
+        let minute = |timestamp: &str, price| MarketData {
+            kind: MarketDataKind::Bar,
+            ..MarketData::tick("AAPL".into(), price, 100.0, timestamp.parse().unwrap())
+        };
+        let config = DataValidationConfig {
+            bar_interval_secs: Some(60),
+            gap: IssuePolicy::Halt,
+            ..Default::default()
+        };
+        // The close (20:00 UTC) of Monday 2024-07-01 and the open of Tuesday
+        let overnight = vec![
+            minute("2024-07-01T19:59:00Z", 10.0),
+            minute("2024-07-02T13:30:00Z", 11.0),
+        ];
+        let mut validator = DataValidator::new(config.clone())
+            .with_calendar(Arc::new(ExchangeCalendar::new(Exchange::Nyse)));
+        assert_eq!(prices(&mut validator, overnight.clone()), vec![10.0, 11.0]);
+        // Without a calendar, intraday gaps are not detected
+        let mut validator = DataValidator::new(config.clone());
+        assert_eq!(prices(&mut validator, overnight), vec![10.0, 11.0]);
+
+        // A missing week is an outage, which is not filled bar by bar
+        let mut validator = DataValidator::new(DataValidationConfig {
+            gap: IssuePolicy::ForwardFill,
+            ..config
+        })
+        .with_calendar(Arc::new(ExchangeCalendar::new(Exchange::Nyse)));
+        let outage = vec![
+            minute("2024-07-01T15:00:00Z", 10.0),
+            minute("2024-07-01T15:03:00Z", 10.5),
+            minute("2024-07-09T15:00:00Z", 11.0),
+        ];
+        assert_eq!(
+            prices(&mut validator, outage),
+            vec![10.0, 10.0, 10.0, 10.5, 11.0]
+        );
+    }
+
+    #[test]
+    fn test_outages_are_not_filled() {
+        let config = DataValidationConfig {
+            gap: IssuePolicy::ForwardFill,
+            bar_interval_secs: Some(86_400),
+            ..Default::default()
+        };
+        // More than 1000 weekdays are missing
+        let mut validator = DataValidator::new(config.clone());
+        assert_eq!(
+            prices(&mut validator, vec![bar(0, 10.0), bar(1500, 11.0)]),
+            vec![10.0, 11.0]
+        );
+        // Gaps of intraday bars are not detected, as every night would be one
+        let mut validator = DataValidator::new(DataValidationConfig {
+            gap: IssuePolicy::Halt,
+            bar_interval_secs: Some(60),
+            ..config
+        });
+        assert_eq!(
+            prices(&mut validator, vec![bar(0, 10.0), bar(1, 11.0)]),
+            vec![10.0, 11.0]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_halt_policy_ends_the_feed() {
+        struct VecDataFeed(VecDeque<MarketData>);
+
+        #[async_trait]
+        impl DataFeed for VecDataFeed {
+            fn name(&self) -> &str {
+                "vec"
+            }
+            async fn next_tick(&mut self) -> Option<Result<MarketData, DataFeedError>> {
+                self.0.pop_front().map(Ok)
+            }
+        }
+
+        let inner = VecDataFeed(VecDeque::from([bar(0, 10.0), bar(0, 10.0), bar(1, 11.0)]));
+        let mut feed = ValidatingDataFeed::new(
+            "validated".into(),
+            Box::new(inner),
+            DataValidationConfig {
+                duplicate_bar: IssuePolicy::Halt,
+                ..Default::default()
+            },
+        );
+        assert!(feed.next_tick().await.unwrap().is_ok());
+        let err = feed.next_tick().await.unwrap().unwrap_err();
+        assert!(err.is_fatal());
+        assert!(feed.next_tick().await.is_none());
+    }
+}
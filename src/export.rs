@@ -0,0 +1,202 @@
+use crate::{
+    config::{ExportConfig, HistoricalExportConfig},
+    data_feed::{
+        historical_cache::{CachedBar, HistoricalDataCache},
+        ib_historical_data_feed::{HistoricalBarsRequest, download_historical_bars},
+    },
+    factory::{parse_bar_size, parse_end_datetime},
+    ib_connection::IbConnection,
+};
+use chrono::DateTime;
+use ibapi::market_data::historical::{Duration, WhatToShow};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use thiserror::Error;
+use tracing::{error, info, warn};
+
+/// Download the configured bars from Interactive Brokers and write one CSV file per symbol and
+/// bar size, in the format that [`crate::data_feed::csv_data_feed::CsvDataFeed`] reads.
+pub async fn export_historical_data(config: ExportConfig) -> Result<(), ExportError> {
+    let connection = IbConnection::connect(&config.ib_connection).map_err(|err| {
+        ExportError::Connection(config.ib_connection.name.clone(), err.to_string())
+    })?;
+    let output_dir = PathBuf::from(&config.output_dir);
+    fs::create_dir_all(&output_dir)
+        .map_err(|err| ExportError::Io(config.output_dir.clone(), err.to_string()))?;
+
+    for export in &config.exports {
+        let (end_datetime, duration) = parse_export_range(export)?;
+        for bar_size_name in &export.bar_sizes {
+            let bar_size = parse_bar_size(bar_size_name).map_err(ExportError::InvalidParameter)?;
+            for symbol in &export.symbols {
+                let cache = match &config.cache_dir {
+                    Some(cache_dir) => Some(
+                        HistoricalDataCache::new(cache_dir)
+                            .map_err(|err| ExportError::Io(cache_dir.clone(), err.to_string()))?,
+                    ),
+                    None => None,
+                };
+                let path = output_dir.join(export_file_name(symbol, bar_size_name));
+                info!(
+                    "Exporting {} bars of {} ({} until {}) to {}",
+                    bar_size_name,
+                    symbol,
+                    export.duration,
+                    export.end_datetime,
+                    path.display()
+                );
+                let request = HistoricalBarsRequest {
+                    symbol: symbol.clone(),
+                    interval_end: end_datetime,
+                    duration,
+                    bar_size,
+                    what_to_show: WhatToShow::Trades,
+                    use_rth: true,
+                };
+                let exported = export_symbol(connection.clone(), request, cache, &path).await?;
+                info!("Exported {} bars to {}", exported, path.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn parse_export_range(
+    export: &HistoricalExportConfig,
+) -> Result<(time::OffsetDateTime, Duration), ExportError> {
+    let end_datetime =
+        parse_end_datetime(&export.end_datetime).map_err(ExportError::InvalidParameter)?;
+    let duration = export
+        .duration
+        .parse::<Duration>()
+        .map_err(|err| ExportError::InvalidParameter(err.to_string()))?;
+    Ok((end_datetime, duration))
+}
+
+async fn export_symbol(
+    connection: Arc<IbConnection>,
+    request: HistoricalBarsRequest,
+    cache: Option<HistoricalDataCache>,
+    path: &Path,
+) -> Result<usize, ExportError> {
+    let symbol = request.symbol.clone();
+    let io_err =
+        |err: &dyn std::fmt::Display| ExportError::Io(path.display().to_string(), err.to_string());
+    let mut writer = csv::Writer::from_path(path).map_err(|err| io_err(&err))?;
+    writer
+        .write_record(CSV_HEADER)
+        .map_err(|err| io_err(&err))?;
+    let mut rx = download_historical_bars(connection, request, cache);
+    let mut exported = 0;
+    while let Some(event) = rx.recv().await {
+        match event {
+            Ok(bar) => {
+                writer
+                    .serialize(csv_row(&symbol, &bar))
+                    .map_err(|err| io_err(&err))?;
+                exported += 1;
+            }
+            Err(err) if err.is_fatal() => {
+                error!("Export of {} stopped: {}", symbol, err);
+                break;
+            }
+            Err(err) => warn!("Export of {} may be incomplete: {}", symbol, err),
+        }
+    }
+    writer.flush().map_err(|err| io_err(&err))?;
+    Ok(exported)
+}
+
+const CSV_HEADER: [&str; 7] = [
+    "timestamp",
+    "price",
+    "volume",
+    "symbol",
+    "open",
+    "high",
+    "low",
+];
+
+/// A `timestamp,price,volume,symbol,open,high,low` row with the close price of the bar as
+/// `price` and its UTC start time
+fn csv_row<'a>(symbol: &'a str, bar: &CachedBar) -> (String, f64, f64, &'a str, f64, f64, f64) {
+    let timestamp = DateTime::from_timestamp(bar.timestamp, 0)
+        .map(|datetime| datetime.naive_utc().to_string())
+        .unwrap_or_default();
+    (
+        timestamp, bar.close, bar.volume, symbol, bar.open, bar.high, bar.low,
+    )
+}
+
+fn export_file_name(symbol: &str, bar_size: &str) -> String {
+    let bar_size: String = bar_size
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{symbol}_{bar_size}.csv")
+}
+
+#[derive(Debug, Error)]
+pub enum ExportError {
+    #[error("Failed to create a Interactive Broker connection for `{0}`: `{1}`")]
+    Connection(String, String),
+    #[error("Invalid export parameter: {0}")]
+    InvalidParameter(String),
+    #[error("Failed to write ({0}): {1}")]
+    Io(String, String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_feed::{DataFeed, MarketDataKind, csv_data_feed::CsvDataFeed};
+
+    #[tokio::test]
+    async fn test_exported_rows_are_readable_by_csv_data_feed() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let mut writer = csv::Writer::from_path(file.path()).unwrap();
+        writer.write_record(CSV_HEADER).unwrap();
+        for (i, close) in [100.0, 101.5, 99.25].iter().enumerate() {
+            let bar = CachedBar {
+                timestamp: 1_700_000_000 + i as i64 * 60,
+                open: *close,
+                high: close + 1.0,
+                low: close - 1.0,
+                close: *close,
+                volume: 1500.0,
+            };
+            writer.serialize(csv_row("MSFT", &bar)).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let path = file.path().to_string_lossy().to_string();
+        let mut feed = CsvDataFeed::new("export".into(), path).unwrap();
+        let mut prices = Vec::new();
+        while let Some(Ok(data)) = feed.next_tick().await {
+            assert_eq!(data.kind, MarketDataKind::Bar);
+            assert_eq!(data.symbol, "MSFT");
+            assert_eq!(data.high - data.low, 2.0);
+            prices.push(data.price);
+        }
+        assert_eq!(prices, vec![100.0, 101.5, 99.25]);
+    }
+
+    #[test]
+    fn test_csv_row_format() {
+        let bar = CachedBar {
+            timestamp: 1_672_565_400, // 2023-01-01 09:30:00 UTC
+            open: 1.0,
+            high: 1.0,
+            low: 1.0,
+            close: 1.0,
+            volume: 10.0,
+        };
+        let row = csv_row("AAPL", &bar);
+        assert_eq!(row.0, "2023-01-01 09:30:00");
+        assert_eq!(row.3, "AAPL");
+        assert_eq!(export_file_name("AAPL", "1 day"), "AAPL_1_day.csv");
+    }
+}
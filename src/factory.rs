@@ -1,29 +1,54 @@
 use crate::{
+    backtest::BacktestClock,
     broker::{Broker, dummy::DummyBroker, ib::Ib},
+    calendar::ExchangeCalendar,
     config::{
-        BotConfig, BrokerConfig, BrokerType, DataFeedConfig, DataFeedType, IbConnectionConfig,
-        PositionSizerConfig, PositionSizerType, StrategyType,
+        BotConfig, BrokerConfig, BrokerType, CalendarConfig, DataFeedConfig, DataFeedType,
+        IbConnectionConfig, PositionSizerConfig, PositionSizerType, StrategyType,
+        SubscriptionConfig, TradingHoursConfig,
     },
+    corporate_actions::CorporateActions,
     data_feed::{
-        DataFeed, csv_data_feed::CsvDataFeed, ib_historical_data_feed::IbHistoricalDataFeed,
-        ib_market_data_feed::IbMarketDataFeed,
+        DataFeed,
+        csv_data_feed::CsvDataFeed,
+        historical_cache::HistoricalDataCache,
+        ib_historical_data_feed::{HistoricalBarsRequest, IbHistoricalDataFeed},
+        ib_market_data_feed::{DEFAULT_GENERIC_TICKS, IbMarketDataFeed, IbMarketDataOptions},
+        ib_realtime_bars_feed::IbRealtimeBarsFeed,
+        ib_tick_by_tick_feed::{IbTickByTickFeed, TickByTickType},
+        market_data_hub::{BackpressurePolicy, MarketDataHub},
+        recording_data_feed::RecordingDataFeed,
+        replay_data_feed::ReplayDataFeed,
+        replay_pacer::{ReplayControl, ReplayPacer},
+        resampling_data_feed::{BarSpec, ResamplingDataFeed},
+        synthetic_data_feed::{SyntheticDataFeed, SyntheticDataFeedConfig},
+        validating_data_feed::{DataValidationConfig, ValidatingDataFeed},
     },
+    ib_connection::IbConnection,
     position_sizer::{
         PositionSizer, fixed_sizer::FixedSizer, percent_of_equity_sizer::PercentOfEquitySizer,
     },
     strategy::{
         Strategy,
+        breakout::{BreakoutParams, BreakoutStrategy},
+        dca_rebalance::{DcaRebalanceParams, DcaRebalanceStrategy, Schedule},
+        grid::{GridParams, GridStrategy},
+        momentum_rotation::{MomentumRotationParams, MomentumRotationStrategy},
+        pairs_trading::{PairsTradingParams, PairsTradingStrategy},
         print::PrintStrategy,
-        sma_cross::{
-            DEFAULT_SMA_CROSS_FAST_WINDOW, DEFAULT_SMA_CROSS_SLOW_WINDOW, SmaCrossStrategy,
-        },
+        rsi_mean_reversion::{RsiMeanReversionParams, RsiMeanReversionStrategy},
+        sma_cross::{SmaCrossParams, SmaCrossStrategy},
+        trading_hours::TradingHours,
     },
 };
-use config::Value;
+use chrono::{NaiveDate, NaiveTime};
+use config::{Map, Value};
 use ibapi::{
-    Client,
     market_data::MarketDataType as IbMarketDataType,
-    market_data::historical::{BarSize, Duration, ToDuration},
+    market_data::historical::{
+        BarSize, Duration, ToDuration, WhatToShow as IbHistoricalWhatToShow,
+    },
+    market_data::realtime::WhatToShow as IbRealtimeWhatToShow,
 };
 use serde::Deserialize;
 use std::{collections::HashMap, sync::Arc};
@@ -31,33 +56,61 @@ use thiserror::Error;
 use time::{OffsetDateTime, PrimitiveDateTime, macros::format_description};
 use tracing::{debug, error, warn};
 
+/// Everything `build_bot` builds from a config that the caller needs at runtime
+pub struct Bot {
+    pub strategies: Vec<Box<dyn Strategy>>,
+    /// Controls of the paced (replayed) data feeds by feed name
+    pub replay_controls: HashMap<String, ReplayControl>,
+}
+
 pub async fn build_strategies(
     bot_config: BotConfig,
 ) -> Result<Vec<Box<dyn Strategy>>, FactoryError> {
+    Ok(build_bot(bot_config).await?.strategies)
+}
+
+pub async fn build_bot(bot_config: BotConfig) -> Result<Bot, FactoryError> {
     let ib_connections = build_connections(bot_config.ib_connections)?;
     // TODO: Discuss if this is the best design (via FillListener and a mpsc) for the update of portfolio
     // TODO: Pass an ib_connection or config init data (maybe based on broker type?) to portfolio constructor
     // let fill_listener = FillListener::new(fill_rx, portfolio.clone());
     // fill_listener.start().await;
-    let brokers = build_brokers(bot_config.brokers, &ib_connections)?;
-    let mut data_feeds = build_data_feeds(bot_config.data_feeds, &ib_connections)?;
+    let calendars = build_calendars(bot_config.calendars)?;
+    let brokers = build_brokers(bot_config.brokers, &ib_connections, &calendars)?;
+    let mut data_feeds = build_data_feeds(
+        bot_config.data_feeds,
+        &ib_connections,
+        &calendars,
+        bot_config.backtest_clock,
+    )?;
     let mut position_sizers = build_sizers(bot_config.position_sizers)?;
+    let backtest_clock = bot_config.backtest_clock.then(BacktestClock::new);
     let mut strategies = Vec::new();
     for config in bot_config.strategies {
         // Brokers are shared between strategies. That's why they stay in Arc
         let broker = brokers
             .get(&config.broker)
             .ok_or(FactoryError::UnknownBroker(config.broker))?;
-        // Feeds stay at Box because each strategy should own its feed instance (to avoid tick stealing)
-        let data_feed = data_feeds
-            .remove(&config.data_feed)
-            .ok_or(FactoryError::UnknownDataFeed(config.data_feed))?;
+        // Feeds stay at Box because each strategy should own its feed instance (to avoid tick stealing).
+        // Feeds shared between strategies are subscriptions to a market data hub.
+        let mut data_feed =
+            data_feeds.take(&config.data_feed, &config.name, &config.subscription)?;
+        if let Some(clock) = &backtest_clock {
+            data_feed = Box::new(clock.synchronize(data_feed));
+        }
         // Sizers stay at Box because each strategy should own its sizer instance.
         let sizer = position_sizers
             .remove(&config.position_sizer)
             .ok_or(FactoryError::UnknownPositionSizer(config.position_sizer))?;
+        let trading_hours = config
+            .trading_hours
+            .map(|hours| build_trading_hours(hours, &calendars))
+            .transpose()?;
         match config.r#type {
             StrategyType::PrintStrategy => {
+                if trading_hours.is_some() {
+                    warn!("{} does not trade and ignores trading hours", config.name);
+                }
                 let strategy: Box<dyn Strategy> = Box::new(PrintStrategy {
                     name: config.name,
                     data_feed,
@@ -67,59 +120,343 @@ pub async fn build_strategies(
                 strategies.push(strategy);
             }
             StrategyType::SmaCrossStrategy => {
-                let fast_window =
-                    get_usize_param(&config.params, "fast_window", DEFAULT_SMA_CROSS_FAST_WINDOW);
-                let slow_window =
-                    get_usize_param(&config.params, "slow_window", DEFAULT_SMA_CROSS_SLOW_WINDOW);
-
-                let strategy: Box<dyn Strategy> = Box::new(SmaCrossStrategy::new(
+                let params: SmaCrossParams =
+                    deserialize_params(&config.name, &config.params.clone().unwrap_or_default())?;
+                if params.fast_window == 0 || params.slow_window == 0 {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "fast_window/slow_window".into(),
+                        "expected windows of at least one price".into(),
+                    ));
+                }
+                let mut strategy = SmaCrossStrategy::new(
                     config.name,
                     data_feed,
                     broker.clone(),
                     sizer,
-                    fast_window,
-                    slow_window,
-                ));
-                strategies.push(strategy);
+                    params.fast_window,
+                    params.slow_window,
+                );
+                if let Some(trading_hours) = trading_hours {
+                    strategy = strategy.with_trading_hours(trading_hours);
+                }
+                strategies.push(Box::new(strategy));
+            }
+            StrategyType::RsiMeanReversionStrategy => {
+                let params: RsiMeanReversionParams =
+                    deserialize_params(&config.name, &config.params.clone().unwrap_or_default())?;
+                if params.period == 0 || params.trend_window == Some(0) {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "period/trend_window".into(),
+                        "expected periods of at least one price".into(),
+                    ));
+                }
+                if !(0.0 < params.oversold && params.oversold < params.exit && params.exit <= 100.0)
+                {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "oversold/exit".into(),
+                        "expected 0 < oversold < exit <= 100".into(),
+                    ));
+                }
+                let mut strategy = RsiMeanReversionStrategy::new(
+                    config.name,
+                    data_feed,
+                    broker.clone(),
+                    sizer,
+                    params,
+                );
+                if let Some(trading_hours) = trading_hours {
+                    strategy = strategy.with_trading_hours(trading_hours);
+                }
+                strategies.push(Box::new(strategy));
+            }
+            StrategyType::BreakoutStrategy => {
+                let params: BreakoutParams =
+                    deserialize_params(&config.name, &config.params.clone().unwrap_or_default())?;
+                validate_breakout_params(&config.name, &params)?;
+                let mut strategy =
+                    BreakoutStrategy::new(config.name, data_feed, broker.clone(), sizer, params);
+                if let Some(trading_hours) = trading_hours {
+                    strategy = strategy.with_trading_hours(trading_hours);
+                }
+                strategies.push(Box::new(strategy));
+            }
+            StrategyType::PairsTradingStrategy => {
+                let params: PairsTradingParams =
+                    deserialize_params(&config.name, &config.params.clone().unwrap_or_default())?;
+                if !(params.exit_z < params.entry_z
+                    && params.stop_z.is_none_or(|stop_z| stop_z > params.entry_z))
+                {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "entry_z/exit_z/stop_z".into(),
+                        "expected exit_z < entry_z < stop_z".into(),
+                    ));
+                }
+                if !broker.allows_short_selling() {
+                    warn!(
+                        "The broker of {} does not allow short selling (`allow_short`)",
+                        config.name
+                    );
+                }
+                let mut strategy = PairsTradingStrategy::new(
+                    config.name,
+                    data_feed,
+                    broker.clone(),
+                    sizer,
+                    params,
+                );
+                if let Some(trading_hours) = trading_hours {
+                    strategy = strategy.with_trading_hours(trading_hours);
+                }
+                strategies.push(Box::new(strategy));
+            }
+            StrategyType::DcaRebalanceStrategy => {
+                let params: DcaRebalanceParams =
+                    deserialize_params(&config.name, &config.params.clone().unwrap_or_default())?;
+                validate_dca_rebalance_params(&config.name, &params)?;
+                if trading_hours.is_some() {
+                    warn!(
+                        "{} trades on its schedule and ignores trading hours",
+                        config.name
+                    );
+                }
+                let strategy =
+                    DcaRebalanceStrategy::new(config.name, data_feed, broker.clone(), params);
+                strategies.push(Box::new(strategy));
+            }
+            StrategyType::GridStrategy => {
+                let params: GridParams =
+                    deserialize_params(&config.name, &config.params.clone().unwrap_or_default())?;
+                // The lowest level has to be above 0
+                if params.levels == 0
+                    || params.spacing <= 0.0
+                    || params.levels as f64 * params.spacing >= 1.0
+                {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "levels/spacing".into(),
+                        "expected at least one level and 0 < levels * spacing < 1".into(),
+                    ));
+                }
+                if params.max_inventory == Some(0) {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "max_inventory".into(),
+                        "expected at least one unit".into(),
+                    ));
+                }
+                let mut strategy =
+                    GridStrategy::new(config.name, data_feed, broker.clone(), sizer, params);
+                if let Some(trading_hours) = trading_hours {
+                    strategy = strategy.with_trading_hours(trading_hours);
+                }
+                strategies.push(Box::new(strategy));
+            }
+            StrategyType::MomentumRotationStrategy => {
+                let params: MomentumRotationParams =
+                    deserialize_params(&config.name, &config.params.clone().unwrap_or_default())?;
+                if params.top == 0 || params.lookback == 0 || params.rebalance_days == 0 {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "top/lookback/rebalance_days".into(),
+                        "expected at least one symbol, price and day".into(),
+                    ));
+                }
+                if trading_hours.is_some() {
+                    warn!(
+                        "{} trades on its schedule and ignores trading hours",
+                        config.name
+                    );
+                }
+                let strategy =
+                    MomentumRotationStrategy::new(config.name, data_feed, broker.clone(), params);
+                strategies.push(Box::new(strategy));
             }
         }
     }
-    Ok(strategies)
+    let replay_controls = data_feeds.start_hubs();
+    Ok(Bot {
+        strategies,
+        replay_controls,
+    })
+}
+
+fn validate_breakout_params(name: &str, params: &BreakoutParams) -> Result<(), FactoryError> {
+    let invalid = |key: &str, expected: &str| {
+        Err(FactoryError::InvalidParameter(
+            name.to_string(),
+            key.to_string(),
+            expected.to_string(),
+        ))
+    };
+    if params.entry_window == 0
+        || params.exit_window == 0
+        || params.atr_period == 0
+        || params.squeeze.is_some_and(|squeeze| squeeze.period == 0)
+    {
+        return invalid(
+            "entry_window/exit_window/atr_period/squeeze.period",
+            "expected periods of at least one bar",
+        );
+    }
+    if params.max_units == 0 || params.stop_atr <= 0.0 {
+        return invalid(
+            "max_units/stop_atr",
+            "expected at least one unit and a positive stop",
+        );
+    }
+    Ok(())
+}
+
+fn validate_dca_rebalance_params(
+    name: &str,
+    params: &DcaRebalanceParams,
+) -> Result<(), FactoryError> {
+    let invalid = |key: &str, expected: &str| {
+        Err(FactoryError::InvalidParameter(
+            name.to_string(),
+            key.to_string(),
+            expected.to_string(),
+        ))
+    };
+    let total_weight: f64 = params.weights.values().sum();
+    if params.weights.is_empty()
+        || params.weights.values().any(|weight| *weight <= 0.0)
+        || total_weight > 1.0 + 1e-9
+    {
+        return invalid("weights", "expected positive weights that sum to at most 1");
+    }
+    if params.amount.is_none() && params.drift_band.is_none() {
+        return invalid(
+            "amount/drift_band",
+            "expected an amount, a drift band or both",
+        );
+    }
+    if params.amount.is_some_and(|amount| amount <= 0.0) {
+        return invalid("amount", "expected a positive amount");
+    }
+    if params
+        .drift_band
+        .is_some_and(|drift_band| !(0.0..1.0).contains(&drift_band))
+    {
+        return invalid("drift_band", "expected 0 <= drift_band < 1");
+    }
+    if let Schedule::Monthly { day } = params.schedule
+        && !(1..=31).contains(&day)
+    {
+        return invalid("schedule", "expected a day of the month from 1 to 31");
+    }
+    Ok(())
 }
 
 fn build_connections(
     configs: Vec<IbConnectionConfig>,
-) -> Result<HashMap<String, Arc<Client>>, FactoryError> {
+) -> Result<HashMap<String, Arc<IbConnection>>, FactoryError> {
     let mut ib_connections = HashMap::new();
     for config in configs {
         debug!(
             "Attempting IB connection '{}' at {} (client_id={})",
             config.name, config.address, config.client_id
         );
-        let client = Client::connect(&config.address, config.client_id).map_err(|err| {
+        let connection = IbConnection::connect(&config).map_err(|err| {
             FactoryError::IbConnectionFailure(config.name.clone(), err.to_string())
         })?;
         debug!(
             "Established IB connection '{}' at {} (client_id={})",
             config.name, config.address, config.client_id
         );
-        ib_connections.insert(config.name, Arc::new(client));
+        ib_connections.insert(config.name, connection);
     }
     Ok(ib_connections)
 }
 
+fn build_calendars(
+    configs: Vec<CalendarConfig>,
+) -> Result<HashMap<String, Arc<ExchangeCalendar>>, FactoryError> {
+    let mut calendars = HashMap::new();
+    for config in configs {
+        let invalid =
+            |value: &str| FactoryError::InvalidCalendar(config.name.clone(), value.into());
+        let parse_date =
+            |date: &str| NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid(date));
+        let mut calendar = ExchangeCalendar::new(config.exchange).with_holidays(
+            config
+                .holidays
+                .iter()
+                .map(|date| parse_date(date))
+                .collect::<Result<Vec<_>, _>>()?,
+        );
+        for (date, close) in &config.early_closes {
+            let close = NaiveTime::parse_from_str(close, "%H:%M").map_err(|_| invalid(close))?;
+            calendar = calendar.with_early_close(parse_date(date)?, close);
+        }
+        calendars.insert(config.name, Arc::new(calendar));
+    }
+    Ok(calendars)
+}
+
+fn get_calendar(
+    calendars: &HashMap<String, Arc<ExchangeCalendar>>,
+    name: &str,
+) -> Result<Arc<ExchangeCalendar>, FactoryError> {
+    calendars
+        .get(name)
+        .cloned()
+        .ok_or_else(|| FactoryError::UnknownCalendar(name.to_string()))
+}
+
+fn build_trading_hours(
+    config: TradingHoursConfig,
+    calendars: &HashMap<String, Arc<ExchangeCalendar>>,
+) -> Result<TradingHours, FactoryError> {
+    Ok(TradingHours::new(
+        get_calendar(calendars, &config.calendar)?,
+        config.sessions,
+        config
+            .flatten_before_close_mins
+            .map(chrono::Duration::minutes),
+    ))
+}
+
 fn build_brokers(
     configs: Vec<BrokerConfig>,
-    ib_connections: &HashMap<String, Arc<Client>>,
+    ib_connections: &HashMap<String, Arc<IbConnection>>,
+    calendars: &HashMap<String, Arc<ExchangeCalendar>>,
 ) -> Result<HashMap<String, Arc<dyn Broker>>, FactoryError> {
     let mut brokers = HashMap::new();
     for config in configs {
         let broker: Arc<dyn Broker> = match config.r#type {
-            BrokerType::DummyBroker => Arc::new(DummyBroker::new(config.name.clone())),
+            BrokerType::DummyBroker => {
+                let mut broker = DummyBroker::new(config.name.clone());
+                if let Some(params) = &config.params {
+                    if let Some(corporate_actions) = load_corporate_actions(params)? {
+                        broker = broker.with_corporate_actions(&corporate_actions);
+                    }
+                    if allows_short_selling(params, &config.name) {
+                        broker = broker.with_short_selling();
+                    }
+                }
+                Arc::new(broker)
+            }
             BrokerType::IbBroker => {
                 let ib_connection = get_ib_connection(config.params.as_ref(), ib_connections)?;
-                let ib_broker = Ib::new(config.name.clone(), ib_connection.clone())
+                let mut ib_broker = Ib::new(config.name.clone(), ib_connection)
                     .map_err(|err| FactoryError::BrokerInit(err.to_string()))?;
+                let calendar = match &config.params {
+                    Some(params) => get_string_param(params, "calendar")?,
+                    None => None,
+                };
+                if let Some(calendar) = calendar {
+                    ib_broker = ib_broker.with_calendar(get_calendar(calendars, &calendar)?);
+                }
+                if let Some(params) = &config.params
+                    && allows_short_selling(params, &config.name)
+                {
+                    ib_broker = ib_broker.with_short_selling();
+                }
                 Arc::new(ib_broker)
             }
         };
@@ -128,11 +465,91 @@ fn build_brokers(
     Ok(brokers)
 }
 
+/// Feeds are owned by their single consumer, hubs are shared by subscribing to them
+#[derive(Default)]
+struct DataFeeds {
+    feeds: HashMap<String, Box<dyn DataFeed>>,
+    hubs: HashMap<String, MarketDataHub>,
+    replay_controls: HashMap<String, ReplayControl>,
+    /// The strategies' feeds are synchronized by a backtest clock
+    clocked: bool,
+}
+
+impl DataFeeds {
+    /// The feed `name` for `consumer`, a subscription if it is a hub. Under a backtest clock,
+    /// subscriptions never block the hub.
+    fn take(
+        &mut self,
+        name: &str,
+        consumer: &str,
+        subscription: &SubscriptionConfig,
+    ) -> Result<Box<dyn DataFeed>, FactoryError> {
+        if let Some(hub) = self.hubs.get_mut(name) {
+            let symbols = (!subscription.symbols.is_empty())
+                .then(|| subscription.symbols.iter().cloned().collect());
+            // A hub that waits for a full subscription would deadlock the clock, which holds
+            // that subscription back until the other ones received their next events
+            let capacity = match (self.clocked, subscription.backpressure) {
+                (true, BackpressurePolicy::Block) => usize::MAX,
+                _ => subscription.capacity,
+            };
+            return Ok(Box::new(hub.subscribe(
+                consumer.to_string(),
+                symbols,
+                subscription.backpressure,
+                capacity,
+            )));
+        }
+        self.feeds
+            .remove(name)
+            .ok_or(FactoryError::UnknownDataFeed(name.to_string()))
+    }
+
+    /// A pacer for feeds with a `speed` or `paused` param (or for all of them with `always`).
+    /// Its control is kept, so the replay can be driven while the bot runs.
+    fn replay_pacer(&mut self, config: &DataFeedConfig, always: bool) -> Option<ReplayPacer> {
+        let paced =
+            always || config.params.contains_key("speed") || config.params.contains_key("paused");
+        if !paced {
+            return None;
+        }
+        // 0 replays as fast as possible
+        let speed =
+            get_param_or_default(&config.params, "speed", 0.0, |v: &f64| Ok(*v), &config.name);
+        let paused = get_param_or_default(
+            &config.params,
+            "paused",
+            false,
+            |v: &bool| Ok(*v),
+            &config.name,
+        );
+        let control = ReplayControl::new(Some(speed), paused);
+        self.replay_controls
+            .insert(config.name.clone(), control.clone());
+        Some(ReplayPacer::new(control))
+    }
+
+    /// Hubs start once all their subscribers are known, so none of them misses events.
+    /// Returns the replay controls.
+    fn start_hubs(self) -> HashMap<String, ReplayControl> {
+        for hub in self.hubs.into_values() {
+            debug!("Starting market data hub '{}'", hub.name());
+            hub.start();
+        }
+        self.replay_controls
+    }
+}
+
 fn build_data_feeds(
     configs: Vec<DataFeedConfig>,
-    ib_connections: &HashMap<String, Arc<Client>>,
-) -> Result<HashMap<String, Box<dyn DataFeed>>, FactoryError> {
-    let mut data_feeds = HashMap::new();
+    ib_connections: &HashMap<String, Arc<IbConnection>>,
+    calendars: &HashMap<String, Arc<ExchangeCalendar>>,
+    clocked: bool,
+) -> Result<DataFeeds, FactoryError> {
+    let mut data_feeds = DataFeeds {
+        clocked,
+        ..Default::default()
+    };
     for config in configs {
         let data_feed: Box<dyn DataFeed> = match config.r#type {
             DataFeedType::CsvDataFeed => {
@@ -144,10 +561,15 @@ fn build_data_feeds(
                     .clone()
                     .into_string()
                     .map_err(|err| FactoryError::WrongCsvPathFormat(err.to_string()))?;
-                Box::new(
-                    CsvDataFeed::new(config.name.clone(), path)
-                        .map_err(|err| FactoryError::CsvDataFeedInitError(err.to_string()))?,
-                )
+                let mut feed = CsvDataFeed::new(config.name.clone(), path)
+                    .map_err(|err| FactoryError::CsvDataFeedInitError(err.to_string()))?;
+                if let Some(corporate_actions) = load_corporate_actions(&config.params)? {
+                    feed = feed.with_corporate_actions(&corporate_actions);
+                }
+                if let Some(pacer) = data_feeds.replay_pacer(&config, false) {
+                    feed = feed.with_pacer(pacer);
+                }
+                Box::new(feed)
             }
             DataFeedType::IbMarketDataFeed => {
                 let ib_connection = get_ib_connection(Some(&config.params), ib_connections)?;
@@ -158,18 +580,47 @@ fn build_data_feeds(
                     |v: &MarketDataType| Ok(v.clone()),
                     "IB Market Data Feed",
                 );
+                let generic_ticks = get_param_or_default(
+                    &config.params,
+                    "generic_ticks",
+                    DEFAULT_GENERIC_TICKS
+                        .iter()
+                        .map(|t| t.to_string())
+                        .collect(),
+                    |v: &Vec<String>| Ok(v.clone()),
+                    "IB Market Data Feed",
+                );
+                let snapshot = get_param_or_default(
+                    &config.params,
+                    "snapshot",
+                    false,
+                    |v: &bool| Ok(*v),
+                    "IB Market Data Feed",
+                );
+                let regulatory_snapshot = get_param_or_default(
+                    &config.params,
+                    "regulatory_snapshot",
+                    false,
+                    |v: &bool| Ok(*v),
+                    "IB Market Data Feed",
+                );
+                let options = IbMarketDataOptions {
+                    market_data_type: ib_market_data_type.into(),
+                    generic_ticks,
+                    snapshot,
+                    regulatory_snapshot,
+                };
                 let feed = IbMarketDataFeed::new(
                     config.name.clone(),
                     ib_connection,
-                    config.symbol,
-                    ib_market_data_type.into(),
+                    config.all_symbols(),
+                    options,
                 )
                 .map_err(|err| FactoryError::FeedInit(err.to_string()))?;
                 Box::new(feed)
             }
             DataFeedType::IbHistoricalDataFeed => {
-                let ib_connection: Arc<Client> =
-                    get_ib_connection(Some(&config.params), ib_connections)?;
+                let ib_connection = get_ib_connection(Some(&config.params), ib_connections)?;
 
                 let default_end_datetime = OffsetDateTime::now_utc();
                 let end_datetime = get_param_or_default(
@@ -185,7 +636,7 @@ fn build_data_feeds(
                     &config.params,
                     "duration",
                     default_duration,
-                    |s: &String| Duration::try_from(s.as_str()).map_err(|e| e.to_string()),
+                    |s: &String| s.parse::<Duration>().map_err(|e| e.to_string()),
                     "IB Historical Data Feed",
                 );
 
@@ -194,21 +645,180 @@ fn build_data_feeds(
                     &config.params,
                     "bar_size",
                     default_bar_size,
-                    |s: &String| Ok(BarSize::from(s.as_str())), // TODO: This panics. Consider solving on repo or wrap in Result
+                    |s: &String| parse_bar_size(s),
                     "IB Historical Data Feed",
                 );
 
-                Box::new(IbHistoricalDataFeed::new(
-                    config.name.clone(),
-                    ib_connection,
-                    config.symbol,
-                    end_datetime,
+                let what_to_show = get_param_or_default(
+                    &config.params,
+                    "what_to_show",
+                    HistoricalWhatToShow::Trades,
+                    |v: &HistoricalWhatToShow| Ok(*v),
+                    "IB Historical Data Feed",
+                );
+                let use_rth = get_param_or_default(
+                    &config.params,
+                    "use_rth",
+                    true,
+                    |v: &bool| Ok(*v),
+                    "IB Historical Data Feed",
+                );
+
+                let cache = get_string_param(&config.params, "cache_dir")?
+                    .map(HistoricalDataCache::new)
+                    .transpose()
+                    .map_err(|err| FactoryError::FeedInit(err.to_string()))?;
+
+                let pacer = data_feeds.replay_pacer(&config, false);
+                let request = HistoricalBarsRequest {
+                    symbol: config.symbol,
+                    interval_end: end_datetime,
                     duration,
                     bar_size,
+                    what_to_show: what_to_show.into(),
+                    use_rth,
+                };
+                let mut feed =
+                    IbHistoricalDataFeed::new(config.name.clone(), ib_connection, request, cache);
+                if let Some(pacer) = pacer {
+                    feed = feed.with_pacer(pacer);
+                }
+                Box::new(feed)
+            }
+            DataFeedType::IbRealtimeBarsFeed => {
+                let ib_connection = get_ib_connection(Some(&config.params), ib_connections)?;
+                let what_to_show = get_param_or_default(
+                    &config.params,
+                    "what_to_show",
+                    RealtimeWhatToShow::Trades,
+                    |v: &RealtimeWhatToShow| Ok(*v),
+                    "IB Realtime Bars Feed",
+                );
+                let use_rth = get_param_or_default(
+                    &config.params,
+                    "use_rth",
+                    true,
+                    |v: &bool| Ok(*v),
+                    "IB Realtime Bars Feed",
+                );
+                Box::new(IbRealtimeBarsFeed::new(
+                    config.name.clone(),
+                    ib_connection,
+                    config.all_symbols(),
+                    what_to_show.into(),
+                    use_rth,
+                ))
+            }
+            DataFeedType::IbTickByTickFeed => {
+                let ib_connection = get_ib_connection(Some(&config.params), ib_connections)?;
+                let tick_type = get_param_or_default(
+                    &config.params,
+                    "tick_type",
+                    TickByTickType::Last,
+                    |v: &TickByTickType| Ok(*v),
+                    "IB Tick By Tick Feed",
+                );
+                Box::new(IbTickByTickFeed::new(
+                    config.name.clone(),
+                    ib_connection,
+                    config.all_symbols(),
+                    tick_type,
                 ))
             }
+            DataFeedType::RecordingDataFeed => {
+                let inner = take_wrapped_data_feed(&config, &mut data_feeds)?;
+                let dir = get_required_string_param(&config, "dir")?;
+                Box::new(
+                    RecordingDataFeed::new(config.name.clone(), inner, dir)
+                        .map_err(|err| FactoryError::FeedInit(err.to_string()))?,
+                )
+            }
+            DataFeedType::ReplayDataFeed => {
+                let path = get_required_string_param(&config, "path")?;
+                let recording = get_string_param(&config.params, "recording")?;
+                let feed =
+                    ReplayDataFeed::new(config.name.clone(), path, recording.as_deref(), None)
+                        .map_err(|err| FactoryError::FeedInit(err.to_string()))?;
+                // Recordings are always paced, so they can be paused
+                match data_feeds.replay_pacer(&config, true) {
+                    Some(pacer) => Box::new(feed.with_pacer(pacer)),
+                    None => Box::new(feed),
+                }
+            }
+            DataFeedType::ResamplingDataFeed => {
+                let inner = take_wrapped_data_feed(&config, &mut data_feeds)?;
+                let bar_type = get_required_string_param(&config, "bar_type")?;
+                let bar_size = get_required_string_param(&config, "bar_size")?;
+                let spec = BarSpec::parse(&bar_type, &bar_size)
+                    .map_err(|err| FactoryError::FeedInit(err.to_string()))?;
+                let close_delay = config
+                    .params
+                    .get("close_delay_secs")
+                    .map(|value| {
+                        value
+                            .clone()
+                            .into_float()
+                            .ok()
+                            .and_then(|secs| std::time::Duration::try_from_secs_f64(secs).ok())
+                            .ok_or_else(|| {
+                                FactoryError::InvalidParameter(
+                                    config.name.clone(),
+                                    "close_delay_secs".into(),
+                                    "expected a number of seconds of at least 0".into(),
+                                )
+                            })
+                    })
+                    .transpose()?;
+                let mut feed = ResamplingDataFeed::new(config.name.clone(), inner, spec);
+                if let Some(close_delay) = close_delay {
+                    feed = feed.with_close_delay(close_delay);
+                }
+                Box::new(feed)
+            }
+            DataFeedType::SyntheticDataFeed => {
+                let synthetic_config: SyntheticDataFeedConfig =
+                    deserialize_params(&config.name, &config.params)?;
+                Box::new(
+                    SyntheticDataFeed::new(
+                        config.name.clone(),
+                        config.all_symbols(),
+                        synthetic_config,
+                    )
+                    .map_err(|err| FactoryError::FeedInit(err.to_string()))?,
+                )
+            }
+            DataFeedType::ValidatingDataFeed => {
+                let inner = take_wrapped_data_feed(&config, &mut data_feeds)?;
+                let validation_config: DataValidationConfig =
+                    deserialize_params(&config.name, &config.params)?;
+                let calendar = get_string_param(&config.params, "calendar")?;
+                // Without the sessions, every night would be a gap
+                if calendar.is_none()
+                    && validation_config
+                        .bar_interval_secs
+                        .is_some_and(|secs| secs < 86_400)
+                {
+                    return Err(FactoryError::InvalidParameter(
+                        config.name,
+                        "bar_interval_secs".into(),
+                        "bars shorter than a day need a `calendar`".into(),
+                    ));
+                }
+                let mut feed =
+                    ValidatingDataFeed::new(config.name.clone(), inner, validation_config);
+                if let Some(calendar) = calendar {
+                    feed = feed.with_calendar(get_calendar(calendars, &calendar)?);
+                }
+                Box::new(feed)
+            }
+            DataFeedType::MarketDataHub => {
+                let source = take_wrapped_data_feed(&config, &mut data_feeds)?;
+                let hub = MarketDataHub::new(config.name.clone(), source);
+                data_feeds.hubs.insert(config.name, hub);
+                continue;
+            }
         };
-        data_feeds.insert(config.name, data_feed);
+        data_feeds.feeds.insert(config.name, data_feed);
     }
     Ok(data_feeds)
 }
@@ -224,7 +834,7 @@ fn build_sizers(
                     &config.params,
                     "qty",
                     10,
-                    |v: &u16| Ok(v.clone() as u32),
+                    |v: &u16| Ok(*v as u32),
                     "Fixed Sizer",
                 );
                 Box::new(FixedSizer::new(config.name.clone(), qty))
@@ -234,7 +844,7 @@ fn build_sizers(
                     &config.params,
                     "percent",
                     0.1,
-                    |v: &f64| Ok(v.clone()),
+                    |v: &f64| Ok(*v),
                     "Percent of Equity Sizer",
                 );
                 Box::new(PercentOfEquitySizer::new(config.name.clone(), percent))
@@ -245,19 +855,69 @@ fn build_sizers(
     Ok(sizers)
 }
 
-fn get_usize_param(params: &Option<HashMap<String, Value>>, key: &str, default: usize) -> usize {
+/// Feeds that wrap another feed take it over (or subscribe to it, if it is a hub), so the
+/// wrapped feed has to be defined before them
+fn take_wrapped_data_feed(
+    config: &DataFeedConfig,
+    data_feeds: &mut DataFeeds,
+) -> Result<Box<dyn DataFeed>, FactoryError> {
+    let name = get_required_string_param(config, "data_feed")?;
+    data_feeds.take(&name, &config.name, &SubscriptionConfig::default())
+}
+
+/// Whether the `allow_short` param of a broker lets it open short positions
+fn allows_short_selling(params: &HashMap<String, Value>, broker_name: &str) -> bool {
+    params.contains_key("allow_short")
+        && get_param_or_default(params, "allow_short", false, |v: &bool| Ok(*v), broker_name)
+}
+
+/// The corporate actions file given in the `corporate_actions` param, if any
+fn load_corporate_actions(
+    params: &HashMap<String, Value>,
+) -> Result<Option<CorporateActions>, FactoryError> {
+    get_string_param(params, "corporate_actions")?
+        .map(|path| {
+            CorporateActions::from_csv(path)
+                .map_err(|err| FactoryError::CorporateActions(err.to_string()))
+        })
+        .transpose()
+}
+
+/// Deserialize all the params of a feed or strategy at once, for structured params
+fn deserialize_params<T: serde::de::DeserializeOwned>(
+    name: &str,
+    params: &HashMap<String, Value>,
+) -> Result<T, FactoryError> {
+    let params = Value::new(None, params.clone().into_iter().collect::<Map<_, _>>());
     params
-        .as_ref()
-        .and_then(|p| p.get(key))
-        .and_then(|v| v.clone().into_uint().ok())
-        .and_then(|v| v.try_into().ok())
-        .unwrap_or(default)
+        .try_deserialize()
+        .map_err(|err| FactoryError::InvalidParameters(name.to_string(), err.to_string()))
+}
+
+fn get_required_string_param(config: &DataFeedConfig, key: &str) -> Result<String, FactoryError> {
+    get_string_param(&config.params, key)?
+        .ok_or_else(|| FactoryError::MissingParameter(config.name.clone(), key.to_string()))
+}
+
+fn get_string_param(
+    params: &HashMap<String, Value>,
+    key: &str,
+) -> Result<Option<String>, FactoryError> {
+    params
+        .get(key)
+        .map(|value| {
+            value
+                .clone()
+                .into_string()
+                .map_err(|err| FactoryError::UnexpectedParameterType(err.to_string()))
+        })
+        .transpose()
 }
 
 fn get_ib_connection(
     params: Option<&HashMap<String, Value>>,
-    ib_connections: &HashMap<String, Arc<Client>>,
-) -> Result<Arc<Client>, FactoryError> {
+    ib_connections: &HashMap<String, Arc<IbConnection>>,
+) -> Result<Arc<IbConnection>, FactoryError> {
     let ib_connection_value = params
         .as_ref()
         .and_then(|p| p.get("connection"))
@@ -316,7 +976,7 @@ where
     }
 }
 
-fn parse_end_datetime(s: &str) -> Result<OffsetDateTime, String> {
+pub(crate) fn parse_end_datetime(s: &str) -> Result<OffsetDateTime, String> {
     if s.eq_ignore_ascii_case("now") {
         return Ok(OffsetDateTime::now_utc());
     }
@@ -334,6 +994,33 @@ fn parse_end_datetime(s: &str) -> Result<OffsetDateTime, String> {
     Err(format!("Invalid end_datetime format: {s}"))
 }
 
+/// The IB bar size named like the variants of [`BarSize`], e.g. "Min5" or "Day" (any case)
+pub(crate) fn parse_bar_size(s: &str) -> Result<BarSize, String> {
+    let bar_size = match s.to_uppercase().as_str() {
+        "SEC" => BarSize::Sec,
+        "SEC5" => BarSize::Sec5,
+        "SEC15" => BarSize::Sec15,
+        "SEC30" => BarSize::Sec30,
+        "MIN" => BarSize::Min,
+        "MIN2" => BarSize::Min2,
+        "MIN3" => BarSize::Min3,
+        "MIN5" => BarSize::Min5,
+        "MIN15" => BarSize::Min15,
+        "MIN20" => BarSize::Min20,
+        "MIN30" => BarSize::Min30,
+        "HOUR" => BarSize::Hour,
+        "HOUR2" => BarSize::Hour2,
+        "HOUR3" => BarSize::Hour3,
+        "HOUR4" => BarSize::Hour4,
+        "HOUR8" => BarSize::Hour8,
+        "DAY" => BarSize::Day,
+        "WEEK" => BarSize::Week,
+        "MONTH" => BarSize::Month,
+        _ => return Err(format!("Invalid bar_size: {s}")),
+    };
+    Ok(bar_size)
+}
+
 #[derive(Clone, Debug, Deserialize)]
 enum MarketDataType {
     Live,
@@ -353,6 +1040,48 @@ impl From<MarketDataType> for IbMarketDataType {
     }
 }
 
+#[derive(Debug, Deserialize, Clone, Copy)]
+enum RealtimeWhatToShow {
+    Trades,
+    MidPoint,
+    Bid,
+    Ask,
+}
+
+impl From<RealtimeWhatToShow> for IbRealtimeWhatToShow {
+    fn from(what_to_show: RealtimeWhatToShow) -> IbRealtimeWhatToShow {
+        match what_to_show {
+            RealtimeWhatToShow::Trades => IbRealtimeWhatToShow::Trades,
+            RealtimeWhatToShow::MidPoint => IbRealtimeWhatToShow::MidPoint,
+            RealtimeWhatToShow::Bid => IbRealtimeWhatToShow::Bid,
+            RealtimeWhatToShow::Ask => IbRealtimeWhatToShow::Ask,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy)]
+enum HistoricalWhatToShow {
+    Trades,
+    MidPoint,
+    Bid,
+    Ask,
+    BidAsk,
+    AdjustedLast,
+}
+
+impl From<HistoricalWhatToShow> for IbHistoricalWhatToShow {
+    fn from(what_to_show: HistoricalWhatToShow) -> IbHistoricalWhatToShow {
+        match what_to_show {
+            HistoricalWhatToShow::Trades => IbHistoricalWhatToShow::Trades,
+            HistoricalWhatToShow::MidPoint => IbHistoricalWhatToShow::MidPoint,
+            HistoricalWhatToShow::Bid => IbHistoricalWhatToShow::Bid,
+            HistoricalWhatToShow::Ask => IbHistoricalWhatToShow::Ask,
+            HistoricalWhatToShow::BidAsk => IbHistoricalWhatToShow::BidAsk,
+            HistoricalWhatToShow::AdjustedLast => IbHistoricalWhatToShow::AdjustedLast,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum FactoryError {
     #[error("Interactive Broker configuration without connection parameter")]
@@ -379,13 +1108,25 @@ pub enum FactoryError {
     BrokerInit(String),
     #[error("Failed to initialize feed: `{0}`")]
     FeedInit(String),
+    #[error("The config of `{0}` does not contain a `{1}` parameter")]
+    MissingParameter(String, String),
+    #[error("Invalid parameters of `{0}`: {1}")]
+    InvalidParameters(String, String),
+    #[error("Invalid `{1}` parameter in `{0}`: {2}")]
+    InvalidParameter(String, String, String),
+    #[error("Failed to load corporate actions: `{0}`")]
+    CorporateActions(String),
+    #[error("The calendar `{0}` was not found in the config")]
+    UnknownCalendar(String),
+    #[error("Invalid date or time in calendar `{0}`: `{1}`")]
+    InvalidCalendar(String, String),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use config::Value;
-    use std::collections::HashMap;
+    use std::{collections::HashMap, io::Write};
     use time::macros::datetime;
 
     fn make_params(map: &[(&str, Value)]) -> HashMap<String, Value> {
@@ -488,4 +1229,326 @@ mod tests {
                 .contains("Invalid end_datetime format")
         );
     }
+
+    #[test]
+    fn test_parse_bar_size() {
+        assert_eq!(parse_bar_size("Min5").unwrap(), BarSize::Min5);
+        assert_eq!(parse_bar_size("day").unwrap(), BarSize::Day);
+        // IB's own names are not accepted
+        assert!(parse_bar_size("1 day").is_err());
+    }
+
+    #[test]
+    fn test_ib_connection_rejects_zero_heartbeat_interval() {
+        let yaml = |heartbeat_interval_secs| {
+            format!(
+                r#"
+ib_connections:
+  - name: "ib"
+    address: "127.0.0.1:4002"
+    client_id: 1
+    heartbeat_interval_secs: {heartbeat_interval_secs}
+brokers: []
+strategies: []
+position_sizers: []
+data_feeds: []
+"#
+            )
+        };
+        let parse = |yaml: String| {
+            config::Config::builder()
+                .add_source(config::File::from_str(&yaml, config::FileFormat::Yaml))
+                .build()
+                .unwrap()
+                .try_deserialize::<BotConfig>()
+        };
+        let config = parse(yaml(5)).unwrap();
+        assert_eq!(config.ib_connections[0].heartbeat_interval_secs, 5);
+        assert!(parse(yaml(0)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_synthetic_data_feed_from_yaml() {
+        let yaml = r#"
+brokers: []
+strategies: []
+position_sizers: []
+data_feeds:
+  - name: "synthetic"
+    type: "SyntheticDataFeed"
+    symbols: ["A", "B"]
+    params:
+      seed: 42
+      steps: 3
+      interval_secs: 3600
+      start: "2024-01-02T09:00:00Z"
+      model:
+        type: "JumpDiffusion"
+        drift: 0.05
+        volatility: 0.2
+        jump_intensity: 2.0
+        jump_mean: -0.05
+        jump_volatility: 0.1
+"#;
+        let config: BotConfig = config::Config::builder()
+            .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let mut data_feeds =
+            build_data_feeds(config.data_feeds, &HashMap::new(), &HashMap::new(), false).unwrap();
+        let mut feed = data_feeds.feeds.remove("synthetic").unwrap();
+
+        let mut bars = Vec::new();
+        while let Some(Ok(data)) = feed.next_tick().await {
+            bars.push((data.symbol, data.timestamp.to_rfc3339()));
+        }
+        assert_eq!(bars.len(), 6);
+        assert_eq!(
+            bars[0],
+            ("A".to_string(), "2024-01-02T09:00:00+00:00".to_string())
+        );
+        assert_eq!(
+            bars[5],
+            ("B".to_string(), "2024-01-02T11:00:00+00:00".to_string())
+        );
+    }
+
+    #[test]
+    fn test_validating_data_feed_needs_a_calendar_for_intraday_bars() {
+        let yaml = |bar_interval_secs| {
+            format!(
+                r#"
+brokers: []
+strategies: []
+position_sizers: []
+data_feeds:
+  - name: "synthetic"
+    type: "SyntheticDataFeed"
+    symbols: ["A"]
+    params:
+      steps: 3
+      model:
+        type: "Gbm"
+        drift: 0.05
+        volatility: 0.2
+  - name: "validated"
+    type: "ValidatingDataFeed"
+    params:
+      data_feed: "synthetic"
+      bar_interval_secs: {bar_interval_secs}
+"#
+            )
+        };
+        let build = |bar_interval_secs| {
+            let config: BotConfig = config::Config::builder()
+                .add_source(config::File::from_str(
+                    &yaml(bar_interval_secs),
+                    config::FileFormat::Yaml,
+                ))
+                .build()
+                .unwrap()
+                .try_deserialize()
+                .unwrap();
+            build_data_feeds(config.data_feeds, &HashMap::new(), &HashMap::new(), false)
+        };
+        assert!(build(86_400).is_ok());
+        assert!(matches!(
+            build(60),
+            Err(FactoryError::InvalidParameter(name, param, _))
+                if name == "validated" && param == "bar_interval_secs"
+        ));
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 3)]
+    async fn test_clocked_hub_subscriptions_do_not_block_the_hub() {
+        // Many events of X before the first one of Y, more than the capacity of a subscription
+        let mut csv = tempfile::NamedTempFile::new().unwrap();
+        writeln!(csv, "timestamp,price,volume,symbol").unwrap();
+        for minute in 0..10 {
+            writeln!(csv, "2024-01-02 09:{:02}:00,100,1,X", 30 + minute).unwrap();
+        }
+        writeln!(csv, "2024-01-02 09:45:00,50,1,Y").unwrap();
+        writeln!(csv, "2024-01-02 09:46:00,101,1,X").unwrap();
+        let yaml = format!(
+            r#"
+brokers: []
+strategies: []
+position_sizers: []
+data_feeds:
+  - name: "csv"
+    type: "CsvDataFeed"
+    params:
+      path: "{}"
+  - name: "hub"
+    type: "MarketDataHub"
+    params:
+      data_feed: "csv"
+"#,
+            csv.path().display()
+        );
+        let config: BotConfig = config::Config::builder()
+            .add_source(config::File::from_str(&yaml, config::FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let mut data_feeds =
+            build_data_feeds(config.data_feeds, &HashMap::new(), &HashMap::new(), true).unwrap();
+        let clock = BacktestClock::new();
+        let mut feeds = Vec::new();
+        for symbol in ["X", "Y"] {
+            let subscription = SubscriptionConfig {
+                backpressure: BackpressurePolicy::Block,
+                capacity: 2,
+                symbols: vec![symbol.to_string()],
+            };
+            let feed = data_feeds.take("hub", symbol, &subscription).unwrap();
+            feeds.push(clock.synchronize(feed));
+        }
+        data_feeds.start_hubs();
+
+        let consumers = feeds.into_iter().map(|mut feed| {
+            tokio::spawn(async move {
+                let mut ticks = Vec::new();
+                while let Some(Ok(data)) = feed.next_tick().await {
+                    ticks.push(data.timestamp);
+                }
+                ticks
+            })
+        });
+        let ticks = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            futures::future::join_all(consumers),
+        )
+        .await
+        .expect("the clock and the hub do not wait for each other");
+        let ticks: Vec<Vec<_>> = ticks.into_iter().map(|ticks| ticks.unwrap()).collect();
+        assert_eq!(ticks[0].len(), 11);
+        assert_eq!(ticks[1].len(), 1);
+        assert_eq!(clock.now(), ticks[0].last().copied());
+    }
+
+    #[test]
+    fn test_dca_rebalance_params_from_yaml() {
+        let yaml = r#"
+weights:
+  SPY: 0.6
+  TLT: 0.3
+schedule:
+  type: "Weekly"
+  weekday: "Friday"
+amount: 500.0
+"#;
+        let params: HashMap<String, Value> = config::Config::builder()
+            .add_source(config::File::from_str(yaml, config::FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        let params: DcaRebalanceParams = deserialize_params("dca", &params).unwrap();
+        assert_eq!(params.weights["SPY"], 0.6);
+        assert_eq!(
+            params.schedule,
+            Schedule::Weekly {
+                weekday: chrono::Weekday::Fri
+            }
+        );
+        assert_eq!(params.amount, Some(500.0));
+        assert!(validate_dca_rebalance_params("dca", &params).is_ok());
+
+        let params = DcaRebalanceParams {
+            amount: None,
+            ..params
+        };
+        assert!(validate_dca_rebalance_params("dca", &params).is_err());
+        let params = DcaRebalanceParams {
+            drift_band: Some(0.05),
+            schedule: Schedule::Monthly { day: 0 },
+            ..params
+        };
+        assert!(validate_dca_rebalance_params("dca", &params).is_err());
+    }
+
+    /// Builds a bot with a single strategy of `strategy_type` with `params` (a YAML mapping)
+    async fn build_strategy(strategy_type: &str, params: &str) -> Result<Bot, FactoryError> {
+        let yaml = format!(
+            r#"
+brokers:
+  - name: "dummy"
+    type: "DummyBroker"
+position_sizers:
+  - name: "fixed"
+    type: "FixedSizer"
+    params:
+      qty: 1
+data_feeds:
+  - name: "synthetic"
+    type: "SyntheticDataFeed"
+    symbols: ["A"]
+    params:
+      steps: 3
+      model:
+        type: "Gbm"
+        drift: 0.05
+        volatility: 0.2
+strategies:
+  - name: "strategy"
+    type: "{strategy_type}"
+    broker: "dummy"
+    data_feed: "synthetic"
+    position_sizer: "fixed"
+    params: {params}
+"#
+        );
+        let config: BotConfig = config::Config::builder()
+            .add_source(config::File::from_str(&yaml, config::FileFormat::Yaml))
+            .build()
+            .unwrap()
+            .try_deserialize()
+            .unwrap();
+        build_bot(config).await
+    }
+
+    #[tokio::test]
+    async fn test_strategies_reject_empty_windows() {
+        let is_invalid = |result: Result<Bot, FactoryError>| matches!(result, Err(FactoryError::InvalidParameter(name, _, _)) if name == "strategy");
+        assert!(
+            build_strategy("SmaCrossStrategy", "{ fast_window: 5 }")
+                .await
+                .is_ok()
+        );
+        assert!(is_invalid(
+            build_strategy("SmaCrossStrategy", "{ fast_window: 0 }").await
+        ));
+        assert!(
+            build_strategy("RsiMeanReversionStrategy", "{ trend_window: 20 }")
+                .await
+                .is_ok()
+        );
+        for params in ["{ period: 0 }", "{ trend_window: 0 }"] {
+            assert!(is_invalid(
+                build_strategy("RsiMeanReversionStrategy", params).await
+            ));
+        }
+        let squeeze = "{ squeeze: { max_bandwidth: 0.05 } }";
+        assert!(build_strategy("BreakoutStrategy", squeeze).await.is_ok());
+        for params in ["{ atr_period: 0 }", "{ squeeze: { period: 0 } }"] {
+            assert!(is_invalid(build_strategy("BreakoutStrategy", params).await));
+        }
+        assert!(
+            build_strategy("GridStrategy", "{ max_inventory: 3 }")
+                .await
+                .is_ok()
+        );
+        assert!(is_invalid(
+            build_strategy("GridStrategy", "{ max_inventory: 0 }").await
+        ));
+        // Inventories beyond u32 are rejected instead of truncated
+        assert!(matches!(
+            build_strategy("GridStrategy", "{ max_inventory: 4294967296 }").await,
+            Err(FactoryError::InvalidParameters(..))
+        ));
+    }
 }
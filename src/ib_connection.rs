@@ -0,0 +1,327 @@
+use crate::config::IbConnectionConfig;
+use ibapi::Client;
+use std::{
+    collections::VecDeque,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tokio::sync::{Mutex, watch};
+use tracing::{debug, error, info, warn};
+
+pub const DEFAULT_HEARTBEAT_INTERVAL_SECS: u64 = 10;
+pub const DEFAULT_RECONNECT_INITIAL_BACKOFF_SECS: u64 = 1;
+pub const DEFAULT_RECONNECT_MAX_BACKOFF_SECS: u64 = 60;
+
+/// IB allows at most this many historical data requests per [`HISTORICAL_PACING_WINDOW`]
+const HISTORICAL_PACING_MAX_REQUESTS: usize = 60;
+const HISTORICAL_PACING_WINDOW: Duration = Duration::from_secs(10 * 60);
+/// IB allows at most this many historical data requests for the same contract per
+/// [`HISTORICAL_PACING_SAME_CONTRACT_WINDOW`]
+const HISTORICAL_PACING_SAME_CONTRACT_MAX_REQUESTS: usize = 6;
+const HISTORICAL_PACING_SAME_CONTRACT_WINDOW: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IbConnectionState {
+    Connected,
+    Reconnecting,
+}
+
+/// A connection to an Interactive Brokers instance that is shared between brokers and data feeds.
+///
+/// The connection is health-checked periodically. When a check fails, a new client is connected
+/// with exponential backoff and swapped in. Users that hold long-lived subscriptions should wait
+/// for the new client with [`IbConnection::wait_for_new_client`] and subscribe again.
+pub struct IbConnection {
+    name: String,
+    address: String,
+    client_id: i32,
+    client: RwLock<Arc<Client>>,
+    state: watch::Sender<IbConnectionState>,
+    historical_data_pacer: HistoricalDataPacer,
+}
+
+struct ReconnectPolicy {
+    heartbeat_interval: Duration,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl ReconnectPolicy {
+    fn from_config(config: &IbConnectionConfig) -> Result<Self, IbConnectionError> {
+        let policy = Self {
+            heartbeat_interval: Duration::from_secs(config.heartbeat_interval_secs),
+            initial_backoff: Duration::from_secs(config.reconnect_initial_backoff_secs),
+            max_backoff: Duration::from_secs(config.reconnect_max_backoff_secs),
+        };
+        if policy.max_backoff < policy.initial_backoff {
+            return Err(IbConnectionError::InvalidReconnectPolicy(format!(
+                "the max backoff ({}s) is shorter than the initial backoff ({}s)",
+                config.reconnect_max_backoff_secs, config.reconnect_initial_backoff_secs
+            )));
+        }
+        Ok(policy)
+    }
+
+    fn next_backoff(&self, current: Duration) -> Duration {
+        (current * 2).min(self.max_backoff)
+    }
+}
+
+impl IbConnection {
+    /// Connect to IB and start monitoring the connection
+    pub fn connect(config: &IbConnectionConfig) -> Result<Arc<Self>, IbConnectionError> {
+        let policy = ReconnectPolicy::from_config(config)?;
+        let client = Client::connect(&config.address, config.client_id)
+            .map_err(|err| IbConnectionError::Connect(err.to_string()))?;
+        let (state, _) = watch::channel(IbConnectionState::Connected);
+        let connection = Arc::new(Self {
+            name: config.name.clone(),
+            address: config.address.clone(),
+            client_id: config.client_id,
+            client: RwLock::new(Arc::new(client)),
+            state,
+            historical_data_pacer: HistoricalDataPacer::default(),
+        });
+        tokio::spawn(connection.clone().monitor(policy));
+        Ok(connection)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The currently active client
+    pub fn client(&self) -> Arc<Client> {
+        self.client.read().unwrap().clone()
+    }
+
+    pub fn state(&self) -> IbConnectionState {
+        *self.state.borrow()
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.state() == IbConnectionState::Connected
+    }
+
+    /// The scheduler that every historical data request on this connection should go through
+    pub fn historical_data_pacer(&self) -> &HistoricalDataPacer {
+        &self.historical_data_pacer
+    }
+
+    /// Observe the state changes of the connection
+    pub fn subscribe_state(&self) -> watch::Receiver<IbConnectionState> {
+        self.state.subscribe()
+    }
+
+    /// Wait until the connection is re-established with a client other than `previous`.
+    /// Used to re-subscribe streams that ended because the connection was lost.
+    pub async fn wait_for_new_client(&self, previous: &Arc<Client>) -> Arc<Client> {
+        let mut state = self.state.subscribe();
+        loop {
+            if *state.borrow_and_update() == IbConnectionState::Connected {
+                let client = self.client();
+                if !Arc::ptr_eq(&client, previous) {
+                    return client;
+                }
+            }
+            // The sender is owned by self, so this can not fail while self is alive
+            let _ = state.changed().await;
+        }
+    }
+
+    async fn monitor(self: Arc<Self>, policy: ReconnectPolicy) {
+        loop {
+            tokio::time::sleep(policy.heartbeat_interval).await;
+            let client = self.client();
+            let healthy = tokio::task::spawn_blocking(move || client.server_time())
+                .await
+                .map(|result| result.is_ok())
+                .unwrap_or(false);
+            if healthy {
+                debug!("IB connection '{}' is healthy", self.name);
+                continue;
+            }
+            warn!(
+                "IB connection '{}' at {} failed its health check. Reconnecting",
+                self.name, self.address
+            );
+            self.state.send_replace(IbConnectionState::Reconnecting);
+            self.reconnect(&policy).await;
+        }
+    }
+
+    async fn reconnect(&self, policy: &ReconnectPolicy) {
+        let mut backoff = policy.initial_backoff;
+        loop {
+            let address = self.address.clone();
+            let client_id = self.client_id;
+            match tokio::task::spawn_blocking(move || Client::connect(&address, client_id)).await {
+                Ok(Ok(client)) => {
+                    *self.client.write().unwrap() = Arc::new(client);
+                    self.state.send_replace(IbConnectionState::Connected);
+                    info!(
+                        "Re-established IB connection '{}' at {} (client_id={})",
+                        self.name, self.address, self.client_id
+                    );
+                    return;
+                }
+                Ok(Err(err)) => warn!(
+                    "Failed to reconnect IB connection '{}': {}. Retrying in {:?}",
+                    self.name, err, backoff
+                ),
+                Err(err) => error!(
+                    "Reconnect task of IB connection '{}' failed: {}. Retrying in {:?}",
+                    self.name, err, backoff
+                ),
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = policy.next_backoff(backoff);
+        }
+    }
+}
+
+/// Schedules historical data requests so that they stay within the pacing limits of IB.
+/// Requests that would violate them are delayed until they are allowed.
+#[derive(Default)]
+pub struct HistoricalDataPacer {
+    /// Time and contract of the requests made within the last pacing window
+    requests: Mutex<VecDeque<(Instant, String)>>,
+}
+
+impl HistoricalDataPacer {
+    /// Wait until a historical data request for `contract_key` is allowed and record it
+    pub async fn acquire(&self, contract_key: &str) {
+        loop {
+            let wait = {
+                let mut requests = self.requests.lock().await;
+                let now = Instant::now();
+                match next_request_delay(&mut requests, contract_key, now) {
+                    None => {
+                        requests.push_back((now, contract_key.to_string()));
+                        return;
+                    }
+                    Some(wait) => wait,
+                }
+            };
+            debug!(
+                "Delaying historical data request for {} by {:?} to respect IB pacing limits",
+                contract_key, wait
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Returns how long a request for `contract_key` has to wait, or `None` if it can be made `now`
+fn next_request_delay(
+    requests: &mut VecDeque<(Instant, String)>,
+    contract_key: &str,
+    now: Instant,
+) -> Option<Duration> {
+    while let Some((at, _)) = requests.front() {
+        if now.duration_since(*at) >= HISTORICAL_PACING_WINDOW {
+            requests.pop_front();
+        } else {
+            break;
+        }
+    }
+    if requests.len() >= HISTORICAL_PACING_MAX_REQUESTS {
+        let (oldest, _) = requests.front()?;
+        return Some(HISTORICAL_PACING_WINDOW - now.duration_since(*oldest));
+    }
+    let same_contract: Vec<Instant> = requests
+        .iter()
+        .filter(|(at, key)| {
+            key == contract_key && now.duration_since(*at) < HISTORICAL_PACING_SAME_CONTRACT_WINDOW
+        })
+        .map(|(at, _)| *at)
+        .collect();
+    if same_contract.len() >= HISTORICAL_PACING_SAME_CONTRACT_MAX_REQUESTS {
+        return Some(HISTORICAL_PACING_SAME_CONTRACT_WINDOW - now.duration_since(same_contract[0]));
+    }
+    None
+}
+
+#[derive(Debug, Error)]
+pub enum IbConnectionError {
+    #[error("Failed to connect: {0}")]
+    Connect(String),
+    #[error("Invalid reconnection policy: {0}")]
+    InvalidReconnectPolicy(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reconnect_policy_backoff_doubles_up_to_max() {
+        let policy = ReconnectPolicy {
+            heartbeat_interval: Duration::from_secs(10),
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+        };
+        let mut backoff = policy.initial_backoff;
+        let mut backoffs = Vec::new();
+        for _ in 0..4 {
+            backoff = policy.next_backoff(backoff);
+            backoffs.push(backoff.as_secs());
+        }
+        assert_eq!(backoffs, vec![2, 4, 5, 5]);
+    }
+
+    #[test]
+    fn test_reconnect_policy_rejects_max_backoff_below_initial() {
+        let config = |initial, max| IbConnectionConfig {
+            name: "ib".into(),
+            address: "127.0.0.1:4002".into(),
+            client_id: 1,
+            heartbeat_interval_secs: 10,
+            reconnect_initial_backoff_secs: initial,
+            reconnect_max_backoff_secs: max,
+        };
+
+        assert!(ReconnectPolicy::from_config(&config(5, 5)).is_ok());
+        assert!(matches!(
+            ReconnectPolicy::from_config(&config(5, 2)),
+            Err(IbConnectionError::InvalidReconnectPolicy(_))
+        ));
+    }
+
+    #[test]
+    fn test_next_request_delay_limits_same_contract_burst() {
+        let mut requests = VecDeque::new();
+        let start = Instant::now();
+        for i in 0..6 {
+            let now = start + Duration::from_millis(100 * i);
+            assert_eq!(next_request_delay(&mut requests, "AAPL", now), None);
+            requests.push_back((now, "AAPL".to_string()));
+        }
+        let now = start + Duration::from_millis(600);
+        assert_eq!(
+            next_request_delay(&mut requests, "AAPL", now),
+            Some(Duration::from_millis(1400))
+        );
+        // Other contracts are not affected
+        assert_eq!(next_request_delay(&mut requests, "MSFT", now), None);
+    }
+
+    #[test]
+    fn test_next_request_delay_limits_requests_per_window() {
+        let mut requests = VecDeque::new();
+        let start = Instant::now();
+        for i in 0..60 {
+            requests.push_back((start + Duration::from_secs(i), format!("SYM{i}")));
+        }
+        let now = start + Duration::from_secs(100);
+        assert_eq!(
+            next_request_delay(&mut requests, "AAPL", now),
+            Some(Duration::from_secs(500))
+        );
+        // Once the oldest request leaves the window a new one is allowed
+        let now = start + Duration::from_secs(600);
+        assert_eq!(next_request_delay(&mut requests, "AAPL", now), None);
+        assert_eq!(requests.len(), 59);
+    }
+}
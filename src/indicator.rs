@@ -0,0 +1,141 @@
+use crate::data_feed::MarketData;
+use std::collections::VecDeque;
+
+pub mod adx;
+pub mod atr;
+pub mod bollinger;
+pub mod donchian;
+pub mod macd;
+pub mod moving_average;
+pub mod rate_of_change;
+pub mod regression;
+pub mod rolling_stats;
+pub mod rsi;
+pub mod stochastic;
+pub mod vwap;
+
+/// A streaming indicator: every input updates it in constant time and memory
+pub trait Indicator {
+    type Input;
+    type Output;
+    /// Add the next input. Returns the new value once enough inputs were seen.
+    fn update(&mut self, input: Self::Input) -> Option<Self::Output>;
+    /// The value after the last input, if there were enough of them
+    fn value(&self) -> Option<Self::Output>;
+    /// Forget all inputs, e.g. at the start of a new session
+    fn reset(&mut self);
+}
+
+/// The parts of a bar that indicators on highs and lows need
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Candle {
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub volume: f64,
+}
+
+impl From<&MarketData> for Candle {
+    fn from(data: &MarketData) -> Self {
+        Self {
+            high: data.high,
+            low: data.low,
+            close: data.price,
+            volume: data.volume,
+        }
+    }
+}
+
+/// The last `capacity` values of a series
+#[derive(Debug, Clone)]
+pub(crate) struct Window {
+    values: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl Window {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            values: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Add a value. Returns the value that dropped out of a full window.
+    pub(crate) fn push(&mut self, value: f64) -> Option<f64> {
+        let evicted = if self.is_full() {
+            self.values.pop_front()
+        } else {
+            None
+        };
+        self.values.push_back(value);
+        evicted
+    }
+
+    pub(crate) fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.values.len() >= self.capacity
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.values.clear();
+    }
+}
+
+/// Wilder's smoothing (an EMA with `alpha = 1 / period`), seeded with the average of the
+/// first `period` values
+#[derive(Debug, Clone)]
+pub(crate) struct WilderAverage {
+    period: usize,
+    count: usize,
+    sum: f64,
+    value: Option<f64>,
+}
+
+impl WilderAverage {
+    pub(crate) fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            count: 0,
+            sum: 0.0,
+            value: None,
+        }
+    }
+
+    pub(crate) fn update(&mut self, input: f64) -> Option<f64> {
+        let period = self.period as f64;
+        self.value = match self.value {
+            Some(value) => Some((value * (period - 1.0) + input) / period),
+            None => {
+                self.count += 1;
+                self.sum += input;
+                (self.count == self.period).then(|| self.sum / period)
+            }
+        };
+        self.value
+    }
+
+    pub(crate) fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new(self.period);
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn assert_close(actual: Option<f64>, expected: f64) {
+    let actual = actual.unwrap_or_else(|| panic!("no value, expected {expected}"));
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "{actual} is not close to {expected}"
+    );
+}
@@ -0,0 +1,110 @@
+use super::{Candle, Indicator, WilderAverage, atr::true_range};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AdxValue {
+    /// Strength of the trend (0 to 100), regardless of its direction
+    pub adx: f64,
+    pub plus_di: f64,
+    pub minus_di: f64,
+}
+
+/// Average directional index with the directional indicators
+#[derive(Debug, Clone)]
+pub struct Adx {
+    plus_dm: WilderAverage,
+    minus_dm: WilderAverage,
+    true_range: WilderAverage,
+    adx: WilderAverage,
+    last: Option<Candle>,
+    value: Option<AdxValue>,
+}
+
+impl Adx {
+    pub fn new(period: usize) -> Self {
+        Self {
+            plus_dm: WilderAverage::new(period),
+            minus_dm: WilderAverage::new(period),
+            true_range: WilderAverage::new(period),
+            adx: WilderAverage::new(period),
+            last: None,
+            value: None,
+        }
+    }
+}
+
+impl Indicator for Adx {
+    type Input = Candle;
+    type Output = AdxValue;
+
+    fn update(&mut self, candle: Candle) -> Option<AdxValue> {
+        let last = self.last.replace(candle)?;
+        let up = candle.high - last.high;
+        let down = last.low - candle.low;
+        let plus_dm = if up > down && up > 0.0 { up } else { 0.0 };
+        let minus_dm = if down > up && down > 0.0 { down } else { 0.0 };
+        let plus_dm = self.plus_dm.update(plus_dm);
+        let minus_dm = self.minus_dm.update(minus_dm);
+        let true_range = self
+            .true_range
+            .update(true_range(&candle, Some(last.close)));
+
+        let (Some(plus_dm), Some(minus_dm), Some(true_range)) = (plus_dm, minus_dm, true_range)
+        else {
+            return None;
+        };
+        let (plus_di, minus_di) = if true_range > 0.0 {
+            (100.0 * plus_dm / true_range, 100.0 * minus_dm / true_range)
+        } else {
+            (0.0, 0.0)
+        };
+        let di_sum = plus_di + minus_di;
+        let dx = if di_sum > 0.0 {
+            100.0 * (plus_di - minus_di).abs() / di_sum
+        } else {
+            0.0
+        };
+        self.value = self.adx.update(dx).map(|adx| AdxValue {
+            adx,
+            plus_di,
+            minus_di,
+        });
+        self.value
+    }
+
+    fn value(&self) -> Option<AdxValue> {
+        self.value
+    }
+
+    fn reset(&mut self) {
+        self.plus_dm.reset();
+        self.minus_dm.reset();
+        self.true_range.reset();
+        self.adx.reset();
+        self.last = None;
+        self.value = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_adx_of_steady_uptrend() {
+        let mut adx = Adx::new(2);
+        let candles = (0..4).map(|i| Candle {
+            high: i as f64 + 1.0,
+            low: i as f64,
+            close: i as f64 + 0.5,
+            volume: 0.0,
+        });
+        let values: Vec<_> = candles.map(|candle| adx.update(candle)).collect();
+        assert!(values[..3].iter().all(Option::is_none));
+        let value = values[3].unwrap();
+        assert_close(Some(value.adx), 100.0);
+        // Moves of 1 with true ranges of 1.5
+        assert_close(Some(value.plus_di), 100.0 / 1.5);
+        assert_close(Some(value.minus_di), 0.0);
+    }
+}
@@ -0,0 +1,72 @@
+use super::{Candle, Indicator, WilderAverage};
+
+/// Average true range with Wilder's smoothing
+#[derive(Debug, Clone)]
+pub struct Atr {
+    average: WilderAverage,
+    last_close: Option<f64>,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        Self {
+            average: WilderAverage::new(period),
+            last_close: None,
+        }
+    }
+}
+
+/// The range of a bar, including the gap from the previous close
+pub(crate) fn true_range(candle: &Candle, last_close: Option<f64>) -> f64 {
+    let range = candle.high - candle.low;
+    match last_close {
+        Some(close) => range
+            .max((candle.high - close).abs())
+            .max((candle.low - close).abs()),
+        None => range,
+    }
+}
+
+impl Indicator for Atr {
+    type Input = Candle;
+    type Output = f64;
+
+    fn update(&mut self, candle: Candle) -> Option<f64> {
+        let true_range = true_range(&candle, self.last_close);
+        self.last_close = Some(candle.close);
+        self.average.update(true_range)
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.average.value()
+    }
+
+    fn reset(&mut self) {
+        self.average.reset();
+        self.last_close = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    fn candle(high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            high,
+            low,
+            close,
+            volume: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_atr() {
+        let mut atr = Atr::new(2);
+        assert_eq!(atr.update(candle(10.0, 8.0, 9.0)), None);
+        // The gap up from the close of 9 makes the true range 3
+        assert_close(atr.update(candle(12.0, 10.0, 11.0)), 2.5);
+        assert_close(atr.update(candle(11.0, 10.0, 10.5)), 1.75);
+    }
+}
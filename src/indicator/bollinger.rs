@@ -0,0 +1,80 @@
+use super::{
+    Indicator,
+    rolling_stats::{RollingStats, RollingStatsValue},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BollingerBandsValue {
+    pub lower: f64,
+    pub middle: f64,
+    pub upper: f64,
+    /// Width of the bands relative to the middle band. Low values are a squeeze.
+    pub bandwidth: f64,
+}
+
+/// A moving average with bands `multiplier` standard deviations above and below it
+#[derive(Debug, Clone)]
+pub struct BollingerBands {
+    stats: RollingStats,
+    multiplier: f64,
+}
+
+impl BollingerBands {
+    pub fn new(period: usize, multiplier: f64) -> Self {
+        Self {
+            stats: RollingStats::new(period),
+            multiplier,
+        }
+    }
+
+    fn bands(&self, stats: RollingStatsValue) -> BollingerBandsValue {
+        let offset = self.multiplier * stats.std_dev;
+        BollingerBandsValue {
+            lower: stats.mean - offset,
+            middle: stats.mean,
+            upper: stats.mean + offset,
+            bandwidth: if stats.mean != 0.0 {
+                2.0 * offset / stats.mean
+            } else {
+                0.0
+            },
+        }
+    }
+}
+
+impl Indicator for BollingerBands {
+    type Input = f64;
+    type Output = BollingerBandsValue;
+
+    fn update(&mut self, input: f64) -> Option<BollingerBandsValue> {
+        self.stats.update(input).map(|stats| self.bands(stats))
+    }
+
+    fn value(&self) -> Option<BollingerBandsValue> {
+        self.stats.value().map(|stats| self.bands(stats))
+    }
+
+    fn reset(&mut self) {
+        self.stats.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_bollinger_bands() {
+        let mut bands = BollingerBands::new(4, 2.0);
+        for input in [2.0, 4.0, 4.0] {
+            assert_eq!(bands.update(input), None);
+        }
+        let value = bands.update(6.0).unwrap();
+        let std_dev = 2.0_f64.sqrt();
+        assert_close(Some(value.middle), 4.0);
+        assert_close(Some(value.upper), 4.0 + 2.0 * std_dev);
+        assert_close(Some(value.lower), 4.0 - 2.0 * std_dev);
+        assert_close(Some(value.bandwidth), std_dev);
+    }
+}
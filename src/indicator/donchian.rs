@@ -0,0 +1,117 @@
+use super::{Candle, Indicator};
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DonchianValue {
+    /// The highest high of the period
+    pub upper: f64,
+    /// The lowest low of the period
+    pub lower: f64,
+}
+
+impl DonchianValue {
+    pub fn middle(&self) -> f64 {
+        (self.upper + self.lower) / 2.0
+    }
+}
+
+/// Donchian channel: the highest high and the lowest low of the last `period` bars. The
+/// extremes are tracked with monotonic queues, so updates take amortized constant time.
+#[derive(Debug, Clone)]
+pub struct Donchian {
+    period: usize,
+    /// Indexes and values of the highs that can still become the highest
+    highs: VecDeque<(usize, f64)>,
+    lows: VecDeque<(usize, f64)>,
+    count: usize,
+}
+
+impl Donchian {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            highs: VecDeque::new(),
+            lows: VecDeque::new(),
+            count: 0,
+        }
+    }
+}
+
+/// Adds `value` to a monotonic queue that keeps the extreme in front, where `dominates` tells
+/// whether the first value is at least as extreme as the second one
+fn push_extreme(
+    queue: &mut VecDeque<(usize, f64)>,
+    index: usize,
+    value: f64,
+    period: usize,
+    dominates: impl Fn(f64, f64) -> bool,
+) {
+    while queue
+        .back()
+        .is_some_and(|(_, last)| dominates(value, *last))
+    {
+        queue.pop_back();
+    }
+    queue.push_back((index, value));
+    while queue
+        .front()
+        .is_some_and(|(first, _)| first + period <= index)
+    {
+        queue.pop_front();
+    }
+}
+
+impl Indicator for Donchian {
+    type Input = Candle;
+    type Output = DonchianValue;
+
+    fn update(&mut self, candle: Candle) -> Option<DonchianValue> {
+        let index = self.count;
+        self.count += 1;
+        push_extreme(&mut self.highs, index, candle.high, self.period, |a, b| {
+            a >= b
+        });
+        push_extreme(&mut self.lows, index, candle.low, self.period, |a, b| {
+            a <= b
+        });
+        self.value()
+    }
+
+    fn value(&self) -> Option<DonchianValue> {
+        if self.count < self.period {
+            return None;
+        }
+        Some(DonchianValue {
+            upper: self.highs.front()?.1,
+            lower: self.lows.front()?.1,
+        })
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.period);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_donchian() {
+        let mut donchian = Donchian::new(3);
+        let candle = |high, low| Candle {
+            high,
+            low,
+            close: low,
+            volume: 0.0,
+        };
+        assert_eq!(donchian.update(candle(10.0, 5.0)), None);
+        assert_eq!(donchian.update(candle(8.0, 6.0)), None);
+        let value = donchian.update(candle(9.0, 7.0)).unwrap();
+        assert_eq!((value.upper, value.lower), (10.0, 5.0));
+        // The first bar dropped out
+        let value = donchian.update(candle(7.0, 6.5)).unwrap();
+        assert_eq!((value.upper, value.lower), (9.0, 6.0));
+        assert_eq!(value.middle(), 7.5);
+    }
+}
@@ -0,0 +1,81 @@
+use super::{Indicator, moving_average::Ema};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MacdValue {
+    /// Fast EMA minus slow EMA
+    pub macd: f64,
+    /// EMA of `macd`
+    pub signal: f64,
+    /// `macd` minus `signal`
+    pub histogram: f64,
+}
+
+/// Moving average convergence/divergence, e.g. with periods 12, 26 and 9
+#[derive(Debug, Clone)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+    value: Option<MacdValue>,
+}
+
+impl Macd {
+    pub fn new(fast_period: usize, slow_period: usize, signal_period: usize) -> Self {
+        Self {
+            fast: Ema::new(fast_period),
+            slow: Ema::new(slow_period),
+            signal: Ema::new(signal_period),
+            value: None,
+        }
+    }
+}
+
+impl Indicator for Macd {
+    type Input = f64;
+    type Output = MacdValue;
+
+    fn update(&mut self, input: f64) -> Option<MacdValue> {
+        let fast = self.fast.update(input);
+        let slow = self.slow.update(input);
+        if let (Some(fast), Some(slow)) = (fast, slow) {
+            let macd = fast - slow;
+            self.value = self.signal.update(macd).map(|signal| MacdValue {
+                macd,
+                signal,
+                histogram: macd - signal,
+            });
+        }
+        self.value
+    }
+
+    fn value(&self) -> Option<MacdValue> {
+        self.value
+    }
+
+    fn reset(&mut self) {
+        self.fast.reset();
+        self.slow.reset();
+        self.signal.reset();
+        self.value = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_macd() {
+        // An EMA of period 1 is the input itself
+        let mut macd = Macd::new(1, 2, 2);
+        assert_eq!(macd.update(1.0), None);
+        // Slow EMA 1.5, MACD 0.5
+        assert_eq!(macd.update(2.0), None);
+        // Slow EMA 1.5 + 2/3 * 2.5, MACD 0.8333, signal (0.5 + 0.8333) / 2
+        let value = macd.update(4.0).unwrap();
+        assert_close(Some(value.macd), 4.0 - (1.5 + 2.5 * 2.0 / 3.0));
+        assert_close(Some(value.signal), (0.5 + value.macd) / 2.0);
+        assert_close(Some(value.histogram), value.macd - value.signal);
+    }
+}
@@ -0,0 +1,168 @@
+use super::{Indicator, Window};
+
+/// Simple moving average
+#[derive(Debug, Clone)]
+pub struct Sma {
+    window: Window,
+    sum: f64,
+}
+
+impl Sma {
+    pub fn new(period: usize) -> Self {
+        Self {
+            window: Window::new(period.max(1)),
+            sum: 0.0,
+        }
+    }
+}
+
+impl Indicator for Sma {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        self.sum += input - self.window.push(input).unwrap_or(0.0);
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.window
+            .is_full()
+            .then(|| self.sum / self.window.len() as f64)
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+        self.sum = 0.0;
+    }
+}
+
+/// Exponential moving average with `alpha = 2 / (period + 1)`, seeded with the SMA of the
+/// first `period` inputs
+#[derive(Debug, Clone)]
+pub struct Ema {
+    period: usize,
+    alpha: f64,
+    seed: Sma,
+    value: Option<f64>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        let period = period.max(1);
+        Self {
+            period,
+            alpha: 2.0 / (period as f64 + 1.0),
+            seed: Sma::new(period),
+            value: None,
+        }
+    }
+}
+
+impl Indicator for Ema {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        self.value = match self.value {
+            Some(value) => Some(value + self.alpha * (input - value)),
+            None => self.seed.update(input),
+        };
+        self.value
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.period);
+    }
+}
+
+/// Linearly weighted moving average: the latest input has weight `period`, the oldest 1
+#[derive(Debug, Clone)]
+pub struct Wma {
+    window: Window,
+    /// Sum of the inputs in the window
+    sum: f64,
+    /// Sum of the weighted inputs in the window
+    weighted_sum: f64,
+}
+
+impl Wma {
+    pub fn new(period: usize) -> Self {
+        Self {
+            window: Window::new(period.max(1)),
+            sum: 0.0,
+            weighted_sum: 0.0,
+        }
+    }
+}
+
+impl Indicator for Wma {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        // Once the window is full, every input in it loses one weight (the oldest drops out)
+        if self.window.is_full() {
+            self.weighted_sum -= self.sum;
+        }
+        let evicted = self.window.push(input).unwrap_or(0.0);
+        self.weighted_sum += self.window.len() as f64 * input;
+        self.sum += input - evicted;
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        let n = self.window.len() as f64;
+        self.window
+            .is_full()
+            .then(|| self.weighted_sum / (n * (n + 1.0) / 2.0))
+    }
+
+    fn reset(&mut self) {
+        self.window.clear();
+        self.sum = 0.0;
+        self.weighted_sum = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_sma() {
+        let mut sma = Sma::new(3);
+        assert_eq!(sma.update(1.0), None);
+        assert_eq!(sma.update(2.0), None);
+        assert_close(sma.update(3.0), 2.0);
+        assert_close(sma.update(7.0), 4.0);
+        sma.reset();
+        assert_eq!(sma.update(1.0), None);
+    }
+
+    #[test]
+    fn test_ema() {
+        let mut ema = Ema::new(3);
+        ema.update(1.0);
+        ema.update(2.0);
+        assert_close(ema.update(3.0), 2.0);
+        // alpha = 0.5
+        assert_close(ema.update(6.0), 4.0);
+        assert_close(ema.update(2.0), 3.0);
+    }
+
+    #[test]
+    fn test_wma() {
+        let mut wma = Wma::new(3);
+        wma.update(1.0);
+        wma.update(2.0);
+        assert_close(wma.update(3.0), (1.0 + 4.0 + 9.0) / 6.0);
+        assert_close(wma.update(4.0), (2.0 + 6.0 + 12.0) / 6.0);
+        assert_close(wma.update(0.0), (3.0 + 8.0) / 6.0);
+    }
+}
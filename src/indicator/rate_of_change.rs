@@ -0,0 +1,54 @@
+use super::{Indicator, Window};
+
+/// The return since `period` inputs ago, as a fraction: `input / input_period_ago - 1`
+#[derive(Debug, Clone)]
+pub struct RateOfChange {
+    window: Window,
+    value: Option<f64>,
+}
+
+impl RateOfChange {
+    pub fn new(period: usize) -> Self {
+        Self {
+            window: Window::new(period.max(1)),
+            value: None,
+        }
+    }
+}
+
+impl Indicator for RateOfChange {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        if let Some(past) = self.window.push(input) {
+            self.value = (past != 0.0).then(|| input / past - 1.0);
+        }
+        self.value
+    }
+
+    fn value(&self) -> Option<f64> {
+        self.value
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.window.capacity());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_rate_of_change() {
+        let mut roc = RateOfChange::new(2);
+        assert_eq!(roc.update(100.0), None);
+        assert_eq!(roc.update(105.0), None);
+        assert_close(roc.update(110.0), 0.1);
+        assert_close(roc.update(84.0), -0.2);
+        roc.reset();
+        assert_eq!(roc.value(), None);
+    }
+}
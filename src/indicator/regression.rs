@@ -0,0 +1,181 @@
+use super::{Indicator, Window};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RegressionValue {
+    pub slope: f64,
+    pub intercept: f64,
+}
+
+/// Ordinary least squares regression of `y` on `x` over the last `period` `(x, y)` inputs
+#[derive(Debug, Clone)]
+pub struct RollingRegression {
+    xs: Window,
+    ys: Window,
+    sum_x: f64,
+    sum_y: f64,
+    sum_xx: f64,
+    sum_xy: f64,
+}
+
+impl RollingRegression {
+    pub fn new(period: usize) -> Self {
+        // A line needs two points
+        let period = period.max(2);
+        Self {
+            xs: Window::new(period),
+            ys: Window::new(period),
+            sum_x: 0.0,
+            sum_y: 0.0,
+            sum_xx: 0.0,
+            sum_xy: 0.0,
+        }
+    }
+}
+
+impl Indicator for RollingRegression {
+    type Input = (f64, f64);
+    type Output = RegressionValue;
+
+    fn update(&mut self, (x, y): (f64, f64)) -> Option<RegressionValue> {
+        let evicted_x = self.xs.push(x).unwrap_or(0.0);
+        let evicted_y = self.ys.push(y).unwrap_or(0.0);
+        self.sum_x += x - evicted_x;
+        self.sum_y += y - evicted_y;
+        self.sum_xx += x * x - evicted_x * evicted_x;
+        self.sum_xy += x * y - evicted_x * evicted_y;
+        self.value()
+    }
+
+    /// None until the window is full, and while all `x` in it are the same
+    fn value(&self) -> Option<RegressionValue> {
+        if !self.xs.is_full() {
+            return None;
+        }
+        let n = self.xs.len() as f64;
+        let denominator = n * self.sum_xx - self.sum_x * self.sum_x;
+        if denominator.abs() <= f64::EPSILON * n * self.sum_xx {
+            return None;
+        }
+        let slope = (n * self.sum_xy - self.sum_x * self.sum_y) / denominator;
+        Some(RegressionValue {
+            slope,
+            intercept: (self.sum_y - slope * self.sum_x) / n,
+        })
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.xs.capacity());
+    }
+}
+
+/// The variance of the slope and intercept before the first input: nothing is known about them
+const INITIAL_VARIANCE: f64 = 1e4;
+
+/// Regression of `y` on `x` whose slope and intercept follow a random walk, estimated with a
+/// Kalman filter. `delta` (0 to 1) is how fast they may change: 0 is a regression over all
+/// inputs. `observation_variance` is the variance of `y` around the line.
+#[derive(Debug, Clone)]
+pub struct KalmanRegression {
+    delta: f64,
+    observation_variance: f64,
+    /// Slope and intercept
+    state: [f64; 2],
+    /// Covariance of the state
+    covariance: [[f64; 2]; 2],
+    count: usize,
+}
+
+impl KalmanRegression {
+    pub fn new(delta: f64, observation_variance: f64) -> Self {
+        Self {
+            delta,
+            observation_variance,
+            state: [0.0; 2],
+            covariance: [[INITIAL_VARIANCE, 0.0], [0.0, INITIAL_VARIANCE]],
+            count: 0,
+        }
+    }
+}
+
+impl Indicator for KalmanRegression {
+    type Input = (f64, f64);
+    type Output = RegressionValue;
+
+    fn update(&mut self, (x, y): (f64, f64)) -> Option<RegressionValue> {
+        let p = &mut self.covariance;
+        // Predict: the state may have drifted since the last input
+        let drift = self.delta / (1.0 - self.delta);
+        p[0][0] += drift;
+        p[1][1] += drift;
+
+        // Update with the observation y = slope * x + intercept
+        let h = [x, 1.0];
+        let ph = [
+            p[0][0] * h[0] + p[0][1] * h[1],
+            p[1][0] * h[0] + p[1][1] * h[1],
+        ];
+        let innovation_variance = h[0] * ph[0] + h[1] * ph[1] + self.observation_variance;
+        let gain = [ph[0] / innovation_variance, ph[1] / innovation_variance];
+        let error = y - (self.state[0] * h[0] + self.state[1] * h[1]);
+        self.state[0] += gain[0] * error;
+        self.state[1] += gain[1] * error;
+        // P = P - K H P, where H P is the transpose of P H
+        for (i, row) in p.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value -= gain[i] * ph[j];
+            }
+        }
+        self.count += 1;
+        self.value()
+    }
+
+    /// None before the second input: a single point does not determine a line
+    fn value(&self) -> Option<RegressionValue> {
+        (self.count >= 2).then_some(RegressionValue {
+            slope: self.state[0],
+            intercept: self.state[1],
+        })
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.delta, self.observation_variance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_rolling_regression() {
+        let mut regression = RollingRegression::new(3);
+        assert_eq!(regression.update((1.0, 3.0)), None);
+        assert_eq!(regression.update((2.0, 5.0)), None);
+        let value = regression.update((3.0, 7.0)).unwrap();
+        assert_close(Some(value.slope), 2.0);
+        assert_close(Some(value.intercept), 1.0);
+        // The first point dropped out
+        let value = regression.update((4.0, 6.0)).unwrap();
+        assert_close(Some(value.slope), 0.5);
+        assert_close(Some(value.intercept), 4.5);
+        // No slope without different x
+        for _ in 0..3 {
+            regression.update((4.0, 6.0));
+        }
+        assert_eq!(regression.value(), None);
+    }
+
+    #[test]
+    fn test_kalman_regression_converges() {
+        let mut regression = KalmanRegression::new(1e-4, 1e-3);
+        let mut value = None;
+        for i in 0..500 {
+            let x = 100.0 + 10.0 * (i as f64 / 10.0).sin();
+            value = regression.update((x, 2.0 * x + 5.0));
+        }
+        let value = value.unwrap();
+        assert!((value.slope - 2.0).abs() < 0.01, "{value:?}");
+        assert!((value.intercept - 5.0).abs() < 0.5, "{value:?}");
+    }
+}
@@ -0,0 +1,89 @@
+use super::{Indicator, Window};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RollingStatsValue {
+    pub mean: f64,
+    /// Population standard deviation
+    pub std_dev: f64,
+    /// Distance of the latest input from the mean, in standard deviations. 0 without variance.
+    pub z_score: f64,
+}
+
+/// Mean, standard deviation and z-score over the last `period` inputs
+#[derive(Debug, Clone)]
+pub struct RollingStats {
+    window: Window,
+    sum: f64,
+    sum_of_squares: f64,
+    last: f64,
+}
+
+impl RollingStats {
+    pub fn new(period: usize) -> Self {
+        Self {
+            window: Window::new(period.max(1)),
+            sum: 0.0,
+            sum_of_squares: 0.0,
+            last: 0.0,
+        }
+    }
+}
+
+impl Indicator for RollingStats {
+    type Input = f64;
+    type Output = RollingStatsValue;
+
+    fn update(&mut self, input: f64) -> Option<RollingStatsValue> {
+        let evicted = self.window.push(input).unwrap_or(0.0);
+        self.sum += input - evicted;
+        self.sum_of_squares += input * input - evicted * evicted;
+        self.last = input;
+        self.value()
+    }
+
+    fn value(&self) -> Option<RollingStatsValue> {
+        if !self.window.is_full() {
+            return None;
+        }
+        let n = self.window.len() as f64;
+        let mean = self.sum / n;
+        // Rounding errors of the running sums must not make the variance negative
+        let std_dev = (self.sum_of_squares / n - mean * mean).max(0.0).sqrt();
+        let z_score = if std_dev > f64::EPSILON * mean.abs().max(1.0) {
+            (self.last - mean) / std_dev
+        } else {
+            0.0
+        };
+        Some(RollingStatsValue {
+            mean,
+            std_dev,
+            z_score,
+        })
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.window.capacity());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_rolling_stats() {
+        let mut stats = RollingStats::new(4);
+        for input in [2.0, 4.0, 4.0] {
+            assert_eq!(stats.update(input), None);
+        }
+        let value = stats.update(6.0).unwrap();
+        assert_close(Some(value.mean), 4.0);
+        assert_close(Some(value.std_dev), 2.0_f64.sqrt());
+        assert_close(Some(value.z_score), 2.0 / 2.0_f64.sqrt());
+
+        // Without variance
+        let value = [5.0; 4].map(|input| stats.update(input)).last().copied();
+        assert_eq!(value.flatten().unwrap().z_score, 0.0);
+    }
+}
@@ -0,0 +1,68 @@
+use super::{Indicator, WilderAverage};
+
+/// Relative strength index (0 to 100) with Wilder's smoothing of gains and losses
+#[derive(Debug, Clone)]
+pub struct Rsi {
+    gains: WilderAverage,
+    losses: WilderAverage,
+    last_input: Option<f64>,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        Self {
+            gains: WilderAverage::new(period),
+            losses: WilderAverage::new(period),
+            last_input: None,
+        }
+    }
+}
+
+impl Indicator for Rsi {
+    type Input = f64;
+    type Output = f64;
+
+    fn update(&mut self, input: f64) -> Option<f64> {
+        if let Some(last_input) = self.last_input.replace(input) {
+            let change = input - last_input;
+            self.gains.update(change.max(0.0));
+            self.losses.update((-change).max(0.0));
+        }
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        let gain = self.gains.value()?;
+        let loss = self.losses.value()?;
+        Some(match (gain, loss) {
+            (gain, loss) if loss > 0.0 => 100.0 - 100.0 / (1.0 + gain / loss),
+            (gain, _) if gain > 0.0 => 100.0,
+            // No change at all
+            _ => 50.0,
+        })
+    }
+
+    fn reset(&mut self) {
+        self.gains.reset();
+        self.losses.reset();
+        self.last_input = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_rsi() {
+        let mut rsi = Rsi::new(2);
+        assert_eq!(rsi.update(1.0), None);
+        assert_eq!(rsi.update(2.0), None);
+        assert_close(rsi.update(3.0), 100.0);
+        // Average gain and loss are both 0.5
+        assert_close(rsi.update(2.0), 50.0);
+        // Average gain 0.25, average loss 1.25
+        assert_close(rsi.update(0.0), 100.0 - 100.0 / 1.2);
+    }
+}
@@ -0,0 +1,83 @@
+use super::{Candle, Indicator, donchian::Donchian, moving_average::Sma};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StochasticValue {
+    /// Position of the close in the range of the last `k_period` bars (0 to 100)
+    pub k: f64,
+    /// SMA of `k`
+    pub d: f64,
+}
+
+/// Stochastic oscillator
+#[derive(Debug, Clone)]
+pub struct Stochastic {
+    k_period: usize,
+    d_period: usize,
+    range: Donchian,
+    d: Sma,
+    value: Option<StochasticValue>,
+}
+
+impl Stochastic {
+    pub fn new(k_period: usize, d_period: usize) -> Self {
+        Self {
+            k_period,
+            d_period,
+            range: Donchian::new(k_period),
+            d: Sma::new(d_period),
+            value: None,
+        }
+    }
+}
+
+impl Indicator for Stochastic {
+    type Input = Candle;
+    type Output = StochasticValue;
+
+    fn update(&mut self, candle: Candle) -> Option<StochasticValue> {
+        let range = self.range.update(candle)?;
+        let k = if range.upper > range.lower {
+            100.0 * (candle.close - range.lower) / (range.upper - range.lower)
+        } else {
+            50.0
+        };
+        self.value = self.d.update(k).map(|d| StochasticValue { k, d });
+        self.value
+    }
+
+    fn value(&self) -> Option<StochasticValue> {
+        self.value
+    }
+
+    fn reset(&mut self) {
+        *self = Self::new(self.k_period, self.d_period);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    fn candle(high: f64, low: f64, close: f64) -> Candle {
+        Candle {
+            high,
+            low,
+            close,
+            volume: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_stochastic() {
+        let mut stochastic = Stochastic::new(3, 2);
+        assert_eq!(stochastic.update(candle(10.0, 5.0, 6.0)), None);
+        assert_eq!(stochastic.update(candle(8.0, 6.0, 7.0)), None);
+        // Range 5..10
+        assert_eq!(stochastic.update(candle(9.0, 7.0, 8.0)), None);
+        // The first bar dropped out: range 6..9
+        let value = stochastic.update(candle(7.0, 6.5, 6.75)).unwrap();
+        assert_close(Some(value.k), 25.0);
+        assert_close(Some(value.d), (60.0 + 25.0) / 2.0);
+    }
+}
@@ -0,0 +1,57 @@
+use super::{Candle, Indicator};
+
+/// Volume weighted average of the typical prices (`(high + low + close) / 3`) since the start
+/// or the last reset, e.g. of the session
+#[derive(Debug, Clone, Default)]
+pub struct Vwap {
+    value_sum: f64,
+    volume_sum: f64,
+}
+
+impl Vwap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Indicator for Vwap {
+    type Input = Candle;
+    type Output = f64;
+
+    fn update(&mut self, candle: Candle) -> Option<f64> {
+        let typical_price = (candle.high + candle.low + candle.close) / 3.0;
+        self.value_sum += typical_price * candle.volume;
+        self.volume_sum += candle.volume;
+        self.value()
+    }
+
+    fn value(&self) -> Option<f64> {
+        (self.volume_sum > 0.0).then(|| self.value_sum / self.volume_sum)
+    }
+
+    fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indicator::assert_close;
+
+    #[test]
+    fn test_vwap() {
+        let mut vwap = Vwap::new();
+        let candle = |price: f64, volume: f64| Candle {
+            high: price,
+            low: price,
+            close: price,
+            volume,
+        };
+        assert_eq!(vwap.update(candle(10.0, 0.0)), None);
+        assert_close(vwap.update(candle(10.0, 100.0)), 10.0);
+        assert_close(vwap.update(candle(13.0, 200.0)), 12.0);
+        vwap.reset();
+        assert_eq!(vwap.value(), None);
+    }
+}
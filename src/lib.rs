@@ -1,7 +1,13 @@
+pub mod backtest;
 pub mod broker;
+pub mod calendar;
 pub mod config;
+pub mod corporate_actions;
 pub mod data_feed;
+pub mod export;
 pub mod factory;
+pub mod ib_connection;
+pub mod indicator;
 pub mod position_sizer;
 pub mod strategy;
 pub mod types;
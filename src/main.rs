@@ -1,18 +1,33 @@
-use clap::Parser;
-use rusty_trader::config::BotConfig;
-use rusty_trader::factory::build_strategies;
-use tracing::Level;
+use clap::{Parser, Subcommand};
+use rusty_trader::config::{BotConfig, ExportConfig};
+use rusty_trader::data_feed::replay_pacer::ReplayControl;
+use rusty_trader::export::export_historical_data;
+use rusty_trader::factory::build_bot;
+use std::collections::HashMap;
+use tracing::{Level, info, warn};
 use tracing_subscriber::fmt;
 
 #[derive(Parser, Debug)]
-#[clap(author, version, about, long_about = None)]
+#[clap(author, version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
     /// The log verbosity level
     #[clap(short, long)]
     pub verbosity: Level,
     /// The path to the config file
-    #[clap(short, long)]
-    pub config: String,
+    #[clap(short, long, required = true)]
+    pub config: Option<String>,
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Download historical bars from Interactive Brokers into CSV files for backtesting
+    ExportHistorical {
+        /// The path to the export config file
+        #[clap(short, long)]
+        config: String,
+    },
 }
 
 #[tokio::main]
@@ -24,13 +39,24 @@ async fn main() {
     let subscriber = fmt().with_max_level(args.verbosity).finish();
     tracing::subscriber::set_global_default(subscriber).expect("Failed to set subscriber");
 
+    if let Some(Command::ExportHistorical { config }) = args.command {
+        let config = ExportConfig::deserialize_from_file(&config).expect("Failed to read config");
+        export_historical_data(config)
+            .await
+            .expect("Failed to export historical data");
+        return;
+    }
+
     // Read config file
-    let config = BotConfig::deserialize_from_file(&args.config).expect("Failed to read config");
+    let config_path = args.config.expect("The config path is required");
+    let config = BotConfig::deserialize_from_file(&config_path).expect("Failed to read config");
 
     // Build strategies from config
-    let strategies = build_strategies(config)
-        .await
-        .expect("Failed to build strategies");
+    let bot = build_bot(config).await.expect("Failed to build strategies");
+    if !bot.replay_controls.is_empty() {
+        control_replays_from_stdin(bot.replay_controls);
+    }
+    let strategies = bot.strategies;
 
     // Fire up strategies
     let mut handles = Vec::new();
@@ -41,3 +67,48 @@ async fn main() {
     futures::future::join_all(handles).await;
     // TODO: Add graceful cleanup
 }
+
+/// Reads `pause`, `resume`, `step` and `speed <x>` commands from stdin, each optionally
+/// followed by the name of a feed (all paced feeds otherwise)
+fn control_replays_from_stdin(controls: HashMap<String, ReplayControl>) {
+    info!("Replay commands: pause, resume, step, speed <x> (optionally followed by a feed name)");
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines().map_while(Result::ok) {
+            if let Err(err) = apply_replay_command(&line, &controls) {
+                warn!("Invalid replay command '{}': {}", line, err);
+            }
+        }
+    });
+}
+
+fn apply_replay_command(
+    line: &str,
+    controls: &HashMap<String, ReplayControl>,
+) -> Result<(), String> {
+    let mut words = line.split_whitespace();
+    let command = words.next().ok_or("empty command")?;
+    let speed = match command {
+        "speed" => Some(
+            words
+                .next()
+                .ok_or("missing speed")?
+                .parse::<f64>()
+                .map_err(|err| err.to_string())?,
+        ),
+        _ => None,
+    };
+    let targets: Vec<&ReplayControl> = match words.next() {
+        Some(feed) => vec![controls.get(feed).ok_or("unknown feed")?],
+        None => controls.values().collect(),
+    };
+    for control in targets {
+        match command {
+            "pause" => control.pause(),
+            "resume" => control.resume(),
+            "step" => control.step(),
+            "speed" => control.set_speed(speed),
+            _ => return Err("unknown command".to_string()),
+        }
+    }
+    Ok(())
+}
@@ -2,7 +2,7 @@ use super::PositionSizer;
 use crate::broker::AccountInfo;
 
 pub struct PercentOfEquitySizer {
-    name: String,
+    pub name: String,
     percent: f64,
 }
 
@@ -1,10 +1,184 @@
+use crate::{
+    broker::Broker,
+    data_feed::MarketData,
+    position_sizer::PositionSizer,
+    types::{Order, OrderId, OrderSide, OrderType},
+};
 use async_trait::async_trait;
+use std::collections::BTreeMap;
+use tracing::{error, info, warn};
 
+pub mod breakout;
+pub mod dca_rebalance;
+pub mod grid;
+pub mod momentum_rotation;
+pub mod pairs_trading;
 pub mod print;
+pub mod rsi_mean_reversion;
 pub mod sma_cross;
+pub mod trading_hours;
 
 #[async_trait]
 pub trait Strategy: Send + Sync {
     fn name(&self) -> &str;
     async fn run(&mut self);
 }
+
+/// Reserve the cash for `order` and place it. Returns whether it was placed.
+pub(crate) async fn submit_order(broker: &dyn Broker, order: Order, price: f64) -> bool {
+    submit_tracked_order(broker, order, price).await.is_some()
+}
+
+/// Reserve the cash for `order` and place it. Returns the id of the placed order, to follow
+/// its status. Orders reserve at their price (the limit price of limit orders), and market
+/// orders without one get `price`, so that their fills and cancellation release what they
+/// reserved.
+pub(crate) async fn submit_tracked_order(
+    broker: &dyn Broker,
+    mut order: Order,
+    price: f64,
+) -> Option<OrderId> {
+    let price = *order.price.get_or_insert(price);
+    if let Err(err) = broker.portfolio_pre_reserve_for_order(&order, price).await {
+        warn!("Order pre-check failed: {}", err);
+        return None;
+    }
+    match broker.place_order(&order).await {
+        Ok(order_id) => {
+            // TODO: Improve logging
+            info!(
+                "Placed {:?} at price {} as order {}",
+                order, price, order_id
+            );
+            Some(order_id)
+        }
+        Err(err) => {
+            error!("Failed to place order: {err}");
+            broker.portfolio_release_reservation(&order, price).await;
+            None
+        }
+    }
+}
+
+/// Place a market order at the price of `data`, with the quantity of `position_sizer`.
+/// Returns whether it was placed.
+pub(crate) async fn submit_sized_order(
+    broker: &dyn Broker,
+    position_sizer: &dyn PositionSizer,
+    strategy_name: &str,
+    side: OrderSide,
+    data: &MarketData,
+) -> bool {
+    let account_snapshot = broker.portfolio_snapshot().await;
+    let qty = position_sizer.size(&account_snapshot, data.price);
+    if qty == 0 {
+        info!("Sizer return qty=0; skipping order");
+        return false;
+    }
+    let order = Order {
+        symbol: data.symbol.clone(),
+        side,
+        qty,
+        price: Some(data.price),
+        order_type: OrderType::Market,
+        strategy_name: strategy_name.to_string(),
+    };
+    submit_order(broker, order, data.price).await
+}
+
+/// Close the position of the strategy in the symbol of `data` at market: sell a long position
+/// or buy back a short one. The shares of other strategies and of the account stay untouched.
+/// Returns whether there was a position and the order was placed.
+pub(crate) async fn close_position(
+    broker: &dyn Broker,
+    strategy_name: &str,
+    data: &MarketData,
+) -> bool {
+    close_position_at(broker, strategy_name, &data.symbol, data.price).await
+}
+
+/// Close the position of the strategy in `symbol` like [`close_position`], at `price` instead
+/// of the last price, e.g. at a stop the market traded through within a bar
+pub(crate) async fn close_position_at(
+    broker: &dyn Broker,
+    strategy_name: &str,
+    symbol: &str,
+    price: f64,
+) -> bool {
+    let position_qty = broker.strategy_position_qty(strategy_name, symbol).await;
+    if position_qty == 0 {
+        return false;
+    }
+    info!("{} closes {} {}", strategy_name, position_qty, symbol);
+    let side = if position_qty > 0 {
+        OrderSide::Sell
+    } else {
+        OrderSide::Buy
+    };
+    let order = Order {
+        symbol: symbol.to_string(),
+        side,
+        qty: position_qty.unsigned_abs() as u32,
+        price: Some(price),
+        order_type: OrderType::Market,
+        strategy_name: strategy_name.to_string(),
+    };
+    submit_order(broker, order, price).await
+}
+
+/// The quantity the strategy holds of each of `symbols`, negative for short positions
+pub(crate) async fn position_quantities(
+    broker: &dyn Broker,
+    strategy_name: &str,
+    symbols: impl IntoIterator<Item = &String>,
+) -> BTreeMap<String, i64> {
+    let mut quantities = BTreeMap::new();
+    for symbol in symbols {
+        let qty = broker.strategy_position_qty(strategy_name, symbol).await;
+        quantities.insert(symbol.clone(), qty);
+    }
+    quantities
+}
+
+/// Place the market orders that take the `holdings` of every symbol of `targets` to its
+/// target quantity at `prices`, sells before buys so that the buys can use their cash.
+/// Returns the number of orders placed.
+pub(crate) async fn submit_rebalance_orders(
+    broker: &dyn Broker,
+    strategy_name: &str,
+    holdings: &BTreeMap<String, i64>,
+    targets: &BTreeMap<String, i64>,
+    prices: &BTreeMap<String, f64>,
+) -> usize {
+    let mut orders: Vec<_> = targets
+        .iter()
+        .filter_map(|(symbol, target)| {
+            let delta = target - holdings.get(symbol).copied().unwrap_or(0);
+            let side = match delta {
+                0 => return None,
+                1.. => OrderSide::Buy,
+                _ => OrderSide::Sell,
+            };
+            Some(Order {
+                symbol: symbol.clone(),
+                side,
+                qty: delta.unsigned_abs() as u32,
+                price: Some(prices[symbol]),
+                order_type: OrderType::Market,
+                strategy_name: strategy_name.to_string(),
+            })
+        })
+        .collect();
+    orders.sort_by_key(|order| order.side == OrderSide::Buy);
+    if !orders.is_empty() {
+        info!("{} rebalances {} symbols", strategy_name, orders.len());
+    }
+    let mut placed = 0;
+    for order in orders {
+        let price = prices[&order.symbol];
+        if submit_order(broker, order, price).await {
+            placed += 1;
+        }
+    }
+    placed
+}
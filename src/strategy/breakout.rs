@@ -0,0 +1,265 @@
+use crate::{
+    broker::Broker,
+    data_feed::DataFeed,
+    indicator::{Candle, Indicator, atr::Atr, bollinger::BollingerBands, donchian::Donchian},
+    position_sizer::PositionSizer,
+    strategy::{
+        Strategy, close_position, close_position_at, submit_sized_order,
+        trading_hours::{TradingAction, TradingHours},
+    },
+    types::OrderSide,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+use tracing::{debug, error, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BreakoutParams {
+    /// Buy when the high exceeds the highest high of this many previous bars
+    pub entry_window: usize,
+    /// Sell when the low falls below the lowest low of this many previous bars
+    pub exit_window: usize,
+    /// The period of the ATR that stops and pyramiding are measured in
+    pub atr_period: usize,
+    /// The stop is this many ATRs below the last entry
+    pub stop_atr: f64,
+    /// Add a unit when the price rises this many ATRs above the last entry
+    pub pyramid_atr: f64,
+    /// The maximum number of units held, including the first one
+    pub max_units: u32,
+    /// Only enter breakouts out of a Bollinger squeeze
+    pub squeeze: Option<SqueezeParams>,
+}
+
+impl Default for BreakoutParams {
+    /// The rules of the turtles' 20 day system
+    fn default() -> Self {
+        Self {
+            entry_window: 20,
+            exit_window: 10,
+            atr_period: 20,
+            stop_atr: 2.0,
+            pyramid_atr: 0.5,
+            max_units: 4,
+            squeeze: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SqueezeParams {
+    /// The period of the Bollinger bands
+    pub period: usize,
+    /// The width of the bands in standard deviations
+    pub multiplier: f64,
+    /// The bands are squeezed while their bandwidth is at most this
+    pub max_bandwidth: f64,
+}
+
+impl Default for SqueezeParams {
+    fn default() -> Self {
+        Self {
+            period: 20,
+            multiplier: 2.0,
+            max_bandwidth: 0.1,
+        }
+    }
+}
+
+/// Buys breakouts of every symbol of its data feed above its Donchian channel and adds units
+/// (pyramids) as the price keeps rising. All units are sold at the ATR stop below the last
+/// entry or when the price breaks below the exit channel. Long only.
+pub struct BreakoutStrategy {
+    name: String,
+    data_feed: Box<dyn DataFeed>,
+    broker: Arc<dyn Broker>,
+    position_sizer: Box<dyn PositionSizer>,
+    params: BreakoutParams,
+    symbols: HashMap<String, SymbolState>,
+    trading_hours: Option<TradingHours>,
+}
+
+#[derive(Debug)]
+struct SymbolState {
+    entry_channel: Donchian,
+    exit_channel: Donchian,
+    atr: Atr,
+    bands: Option<BollingerBands>,
+    /// The number of units bought since the position was opened
+    units: u32,
+    last_entry: f64,
+    stop: f64,
+    /// The close before which the position was last flattened
+    flattened_for: Option<DateTime<Utc>>,
+}
+
+impl SymbolState {
+    fn new(params: &BreakoutParams) -> Self {
+        Self {
+            entry_channel: Donchian::new(params.entry_window),
+            exit_channel: Donchian::new(params.exit_window),
+            atr: Atr::new(params.atr_period),
+            bands: params
+                .squeeze
+                .map(|squeeze| BollingerBands::new(squeeze.period, squeeze.multiplier)),
+            units: 0,
+            last_entry: 0.0,
+            stop: 0.0,
+            flattened_for: None,
+        }
+    }
+}
+
+/// What the indicators of the bars before the current one say about it
+struct Signal {
+    breakout: bool,
+    exit: bool,
+    atr: Option<f64>,
+}
+
+impl BreakoutStrategy {
+    pub fn new(
+        name: String,
+        data_feed: Box<dyn DataFeed>,
+        broker: Arc<dyn Broker>,
+        position_sizer: Box<dyn PositionSizer>,
+        params: BreakoutParams,
+    ) -> Self {
+        Self {
+            name,
+            data_feed,
+            broker,
+            position_sizer,
+            params,
+            symbols: HashMap::new(),
+            trading_hours: None,
+        }
+    }
+
+    /// Only trade in the given sessions, and optionally flatten positions before the close
+    pub fn with_trading_hours(mut self, trading_hours: TradingHours) -> Self {
+        self.trading_hours = Some(trading_hours);
+        self
+    }
+}
+
+#[async_trait]
+impl Strategy for BreakoutStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn run(&mut self) {
+        while let Some(event) = self.data_feed.next_tick().await {
+            let data = match event {
+                Ok(data) => data,
+                Err(err) if err.is_fatal() => {
+                    error!("Stopping {}: {}", self.name, err);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{} waits for data: {}", self.name, err);
+                    continue;
+                }
+            };
+            self.broker.on_market_data(&data).await;
+            // The channels are calculated on traded prices only
+            if data.is_quote() {
+                continue;
+            }
+            let params = self.params;
+            let state = self
+                .symbols
+                .entry(data.symbol.clone())
+                .or_insert_with(|| SymbolState::new(&params));
+
+            // Breakouts are measured against the bars before the current one
+            let squeezed = match (&state.bands, params.squeeze) {
+                (Some(bands), Some(squeeze)) => bands
+                    .value()
+                    .is_some_and(|bands| bands.bandwidth <= squeeze.max_bandwidth),
+                _ => true,
+            };
+            let signal = Signal {
+                breakout: squeezed
+                    && state
+                        .entry_channel
+                        .value()
+                        .is_some_and(|channel| data.high > channel.upper),
+                exit: state
+                    .exit_channel
+                    .value()
+                    .is_some_and(|channel| data.low < channel.lower),
+                atr: state.atr.value(),
+            };
+            let candle = Candle::from(&data);
+            state.entry_channel.update(candle);
+            state.exit_channel.update(candle);
+            state.atr.update(candle);
+            if let Some(bands) = &mut state.bands {
+                bands.update(data.price);
+            }
+
+            if let Some(hours) = &self.trading_hours {
+                match hours.action(data.timestamp, &mut state.flattened_for) {
+                    TradingAction::Trade => {}
+                    TradingAction::Flatten => {
+                        state.units = 0;
+                        close_position(self.broker.as_ref(), &self.name, &data).await;
+                        continue;
+                    }
+                    TradingAction::Wait => continue,
+                }
+            }
+
+            if state.units > 0 && (data.low <= state.stop || signal.exit) {
+                // The stop sells where the market traded through it: at the stop, or at the
+                // open when the bar gapped below it
+                let price = if data.low <= state.stop {
+                    state.stop.min(data.open)
+                } else {
+                    data.price
+                };
+                debug!(
+                    "{} exits {} units at {} (stop {})",
+                    data.symbol, state.units, price, state.stop
+                );
+                state.units = 0;
+                close_position_at(self.broker.as_ref(), &self.name, &data.symbol, price).await;
+                continue;
+            }
+            let Some(atr) = signal.atr else {
+                continue;
+            };
+            let add_unit = if state.units == 0 {
+                signal.breakout
+            } else {
+                state.units < params.max_units
+                    && data.high >= state.last_entry + params.pyramid_atr * atr
+            };
+            if add_unit
+                && submit_sized_order(
+                    self.broker.as_ref(),
+                    self.position_sizer.as_ref(),
+                    &self.name,
+                    OrderSide::Buy,
+                    &data,
+                )
+                .await
+            {
+                // The stop of all units follows the last entry
+                state.units += 1;
+                state.last_entry = data.price;
+                state.stop = data.price - params.stop_atr * atr;
+                debug!(
+                    "{} holds {} units of {}, stop at {}",
+                    self.name, state.units, data.symbol, state.stop
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,298 @@
+use crate::{
+    broker::Broker,
+    data_feed::DataFeed,
+    strategy::{Strategy, position_quantities, submit_rebalance_orders},
+};
+use async_trait::async_trait;
+use chrono::{Datelike, Days, Months, NaiveDate, Weekday};
+use serde::{Deserialize, Deserializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+use tracing::{debug, error, warn};
+
+/// The calendar dates the strategy trades on. When the market is closed on a date, it trades
+/// on the first prices after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type")]
+pub enum Schedule {
+    Daily,
+    Weekly {
+        /// E.g. `Mon` or `Monday`
+        #[serde(deserialize_with = "deserialize_weekday")]
+        weekday: Weekday,
+    },
+    /// On the given day of every month, or on its last day if the month is shorter
+    Monthly {
+        day: u32,
+    },
+}
+
+fn deserialize_weekday<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Weekday, D::Error> {
+    let weekday = String::deserialize(deserializer)?;
+    weekday
+        .parse()
+        .map_err(|_| serde::de::Error::custom(format!("invalid weekday `{weekday}`")))
+}
+
+impl Schedule {
+    /// The first scheduled date after `date`
+    pub fn next_after(&self, date: NaiveDate) -> NaiveDate {
+        match *self {
+            Self::Daily => date + Days::new(1),
+            Self::Weekly { weekday } => {
+                let days = (weekday.num_days_from_monday() + 7
+                    - date.weekday().num_days_from_monday())
+                    % 7;
+                date + Days::new(if days == 0 { 7 } else { days as u64 })
+            }
+            Self::Monthly { day } => {
+                let first_of_month = date.with_day(1).expect("every month has a first day");
+                let this_month = day_of_month(first_of_month, day);
+                if this_month > date {
+                    this_month
+                } else {
+                    day_of_month(first_of_month + Months::new(1), day)
+                }
+            }
+        }
+    }
+}
+
+/// The `day` of the month starting at `first_of_month`, or its last day if the month is shorter
+fn day_of_month(first_of_month: NaiveDate, day: u32) -> NaiveDate {
+    let last_of_month = first_of_month + Months::new(1) - Days::new(1);
+    last_of_month
+        .with_day(day.clamp(1, last_of_month.day()))
+        .expect("the day is clamped to the month")
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct DcaRebalanceParams {
+    /// The target weight of every symbol of the basket, as a fraction of the cash and basket.
+    /// Weights that sum to less than 1 keep the rest in cash.
+    pub weights: BTreeMap<String, f64>,
+    pub schedule: Schedule,
+    /// The cash invested on every scheduled date, split across the basket by weight
+    #[serde(default)]
+    pub amount: Option<f64>,
+    /// On every scheduled date, bring all weights back to their targets if any of them
+    /// drifted further than this from its target
+    #[serde(default)]
+    pub drift_band: Option<f64>,
+}
+
+impl DcaRebalanceParams {
+    /// The quantities to hold after investing `amount` and rebalancing, given the available
+    /// `cash`, the current `holdings` and `prices` of all symbols of the basket
+    fn target_holdings(
+        &self,
+        mut cash: f64,
+        holdings: &BTreeMap<String, i64>,
+        prices: &BTreeMap<String, f64>,
+    ) -> BTreeMap<String, i64> {
+        let mut targets = holdings.clone();
+        if let Some(amount) = self.amount {
+            let invested = amount.min(cash.max(0.0));
+            let total_weight: f64 = self.weights.values().sum();
+            for (symbol, weight) in &self.weights {
+                let price = prices[symbol];
+                let qty = (invested * weight / total_weight / price).floor();
+                cash -= qty * price;
+                *targets.entry(symbol.clone()).or_default() += qty as i64;
+            }
+        }
+        if let Some(drift_band) = self.drift_band {
+            let equity = cash
+                + targets
+                    .iter()
+                    .map(|(symbol, qty)| *qty as f64 * prices[symbol])
+                    .sum::<f64>();
+            let drifted = equity > 0.0
+                && self.weights.iter().any(|(symbol, weight)| {
+                    let current = targets[symbol] as f64 * prices[symbol] / equity;
+                    (current - weight).abs() > drift_band
+                });
+            if drifted {
+                for (symbol, weight) in &self.weights {
+                    let qty = (weight * equity / prices[symbol]).floor();
+                    targets.insert(symbol.clone(), qty as i64);
+                }
+            }
+        }
+        targets
+    }
+}
+
+/// Invests a fixed amount into a basket of the symbols of its data feed on a calendar schedule
+/// (dollar-cost averaging), and/or rebalances the basket to its target weights when they
+/// drifted beyond a band. The basket is valued at the last prices, together with the
+/// available cash of the broker. Ignores the position sizer. Long only.
+pub struct DcaRebalanceStrategy {
+    name: String,
+    data_feed: Box<dyn DataFeed>,
+    broker: Arc<dyn Broker>,
+    params: DcaRebalanceParams,
+    /// The last traded price of every symbol and its date
+    prices: HashMap<String, (f64, NaiveDate)>,
+    /// The date of the next scheduled trades, from the first price on
+    next_run: Option<NaiveDate>,
+}
+
+impl DcaRebalanceStrategy {
+    pub fn new(
+        name: String,
+        data_feed: Box<dyn DataFeed>,
+        broker: Arc<dyn Broker>,
+        params: DcaRebalanceParams,
+    ) -> Self {
+        Self {
+            name,
+            data_feed,
+            broker,
+            params,
+            prices: HashMap::new(),
+            next_run: None,
+        }
+    }
+
+    /// The prices of the basket once every symbol has traded on or after `date`
+    fn basket_prices(&self, date: NaiveDate) -> Option<BTreeMap<String, f64>> {
+        self.params
+            .weights
+            .keys()
+            .map(|symbol| match self.prices.get(symbol) {
+                Some((price, price_date)) if *price_date >= date => Some((symbol.clone(), *price)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    async fn trade_basket(&self, prices: &BTreeMap<String, f64>) {
+        let account = self.broker.portfolio_snapshot().await;
+        let holdings = position_quantities(self.broker.as_ref(), &self.name, prices.keys()).await;
+        let targets =
+            self.params
+                .target_holdings(account.cash - account.reserved_cash, &holdings, prices);
+        if submit_rebalance_orders(
+            self.broker.as_ref(),
+            &self.name,
+            &holdings,
+            &targets,
+            prices,
+        )
+        .await
+            == 0
+        {
+            debug!(
+                "{} placed no orders for its targets {:?}",
+                self.name, targets
+            );
+        }
+    }
+}
+
+#[async_trait]
+impl Strategy for DcaRebalanceStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn run(&mut self) {
+        while let Some(event) = self.data_feed.next_tick().await {
+            let data = match event {
+                Ok(data) => data,
+                Err(err) if err.is_fatal() => {
+                    error!("Stopping {}: {}", self.name, err);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{} waits for data: {}", self.name, err);
+                    continue;
+                }
+            };
+            self.broker.on_market_data(&data).await;
+            // The basket is valued at traded prices only
+            if data.is_quote() {
+                continue;
+            }
+            let date = data.timestamp.date_naive();
+            self.prices.insert(data.symbol.clone(), (data.price, date));
+            let next_run = *self.next_run.get_or_insert_with(|| {
+                let day_before = date.pred_opt().unwrap_or(date);
+                self.params.schedule.next_after(day_before)
+            });
+            if date < next_run {
+                continue;
+            }
+            let Some(prices) = self.basket_prices(next_run) else {
+                continue;
+            };
+            self.trade_basket(&prices).await;
+            self.next_run = Some(self.params.schedule.next_after(date));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().unwrap()
+    }
+
+    #[test]
+    fn test_schedule_next_after() {
+        assert_eq!(
+            Schedule::Daily.next_after(date("2024-02-29")),
+            date("2024-03-01")
+        );
+        let weekly = Schedule::Weekly {
+            weekday: Weekday::Mon,
+        };
+        // 2024-01-01 is a Monday
+        assert_eq!(weekly.next_after(date("2024-01-01")), date("2024-01-08"));
+        assert_eq!(weekly.next_after(date("2024-01-03")), date("2024-01-08"));
+        let monthly = Schedule::Monthly { day: 31 };
+        assert_eq!(monthly.next_after(date("2024-01-15")), date("2024-01-31"));
+        assert_eq!(monthly.next_after(date("2024-01-31")), date("2024-02-29"));
+        assert_eq!(monthly.next_after(date("2024-02-29")), date("2024-03-31"));
+    }
+
+    #[test]
+    fn test_target_holdings() {
+        let params = DcaRebalanceParams {
+            weights: BTreeMap::from([("A".into(), 0.6), ("B".into(), 0.4)]),
+            schedule: Schedule::Daily,
+            amount: Some(100.0),
+            drift_band: None,
+        };
+        let prices = BTreeMap::from([("A".into(), 10.0), ("B".into(), 20.0)]);
+        let holdings = BTreeMap::from([("A".into(), 10), ("B".into(), 0)]);
+        // 60 buy 6 A, 40 buy 2 B
+        let targets = params.target_holdings(500.0, &holdings, &prices);
+        assert_eq!(targets, BTreeMap::from([("A".into(), 16), ("B".into(), 2)]));
+        // Only the available cash is invested
+        let targets = params.target_holdings(50.0, &holdings, &prices);
+        assert_eq!(targets, BTreeMap::from([("A".into(), 13), ("B".into(), 1)]));
+
+        let params = DcaRebalanceParams {
+            amount: None,
+            drift_band: Some(0.05),
+            ..params
+        };
+        // 100 in A and 100 in B are 50% each, beyond the band of 60/40
+        let targets = params.target_holdings(
+            0.0,
+            &BTreeMap::from([("A".into(), 10), ("B".into(), 5)]),
+            &prices,
+        );
+        assert_eq!(targets, BTreeMap::from([("A".into(), 12), ("B".into(), 4)]));
+        // 57% and 43% are within it
+        let holdings = BTreeMap::from([("A".into(), 57), ("B".into(), 21)]);
+        let targets = params.target_holdings(10.0, &holdings, &prices);
+        assert_eq!(targets, holdings);
+    }
+}
@@ -0,0 +1,462 @@
+use crate::{
+    broker::Broker,
+    data_feed::{DataFeed, MarketData},
+    position_sizer::PositionSizer,
+    strategy::{
+        Strategy, close_position, submit_tracked_order,
+        trading_hours::{TradingAction, TradingHours},
+    },
+    types::{Order, OrderId, OrderSide, OrderStatus, OrderType},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+use tracing::{debug, error, info, warn};
+
+pub const DEFAULT_GRID_LEVELS: usize = 5;
+pub const DEFAULT_GRID_SPACING: f64 = 0.01;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct GridParams {
+    /// The number of buy levels below the reference price
+    pub levels: usize,
+    /// The distance between two levels as a fraction of the reference price
+    pub spacing: f64,
+    /// The most units of a symbol held plus bought by its open buy orders. Unlimited without.
+    pub max_inventory: Option<u32>,
+}
+
+impl Default for GridParams {
+    fn default() -> Self {
+        Self {
+            levels: DEFAULT_GRID_LEVELS,
+            spacing: DEFAULT_GRID_SPACING,
+            max_inventory: None,
+        }
+    }
+}
+
+/// The order of a level of the grid. Every level buys at its price and sells what it bought
+/// one step higher.
+#[derive(Debug, Clone, PartialEq)]
+enum Level {
+    /// Armed with a buy once the price is above the level
+    Idle,
+    Buying {
+        order_id: OrderId,
+        qty: u32,
+        sell_price: f64,
+    },
+    /// Holds `qty` that still has to be offered at `sell_price`
+    Bought { qty: u32, sell_price: f64 },
+    Selling {
+        order_id: OrderId,
+        qty: u32,
+        sell_price: f64,
+    },
+}
+
+#[derive(Debug)]
+struct SymbolState {
+    /// The price the grid hangs below
+    reference: f64,
+    step: f64,
+    /// `levels[k]` buys `k + 1` steps below the reference
+    levels: Vec<Level>,
+    /// The close before which the position was last flattened
+    flattened_for: Option<DateTime<Utc>>,
+    /// Taking the grid down: waiting for the broker to report its orders cancelled before
+    /// closing what is left of the position
+    flattening: bool,
+}
+
+impl SymbolState {
+    fn new(reference: f64, params: &GridParams) -> Self {
+        Self {
+            reference,
+            step: reference * params.spacing,
+            levels: vec![Level::Idle; params.levels],
+            flattened_for: None,
+            flattening: false,
+        }
+    }
+
+    fn buy_price(&self, level: usize) -> f64 {
+        self.reference - (level + 1) as f64 * self.step
+    }
+
+    /// Whether any level holds what it bought
+    fn has_inventory(&self) -> bool {
+        self.levels
+            .iter()
+            .any(|level| matches!(level, Level::Bought { .. } | Level::Selling { .. }))
+    }
+
+    /// Whether any level has an order working at the broker
+    fn has_open_orders(&self) -> bool {
+        self.levels
+            .iter()
+            .any(|level| matches!(level, Level::Buying { .. } | Level::Selling { .. }))
+    }
+
+    /// The quantity the open buy orders of the levels would add to the position
+    fn pending_buys(&self) -> u32 {
+        self.levels
+            .iter()
+            .map(|level| match level {
+                Level::Buying { qty, .. } => *qty,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    /// Move the levels on as the broker reports their orders filled or cancelled
+    async fn update(&mut self, broker: &dyn Broker) {
+        for level in &mut self.levels {
+            let next = match level {
+                Level::Idle | Level::Bought { .. } => continue,
+                Level::Buying {
+                    order_id,
+                    qty,
+                    sell_price,
+                } => match broker.order_status(order_id).await {
+                    Some(OrderStatus::Open { .. }) => continue,
+                    Some(OrderStatus::Filled) => Level::Bought {
+                        qty: *qty,
+                        sell_price: *sell_price,
+                    },
+                    Some(OrderStatus::Cancelled { filled_qty }) if filled_qty > 0 => {
+                        Level::Bought {
+                            qty: filled_qty,
+                            sell_price: *sell_price,
+                        }
+                    }
+                    Some(OrderStatus::Cancelled { .. }) | None => Level::Idle,
+                },
+                Level::Selling {
+                    order_id,
+                    qty,
+                    sell_price,
+                } => match broker.order_status(order_id).await {
+                    Some(OrderStatus::Open { .. }) => continue,
+                    Some(OrderStatus::Cancelled { filled_qty }) if filled_qty < *qty => {
+                        Level::Bought {
+                            qty: *qty - filled_qty,
+                            sell_price: *sell_price,
+                        }
+                    }
+                    _ => Level::Idle,
+                },
+            };
+            *level = next;
+        }
+    }
+
+    /// Request the cancellation of the open buys of the levels, and of their sells if
+    /// `sells`. The levels follow once the broker reports them cancelled.
+    async fn cancel_orders(&self, broker: &dyn Broker, sells: bool) {
+        for level in &self.levels {
+            let order_id = match level {
+                Level::Buying { order_id, .. } => order_id,
+                Level::Selling { order_id, .. } if sells => order_id,
+                _ => continue,
+            };
+            if let Err(err) = broker.cancel_order(order_id).await {
+                warn!("Failed to cancel grid order {order_id}: {err}");
+            }
+        }
+    }
+
+    /// Close the position of the strategy once the broker reported every order of the levels
+    /// cancelled or filled, so that no level fills after it. Returns whether it did.
+    async fn close_when_cancelled(
+        &mut self,
+        broker: &dyn Broker,
+        strategy_name: &str,
+        data: &MarketData,
+    ) -> bool {
+        self.update(broker).await;
+        if self.has_open_orders() {
+            debug!(
+                "{} waits for the grid orders of {} to be cancelled",
+                strategy_name, data.symbol
+            );
+            return false;
+        }
+        close_position(broker, strategy_name, data).await;
+        true
+    }
+}
+
+/// Trades the range of every symbol of its data feed with a ladder of resting limit buys
+/// below a reference price, each of which offers what it bought one level higher. Levels are
+/// re-armed once their sells filled, and the ladder moves up with the price while it holds
+/// nothing. Long only.
+pub struct GridStrategy {
+    name: String,
+    data_feed: Box<dyn DataFeed>,
+    broker: Arc<dyn Broker>,
+    position_sizer: Box<dyn PositionSizer>,
+    params: GridParams,
+    symbols: HashMap<String, SymbolState>,
+    trading_hours: Option<TradingHours>,
+}
+
+impl GridStrategy {
+    pub fn new(
+        name: String,
+        data_feed: Box<dyn DataFeed>,
+        broker: Arc<dyn Broker>,
+        position_sizer: Box<dyn PositionSizer>,
+        params: GridParams,
+    ) -> Self {
+        Self {
+            name,
+            data_feed,
+            broker,
+            position_sizer,
+            params,
+            symbols: HashMap::new(),
+            trading_hours: None,
+        }
+    }
+
+    /// Only trade in the given sessions, and optionally flatten positions before the close
+    pub fn with_trading_hours(mut self, trading_hours: TradingHours) -> Self {
+        self.trading_hours = Some(trading_hours);
+        self
+    }
+
+    /// Place the buys of the idle levels below the price, as far as the inventory cap allows,
+    /// and the sells of the levels that bought
+    async fn arm_levels(&self, state: &mut SymbolState, data: &MarketData) {
+        let held = self
+            .broker
+            .strategy_position_qty(&self.name, &data.symbol)
+            .await
+            .max(0) as u64;
+        let mut inventory = held + state.pending_buys() as u64;
+        for k in 0..state.levels.len() {
+            let buy_price = state.buy_price(k);
+            let sell_price = buy_price + state.step;
+            match state.levels[k] {
+                Level::Idle if data.price > buy_price => {
+                    let account = self.broker.portfolio_snapshot().await;
+                    let qty = self.position_sizer.size(&account, buy_price);
+                    let capped = self
+                        .params
+                        .max_inventory
+                        .is_some_and(|max| inventory + qty as u64 > max as u64);
+                    if qty == 0 || capped {
+                        continue;
+                    }
+                    let order = self.limit_order(&data.symbol, OrderSide::Buy, qty, buy_price);
+                    if let Some(order_id) =
+                        submit_tracked_order(self.broker.as_ref(), order, buy_price).await
+                    {
+                        inventory += qty as u64;
+                        state.levels[k] = Level::Buying {
+                            order_id,
+                            qty,
+                            sell_price,
+                        };
+                    }
+                }
+                Level::Bought { qty, sell_price } => {
+                    let order = self.limit_order(&data.symbol, OrderSide::Sell, qty, sell_price);
+                    if let Some(order_id) =
+                        submit_tracked_order(self.broker.as_ref(), order, sell_price).await
+                    {
+                        state.levels[k] = Level::Selling {
+                            order_id,
+                            qty,
+                            sell_price,
+                        };
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn limit_order(&self, symbol: &str, side: OrderSide, qty: u32, price: f64) -> Order {
+        Order {
+            symbol: symbol.to_string(),
+            side,
+            qty,
+            price: Some(price),
+            order_type: OrderType::Limit,
+            strategy_name: self.name.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl Strategy for GridStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn run(&mut self) {
+        while let Some(event) = self.data_feed.next_tick().await {
+            let data = match event {
+                Ok(data) => data,
+                Err(err) if err.is_fatal() => {
+                    error!("Stopping {}: {}", self.name, err);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{} waits for data: {}", self.name, err);
+                    continue;
+                }
+            };
+            // Simulated brokers fill the resting orders of the grid here
+            self.broker.on_market_data(&data).await;
+            // The grid follows traded prices only
+            if data.is_quote() {
+                continue;
+            }
+            let params = self.params;
+            let mut state = self
+                .symbols
+                .remove(&data.symbol)
+                .unwrap_or_else(|| SymbolState::new(data.price, &params));
+            let broker = self.broker.as_ref();
+
+            let action = match &self.trading_hours {
+                // Nothing trades until the grid is down
+                _ if state.flattening => TradingAction::Wait,
+                Some(hours) => hours.action(data.timestamp, &mut state.flattened_for),
+                None => TradingAction::Trade,
+            };
+            match action {
+                TradingAction::Trade => {
+                    state.update(broker).await;
+                    if !state.has_inventory() && data.price > state.reference + state.step {
+                        debug!(
+                            "{} moves the grid of {} up to {}",
+                            self.name, data.symbol, data.price
+                        );
+                        state.cancel_orders(broker, false).await;
+                        state.update(broker).await;
+                        state = SymbolState {
+                            levels: state.levels,
+                            flattened_for: state.flattened_for,
+                            ..SymbolState::new(data.price, &params)
+                        };
+                    }
+                    self.arm_levels(&mut state, &data).await;
+                }
+                TradingAction::Flatten => {
+                    info!("{} takes down the grid of {}", self.name, data.symbol);
+                    state.cancel_orders(broker, true).await;
+                    state.flattening = true;
+                }
+                TradingAction::Wait => {}
+            }
+            if state.flattening && state.close_when_cancelled(broker, &self.name, &data).await {
+                state = SymbolState {
+                    flattened_for: state.flattened_for,
+                    ..SymbolState::new(data.price, &params)
+                };
+            }
+            self.symbols.insert(data.symbol.clone(), state);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        broker::{BrokerError, PortfolioManager, dummy::DummyBroker},
+        types::OrderType,
+    };
+    use tokio::sync::Mutex;
+
+    /// A simulated broker that only cancels orders once asked to confirm the cancellations
+    struct DeferredCancelBroker {
+        inner: DummyBroker,
+        cancelling: Mutex<Vec<String>>,
+    }
+
+    impl DeferredCancelBroker {
+        async fn confirm_cancellations(&self) {
+            for order_id in self.cancelling.lock().await.drain(..) {
+                self.inner.cancel_order(&order_id).await.unwrap();
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Broker for DeferredCancelBroker {
+        fn name(&self) -> &str {
+            self.inner.name()
+        }
+        async fn place_order(&self, order: &Order) -> Result<OrderId, BrokerError> {
+            self.inner.place_order(order).await
+        }
+        async fn cancel_order(&self, order_id: &str) -> Result<(), BrokerError> {
+            self.cancelling.lock().await.push(order_id.to_string());
+            Ok(())
+        }
+        async fn order_status(&self, order_id: &str) -> Option<OrderStatus> {
+            self.inner.order_status(order_id).await
+        }
+        fn portfolio_manager(&self) -> &PortfolioManager {
+            self.inner.portfolio_manager()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_flatten_waits_for_the_cancellations() {
+        let broker = DeferredCancelBroker {
+            inner: DummyBroker::new("dummy".into()),
+            cancelling: Mutex::new(Vec::new()),
+        };
+        let order = |side, order_type, price| Order {
+            symbol: "AAPL".into(),
+            side,
+            qty: 5,
+            price: Some(price),
+            order_type,
+            strategy_name: "grid".into(),
+        };
+        submit_tracked_order(
+            &broker,
+            order(OrderSide::Buy, OrderType::Market, 100.0),
+            100.0,
+        )
+        .await
+        .unwrap();
+        let order_id = submit_tracked_order(
+            &broker,
+            order(OrderSide::Sell, OrderType::Limit, 101.0),
+            101.0,
+        )
+        .await
+        .unwrap();
+        let mut state = SymbolState::new(100.0, &GridParams::default());
+        state.levels[0] = Level::Selling {
+            order_id: order_id.clone(),
+            qty: 5,
+            sell_price: 101.0,
+        };
+        let data = MarketData::tick("AAPL".into(), 100.0, 0.0, Utc::now());
+
+        state.cancel_orders(&broker, true).await;
+        // The sell still works, so the position stays
+        assert!(!state.close_when_cancelled(&broker, "grid", &data).await);
+        assert_eq!(broker.strategy_position_qty("grid", "AAPL").await, 5);
+
+        broker.confirm_cancellations().await;
+        assert!(state.close_when_cancelled(&broker, "grid", &data).await);
+        assert_eq!(
+            broker.order_status(&order_id).await,
+            Some(OrderStatus::Cancelled { filled_qty: 0 })
+        );
+        assert_eq!(broker.strategy_position_qty("grid", "AAPL").await, 0);
+    }
+}
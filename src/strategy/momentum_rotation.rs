@@ -0,0 +1,359 @@
+use crate::{
+    broker::Broker,
+    data_feed::DataFeed,
+    indicator::{Indicator, rate_of_change::RateOfChange, rolling_stats::RollingStats},
+    strategy::{Strategy, close_position_at, position_quantities, submit_rebalance_orders},
+};
+use async_trait::async_trait;
+use chrono::{Days, NaiveDate};
+use serde::Deserialize;
+use std::{collections::BTreeMap, sync::Arc};
+use tracing::{debug, error, info, warn};
+
+/// How the capital is split across the symbols held
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum Weighting {
+    #[default]
+    Equal,
+    /// In proportion to the inverse of the volatility of each symbol, so that each contributes
+    /// about the same risk
+    InverseVolatility,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MomentumRotationParams {
+    /// The number of prices the trailing return is measured over
+    #[serde(default = "default_lookback")]
+    pub lookback: usize,
+    /// Rank the universe and rebalance every this many calendar days
+    #[serde(default = "default_rebalance_days")]
+    pub rebalance_days: u64,
+    /// The number of symbols held
+    #[serde(default = "default_top")]
+    pub top: usize,
+    #[serde(default)]
+    pub weighting: Weighting,
+    /// The number of returns the volatility for `InverseVolatility` is measured over
+    #[serde(default = "default_volatility_window")]
+    pub volatility_window: usize,
+    /// Only hold symbols whose trailing return is above this. Their share stays in cash.
+    #[serde(default)]
+    pub min_return: Option<f64>,
+    /// Symbols without a price for more than this many days are dropped from the universe
+    /// (e.g. delisted ones), so that the rankings do not wait for them. What is held of them
+    /// is sold at their last price.
+    #[serde(default = "default_max_stale_days")]
+    pub max_stale_days: u64,
+}
+
+fn default_lookback() -> usize {
+    90
+}
+
+fn default_rebalance_days() -> u64 {
+    30
+}
+
+fn default_top() -> usize {
+    3
+}
+
+fn default_volatility_window() -> usize {
+    20
+}
+
+fn default_max_stale_days() -> u64 {
+    5
+}
+
+/// A symbol that can be ranked
+#[derive(Debug, Clone, PartialEq)]
+struct Candidate {
+    symbol: String,
+    /// The trailing return
+    momentum: f64,
+    /// The standard deviation of the returns, if known yet
+    volatility: Option<f64>,
+}
+
+impl MomentumRotationParams {
+    /// The weights of the `top` candidates with the highest momentum. Every one of the `top`
+    /// slots that no candidate qualifies for stays in cash.
+    fn target_weights(&self, mut candidates: Vec<Candidate>) -> BTreeMap<String, f64> {
+        candidates.retain(|candidate| {
+            self.min_return
+                .is_none_or(|min_return| candidate.momentum > min_return)
+                && (self.weighting == Weighting::Equal
+                    || candidate
+                        .volatility
+                        .is_some_and(|volatility| volatility > 0.0))
+        });
+        candidates.sort_by(|a, b| b.momentum.total_cmp(&a.momentum));
+        candidates.truncate(self.top);
+        let slot = 1.0 / self.top as f64;
+        match self.weighting {
+            Weighting::Equal => candidates
+                .into_iter()
+                .map(|candidate| (candidate.symbol, slot))
+                .collect(),
+            Weighting::InverseVolatility => {
+                let invested = candidates.len() as f64 * slot;
+                let inverse = |candidate: &Candidate| 1.0 / candidate.volatility.unwrap_or(1.0);
+                let total: f64 = candidates.iter().map(inverse).sum();
+                candidates
+                    .iter()
+                    .map(|candidate| {
+                        let weight = invested * inverse(candidate) / total;
+                        (candidate.symbol.clone(), weight)
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct SymbolState {
+    momentum: RateOfChange,
+    /// Of the returns from price to price
+    volatility: RollingStats,
+    /// The last price and its date
+    price: f64,
+    date: NaiveDate,
+}
+
+impl SymbolState {
+    fn new(params: &MomentumRotationParams, price: f64, date: NaiveDate) -> Self {
+        let mut momentum = RateOfChange::new(params.lookback);
+        momentum.update(price);
+        Self {
+            momentum,
+            volatility: RollingStats::new(params.volatility_window),
+            price,
+            date,
+        }
+    }
+
+    fn update(&mut self, price: f64, date: NaiveDate) {
+        if self.price != 0.0 {
+            self.volatility.update(price / self.price - 1.0);
+        }
+        self.momentum.update(price);
+        self.price = price;
+        self.date = date;
+    }
+}
+
+/// Ranks the symbols of its data feed by their trailing return on a fixed schedule and holds
+/// the top ones, with equal or inverse volatility weights. Symbols that drop out of the top
+/// are sold and the rest is rebalanced in one batch of orders. The basket is valued at the
+/// last prices, together with the available cash of the broker. Ignores the position sizer.
+/// Long only.
+pub struct MomentumRotationStrategy {
+    name: String,
+    data_feed: Box<dyn DataFeed>,
+    broker: Arc<dyn Broker>,
+    params: MomentumRotationParams,
+    symbols: BTreeMap<String, SymbolState>,
+    /// The date of the next ranking, from the first price on
+    next_rebalance: Option<NaiveDate>,
+}
+
+impl MomentumRotationStrategy {
+    pub fn new(
+        name: String,
+        data_feed: Box<dyn DataFeed>,
+        broker: Arc<dyn Broker>,
+        params: MomentumRotationParams,
+    ) -> Self {
+        Self {
+            name,
+            data_feed,
+            broker,
+            params,
+            symbols: BTreeMap::new(),
+            next_rebalance: None,
+        }
+    }
+
+    /// Rank the universe and trade to the new weights. Returns false, without trading, while
+    /// the trailing return of no symbol is known yet.
+    async fn rebalance(&self) -> bool {
+        let candidates: Vec<_> = self
+            .symbols
+            .iter()
+            .filter_map(|(symbol, state)| {
+                Some(Candidate {
+                    symbol: symbol.clone(),
+                    momentum: state.momentum.value()?,
+                    volatility: state.volatility.value().map(|stats| stats.std_dev),
+                })
+            })
+            .collect();
+        if candidates.is_empty() {
+            return false;
+        }
+        let weights = self.params.target_weights(candidates);
+        info!("{} holds {:?}", self.name, weights);
+
+        let prices: BTreeMap<_, _> = self
+            .symbols
+            .iter()
+            .map(|(symbol, state)| (symbol.clone(), state.price))
+            .collect();
+        let holdings = position_quantities(self.broker.as_ref(), &self.name, prices.keys()).await;
+        let account = self.broker.portfolio_snapshot().await;
+        let equity = account.cash - account.reserved_cash
+            + holdings
+                .iter()
+                .map(|(symbol, qty)| *qty as f64 * prices[symbol])
+                .sum::<f64>();
+        let targets = prices
+            .iter()
+            .map(|(symbol, price)| {
+                let weight = weights.get(symbol).copied().unwrap_or(0.0);
+                (
+                    symbol.clone(),
+                    (weight * equity.max(0.0) / price).floor() as i64,
+                )
+            })
+            .collect();
+        submit_rebalance_orders(
+            self.broker.as_ref(),
+            &self.name,
+            &holdings,
+            &targets,
+            &prices,
+        )
+        .await;
+        true
+    }
+}
+
+#[async_trait]
+impl Strategy for MomentumRotationStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn run(&mut self) {
+        while let Some(event) = self.data_feed.next_tick().await {
+            let data = match event {
+                Ok(data) => data,
+                Err(err) if err.is_fatal() => {
+                    error!("Stopping {}: {}", self.name, err);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{} waits for data: {}", self.name, err);
+                    continue;
+                }
+            };
+            self.broker.on_market_data(&data).await;
+            // Returns are calculated on traded prices only
+            if data.is_quote() {
+                continue;
+            }
+            let date = data.timestamp.date_naive();
+            match self.symbols.get_mut(&data.symbol) {
+                Some(state) => state.update(data.price, date),
+                None => {
+                    let state = SymbolState::new(&self.params, data.price, date);
+                    self.symbols.insert(data.symbol.clone(), state);
+                }
+            }
+
+            let max_stale_days = self.params.max_stale_days;
+            let stale: Vec<_> = self
+                .symbols
+                .iter()
+                .filter(|(_, state)| (date - state.date).num_days() > max_stale_days as i64)
+                .map(|(symbol, _)| symbol.clone())
+                .collect();
+            for symbol in stale {
+                let Some(state) = self.symbols.remove(&symbol) else {
+                    continue;
+                };
+                warn!(
+                    "{} drops {} without a price since {}",
+                    self.name, symbol, state.date
+                );
+                // What is still held would never be sold by a ranking
+                close_position_at(self.broker.as_ref(), &self.name, &symbol, state.price).await;
+            }
+
+            let next_rebalance = *self.next_rebalance.get_or_insert(date);
+            // Every symbol of the universe has to have its price of the day
+            if date < next_rebalance
+                || self
+                    .symbols
+                    .values()
+                    .any(|state| state.date < next_rebalance)
+            {
+                continue;
+            }
+            let days = if self.rebalance().await {
+                self.params.rebalance_days
+            } else {
+                debug!("{} waits for {} prices", self.name, self.params.lookback);
+                1
+            };
+            self.next_rebalance = Some(date + Days::new(days));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(symbol: &str, momentum: f64, volatility: f64) -> Candidate {
+        Candidate {
+            symbol: symbol.into(),
+            momentum,
+            volatility: Some(volatility),
+        }
+    }
+
+    #[test]
+    fn test_target_weights() {
+        let candidates = vec![
+            candidate("A", 0.2, 0.01),
+            candidate("B", -0.1, 0.01),
+            candidate("C", 0.3, 0.02),
+            candidate("D", 0.1, 0.04),
+        ];
+        let params = MomentumRotationParams {
+            lookback: 90,
+            rebalance_days: 30,
+            top: 2,
+            weighting: Weighting::Equal,
+            volatility_window: 20,
+            min_return: None,
+            max_stale_days: 5,
+        };
+        let weights = params.target_weights(candidates.clone());
+        assert_eq!(
+            weights,
+            BTreeMap::from([("A".into(), 0.5), ("C".into(), 0.5)])
+        );
+
+        // A is half as volatile as C, so it gets twice the weight
+        let params = MomentumRotationParams {
+            weighting: Weighting::InverseVolatility,
+            ..params
+        };
+        let weights = params.target_weights(candidates.clone());
+        assert!((weights["A"] - 2.0 / 3.0).abs() < 1e-9);
+        assert!((weights["C"] - 1.0 / 3.0).abs() < 1e-9);
+
+        // Only C qualifies, the other slot stays in cash
+        let params = MomentumRotationParams {
+            top: 2,
+            min_return: Some(0.25),
+            ..params
+        };
+        let weights = params.target_weights(candidates);
+        assert_eq!(weights, BTreeMap::from([("C".into(), 0.5)]));
+    }
+}
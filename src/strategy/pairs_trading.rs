@@ -0,0 +1,369 @@
+use crate::{
+    broker::Broker,
+    data_feed::{DataFeed, MarketData},
+    indicator::{
+        Indicator,
+        regression::{KalmanRegression, RegressionValue, RollingRegression},
+        rolling_stats::RollingStats,
+    },
+    position_sizer::PositionSizer,
+    strategy::{
+        Strategy, close_position, submit_order, submit_tracked_order,
+        trading_hours::{TradingAction, TradingHours},
+    },
+    types::{Order, OrderSide, OrderStatus, OrderType},
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+use tracing::{debug, error, info, warn};
+
+/// How the hedge ratio of the pair is estimated
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "type")]
+pub enum HedgeRatioModel {
+    /// Least squares regression over the last `window` prices
+    Ols {
+        #[serde(default = "default_ols_window")]
+        window: usize,
+    },
+    /// Kalman filter regression (see [`KalmanRegression`])
+    Kalman {
+        #[serde(default = "default_kalman_delta")]
+        delta: f64,
+        #[serde(default = "default_kalman_observation_variance")]
+        observation_variance: f64,
+    },
+}
+
+impl Default for HedgeRatioModel {
+    fn default() -> Self {
+        Self::Ols {
+            window: default_ols_window(),
+        }
+    }
+}
+
+fn default_ols_window() -> usize {
+    60
+}
+
+fn default_kalman_delta() -> f64 {
+    1e-4
+}
+
+fn default_kalman_observation_variance() -> f64 {
+    1e-3
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PairsTradingParams {
+    /// The symbol whose price is regressed on the price of `symbol_x`
+    pub symbol_y: String,
+    pub symbol_x: String,
+    #[serde(default)]
+    pub hedge_ratio: HedgeRatioModel,
+    /// The number of spreads the z-score is calculated over
+    #[serde(default = "default_z_window")]
+    pub z_window: usize,
+    /// Open a position when the z-score of the spread is beyond this
+    #[serde(default = "default_entry_z")]
+    pub entry_z: f64,
+    /// Close the position when the z-score has reverted to within this
+    #[serde(default = "default_exit_z")]
+    pub exit_z: f64,
+    /// Close the position when the z-score diverges beyond this. No new position is opened
+    /// before it reverted.
+    #[serde(default)]
+    pub stop_z: Option<f64>,
+}
+
+fn default_z_window() -> usize {
+    60
+}
+
+fn default_entry_z() -> f64 {
+    2.0
+}
+
+fn default_exit_z() -> f64 {
+    0.5
+}
+
+/// The hedge ratio estimators behind [`HedgeRatioModel`]
+#[derive(Debug, Clone)]
+enum HedgeRatio {
+    Ols(RollingRegression),
+    Kalman(KalmanRegression),
+}
+
+impl HedgeRatio {
+    fn new(model: HedgeRatioModel) -> Self {
+        match model {
+            HedgeRatioModel::Ols { window } => Self::Ols(RollingRegression::new(window)),
+            HedgeRatioModel::Kalman {
+                delta,
+                observation_variance,
+            } => Self::Kalman(KalmanRegression::new(delta, observation_variance)),
+        }
+    }
+
+    fn update(&mut self, x: f64, y: f64) -> Option<RegressionValue> {
+        match self {
+            Self::Ols(regression) => regression.update((x, y)),
+            Self::Kalman(regression) => regression.update((x, y)),
+        }
+    }
+}
+
+/// Which side of the spread `y - hedge_ratio * x` is held
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpreadPosition {
+    /// Long `y`, short `x`
+    Long,
+    /// Short `y`, long `x`
+    Short,
+}
+
+/// The last price of a leg and whether it changed since the spread was last calculated
+#[derive(Debug, Default)]
+struct Leg {
+    price: Option<f64>,
+    updated: bool,
+}
+
+/// Trades the spread between two cointegrated symbols of its data feed: when the z-score of
+/// `y - hedge_ratio * x` is beyond `entry_z`, it buys the cheap leg and sells the expensive
+/// one short, and it closes both legs once the spread reverted. Needs a broker that allows
+/// short selling.
+pub struct PairsTradingStrategy {
+    name: String,
+    data_feed: Box<dyn DataFeed>,
+    broker: Arc<dyn Broker>,
+    position_sizer: Box<dyn PositionSizer>,
+    params: PairsTradingParams,
+    hedge_ratio: HedgeRatio,
+    spread: RollingStats,
+    leg_y: Leg,
+    leg_x: Leg,
+    position: Option<SpreadPosition>,
+    /// Set when the stop closed the position, until the spread reverted
+    stopped: bool,
+    trading_hours: Option<TradingHours>,
+    /// The close before which the position was last flattened
+    flattened_for: Option<DateTime<Utc>>,
+}
+
+impl PairsTradingStrategy {
+    pub fn new(
+        name: String,
+        data_feed: Box<dyn DataFeed>,
+        broker: Arc<dyn Broker>,
+        position_sizer: Box<dyn PositionSizer>,
+        params: PairsTradingParams,
+    ) -> Self {
+        Self {
+            name,
+            data_feed,
+            broker,
+            position_sizer,
+            hedge_ratio: HedgeRatio::new(params.hedge_ratio),
+            spread: RollingStats::new(params.z_window),
+            params,
+            leg_y: Leg::default(),
+            leg_x: Leg::default(),
+            position: None,
+            stopped: false,
+            trading_hours: None,
+            flattened_for: None,
+        }
+    }
+
+    /// Only trade in the given sessions, and optionally flatten positions before the close
+    pub fn with_trading_hours(mut self, trading_hours: TradingHours) -> Self {
+        self.trading_hours = Some(trading_hours);
+        self
+    }
+
+    /// Recalculate the spread once both legs have a new price, and trade on it
+    async fn on_prices(&mut self, y: f64, x: f64, timestamp: DateTime<Utc>) {
+        let Some(regression) = self.hedge_ratio.update(x, y) else {
+            return;
+        };
+        let hedge_ratio = regression.slope;
+        let Some(spread) = self.spread.update(y - hedge_ratio * x) else {
+            return;
+        };
+        let z = spread.z_score;
+        debug!(
+            "{}: hedge ratio {hedge_ratio:.3}, spread z-score {z:.2}",
+            self.name
+        );
+
+        if let Some(hours) = &self.trading_hours {
+            match hours.action(timestamp, &mut self.flattened_for) {
+                TradingAction::Trade => {}
+                TradingAction::Flatten => {
+                    self.close_legs(y, x, timestamp).await;
+                    return;
+                }
+                TradingAction::Wait => return,
+            }
+        }
+
+        if self.stopped && z.abs() <= self.params.exit_z {
+            self.stopped = false;
+        }
+        let beyond_stop = self.params.stop_z.is_some_and(|stop_z| z.abs() >= stop_z);
+        match self.position {
+            Some(position) => {
+                let reverted = match position {
+                    SpreadPosition::Long => z >= -self.params.exit_z,
+                    SpreadPosition::Short => z <= self.params.exit_z,
+                };
+                if reverted || beyond_stop {
+                    info!("{} closes the spread at z-score {z:.2}", self.name);
+                    self.stopped = beyond_stop;
+                    self.close_legs(y, x, timestamp).await;
+                }
+            }
+            // A negative hedge ratio is no hedge
+            None if self.stopped || beyond_stop || hedge_ratio <= 0.0 => {}
+            None if z >= self.params.entry_z => {
+                self.open_legs(SpreadPosition::Short, hedge_ratio, y, x)
+                    .await
+            }
+            None if z <= -self.params.entry_z => {
+                self.open_legs(SpreadPosition::Long, hedge_ratio, y, x)
+                    .await
+            }
+            None => {}
+        }
+    }
+
+    /// Open both legs, `hedge_ratio` units of `x` per unit of `y`. The position is only
+    /// opened if both orders were placed, otherwise the order of the first leg is unwound.
+    async fn open_legs(&mut self, position: SpreadPosition, hedge_ratio: f64, y: f64, x: f64) {
+        let account_snapshot = self.broker.portfolio_snapshot().await;
+        let qty_y = self.position_sizer.size(&account_snapshot, y);
+        let qty_x = (qty_y as f64 * hedge_ratio).round() as u32;
+        if qty_y == 0 || qty_x == 0 {
+            info!("Sizer return qty=0; skipping order");
+            return;
+        }
+        let (side_y, side_x) = match position {
+            SpreadPosition::Long => (OrderSide::Buy, OrderSide::Sell),
+            SpreadPosition::Short => (OrderSide::Sell, OrderSide::Buy),
+        };
+        info!(
+            "{} opens a {:?} spread: {side_y:?} {qty_y} {}, {side_x:?} {qty_x} {}",
+            self.name, position, self.params.symbol_y, self.params.symbol_x
+        );
+        let order = |symbol: &str, side, qty, price| Order {
+            symbol: symbol.to_string(),
+            side,
+            qty,
+            price: Some(price),
+            order_type: OrderType::Market,
+            strategy_name: self.name.clone(),
+        };
+        let order_y = order(&self.params.symbol_y, side_y, qty_y, y);
+        let order_x = order(&self.params.symbol_x, side_x, qty_x, x);
+        let Some(order_id_y) = submit_tracked_order(self.broker.as_ref(), order_y.clone(), y).await
+        else {
+            return;
+        };
+        if !submit_order(self.broker.as_ref(), order_x, x).await {
+            warn!(
+                "{} unwinds {} without the second leg",
+                self.name, self.params.symbol_y
+            );
+            self.unwind(&order_id_y, order_y, y).await;
+            return;
+        }
+        self.position = Some(position);
+    }
+
+    /// Cancel the order `order_id` of a leg, and offset at `price` the part of it that was
+    /// already filled
+    async fn unwind(&self, order_id: &str, order: Order, price: f64) {
+        if let Err(err) = self.broker.cancel_order(order_id).await {
+            debug!("Order {} could not be cancelled: {}", order_id, err);
+        }
+        let filled_qty = match self.broker.order_status(order_id).await {
+            Some(OrderStatus::Filled) => order.qty,
+            Some(OrderStatus::Open { filled_qty } | OrderStatus::Cancelled { filled_qty }) => {
+                filled_qty
+            }
+            None => 0,
+        };
+        if filled_qty == 0 {
+            return;
+        }
+        let side = match order.side {
+            OrderSide::Buy => OrderSide::Sell,
+            OrderSide::Sell => OrderSide::Buy,
+        };
+        let offset = Order {
+            side,
+            qty: filled_qty,
+            price: Some(price),
+            ..order
+        };
+        submit_order(self.broker.as_ref(), offset, price).await;
+    }
+
+    async fn close_legs(&mut self, y: f64, x: f64, timestamp: DateTime<Utc>) {
+        self.position = None;
+        for (symbol, price) in [(&self.params.symbol_y, y), (&self.params.symbol_x, x)] {
+            let data = MarketData::tick(symbol.clone(), price, 0.0, timestamp);
+            close_position(self.broker.as_ref(), &self.name, &data).await;
+        }
+    }
+}
+
+#[async_trait]
+impl Strategy for PairsTradingStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn run(&mut self) {
+        while let Some(event) = self.data_feed.next_tick().await {
+            let data = match event {
+                Ok(data) => data,
+                Err(err) if err.is_fatal() => {
+                    error!("Stopping {}: {}", self.name, err);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{} waits for data: {}", self.name, err);
+                    continue;
+                }
+            };
+            self.broker.on_market_data(&data).await;
+            // The spread is calculated on traded prices only
+            if data.is_quote() {
+                continue;
+            }
+            let leg = if data.symbol == self.params.symbol_y {
+                &mut self.leg_y
+            } else if data.symbol == self.params.symbol_x {
+                &mut self.leg_x
+            } else {
+                continue;
+            };
+            leg.price = Some(data.price);
+            leg.updated = true;
+            if self.leg_y.updated
+                && self.leg_x.updated
+                && let (Some(y), Some(x)) = (self.leg_y.price, self.leg_x.price)
+            {
+                self.leg_y.updated = false;
+                self.leg_x.updated = false;
+                self.on_prices(y, x, data.timestamp).await;
+            }
+        }
+    }
+}
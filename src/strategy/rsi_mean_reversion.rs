@@ -0,0 +1,184 @@
+use crate::{
+    broker::Broker,
+    data_feed::DataFeed,
+    indicator::{Indicator, moving_average::Sma, rsi::Rsi},
+    position_sizer::PositionSizer,
+    strategy::{
+        Strategy, close_position, submit_sized_order,
+        trading_hours::{TradingAction, TradingHours},
+    },
+    types::OrderSide,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+use tracing::{debug, error, warn};
+
+pub const DEFAULT_RSI_PERIOD: usize = 14;
+pub const DEFAULT_RSI_OVERSOLD: f64 = 30.0;
+pub const DEFAULT_RSI_EXIT: f64 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RsiMeanReversionParams {
+    /// The period of the RSI
+    pub period: usize,
+    /// Buy when the RSI drops below this
+    pub oversold: f64,
+    /// Sell the position when the RSI recovers to this
+    pub exit: f64,
+    /// Only buy when the price is above its SMA over this many prices, i.e. buy dips in an
+    /// uptrend only
+    pub trend_window: Option<usize>,
+}
+
+impl Default for RsiMeanReversionParams {
+    fn default() -> Self {
+        Self {
+            period: DEFAULT_RSI_PERIOD,
+            oversold: DEFAULT_RSI_OVERSOLD,
+            exit: DEFAULT_RSI_EXIT,
+            trend_window: None,
+        }
+    }
+}
+
+/// Buys every symbol of its data feed when it is oversold and sells the position when the
+/// RSI has reverted to the exit threshold. Long only.
+pub struct RsiMeanReversionStrategy {
+    name: String,
+    data_feed: Box<dyn DataFeed>,
+    broker: Arc<dyn Broker>,
+    position_sizer: Box<dyn PositionSizer>,
+    params: RsiMeanReversionParams,
+    symbols: HashMap<String, SymbolState>,
+    trading_hours: Option<TradingHours>,
+}
+
+#[derive(Debug)]
+struct SymbolState {
+    rsi: Rsi,
+    trend: Option<Sma>,
+    /// Whether a buy was placed that was not sold yet
+    in_position: bool,
+    /// The close before which the position was last flattened
+    flattened_for: Option<DateTime<Utc>>,
+}
+
+impl SymbolState {
+    fn new(params: &RsiMeanReversionParams) -> Self {
+        Self {
+            rsi: Rsi::new(params.period),
+            trend: params.trend_window.map(Sma::new),
+            in_position: false,
+            flattened_for: None,
+        }
+    }
+
+    /// Whether the trend filter allows buying at `price`. Not before the SMA is warmed up.
+    fn in_uptrend(&self, price: f64) -> bool {
+        match &self.trend {
+            Some(trend) => trend.value().is_some_and(|sma| price > sma),
+            None => true,
+        }
+    }
+}
+
+impl RsiMeanReversionStrategy {
+    pub fn new(
+        name: String,
+        data_feed: Box<dyn DataFeed>,
+        broker: Arc<dyn Broker>,
+        position_sizer: Box<dyn PositionSizer>,
+        params: RsiMeanReversionParams,
+    ) -> Self {
+        Self {
+            name,
+            data_feed,
+            broker,
+            position_sizer,
+            params,
+            symbols: HashMap::new(),
+            trading_hours: None,
+        }
+    }
+
+    /// Only trade in the given sessions, and optionally flatten positions before the close
+    pub fn with_trading_hours(mut self, trading_hours: TradingHours) -> Self {
+        self.trading_hours = Some(trading_hours);
+        self
+    }
+}
+
+#[async_trait]
+impl Strategy for RsiMeanReversionStrategy {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn run(&mut self) {
+        while let Some(event) = self.data_feed.next_tick().await {
+            let data = match event {
+                Ok(data) => data,
+                Err(err) if err.is_fatal() => {
+                    error!("Stopping {}: {}", self.name, err);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{} waits for data: {}", self.name, err);
+                    continue;
+                }
+            };
+            self.broker.on_market_data(&data).await;
+            // The RSI is calculated on traded prices only
+            if data.is_quote() {
+                continue;
+            }
+            let params = self.params;
+            let state = self
+                .symbols
+                .entry(data.symbol.clone())
+                .or_insert_with(|| SymbolState::new(&params));
+            let rsi = state.rsi.update(data.price);
+            // The filter compares with the SMA before the current price
+            let in_uptrend = state.in_uptrend(data.price);
+            if let Some(trend) = &mut state.trend {
+                trend.update(data.price);
+            }
+            if let Some(hours) = &self.trading_hours {
+                match hours.action(data.timestamp, &mut state.flattened_for) {
+                    TradingAction::Trade => {}
+                    TradingAction::Flatten => {
+                        state.in_position = false;
+                        close_position(self.broker.as_ref(), &self.name, &data).await;
+                        continue;
+                    }
+                    TradingAction::Wait => continue,
+                }
+            }
+            let Some(rsi) = rsi else {
+                continue;
+            };
+
+            if state.in_position {
+                if rsi >= params.exit {
+                    debug!("{} reverted to RSI {rsi:.1}", data.symbol);
+                    // Also resets the state when the position was closed elsewhere
+                    close_position(self.broker.as_ref(), &self.name, &data).await;
+                    state.in_position = false;
+                }
+            } else if rsi < params.oversold && in_uptrend {
+                debug!("{} is oversold at RSI {rsi:.1}", data.symbol);
+                state.in_position = submit_sized_order(
+                    self.broker.as_ref(),
+                    self.position_sizer.as_ref(),
+                    &self.name,
+                    OrderSide::Buy,
+                    &data,
+                )
+                .await;
+            }
+        }
+    }
+}
@@ -1,17 +1,42 @@
 use crate::{
     broker::Broker,
     data_feed::DataFeed,
+    indicator::{Indicator, moving_average::Sma},
     position_sizer::PositionSizer,
-    strategy::Strategy,
-    types::{Order, OrderSide, OrderType},
+    strategy::{
+        Strategy, close_position, submit_sized_order,
+        trading_hours::{TradingAction, TradingHours},
+    },
+    types::OrderSide,
 };
 use async_trait::async_trait;
-use std::sync::Arc;
-use tracing::{debug, error, info, warn};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
+use tracing::{debug, error, warn};
 
 pub const DEFAULT_SMA_CROSS_FAST_WINDOW: usize = 50;
 pub const DEFAULT_SMA_CROSS_SLOW_WINDOW: usize = 200;
 
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct SmaCrossParams {
+    /// The number of prices of the fast SMA
+    #[serde(default = "default_fast_window")]
+    pub fast_window: usize,
+    /// The number of prices of the slow SMA
+    #[serde(default = "default_slow_window")]
+    pub slow_window: usize,
+}
+
+fn default_fast_window() -> usize {
+    DEFAULT_SMA_CROSS_FAST_WINDOW
+}
+
+fn default_slow_window() -> usize {
+    DEFAULT_SMA_CROSS_SLOW_WINDOW
+}
+
+/// Trades every symbol of its data feed independently, with its own SMAs and signals
 pub struct SmaCrossStrategy {
     name: String,
     data_feed: Box<dyn DataFeed>,
@@ -19,42 +44,37 @@ pub struct SmaCrossStrategy {
     position_sizer: Box<dyn PositionSizer>,
     slow_window: usize,
     fast_window: usize,
-    prices: Vec<f64>,
+    symbols: HashMap<String, SymbolState>,
+    trading_hours: Option<TradingHours>,
+}
+
+#[derive(Debug)]
+struct SymbolState {
+    fast: Sma,
+    slow: Sma,
     last_signal: Option<SmaCrossSignal>,
+    /// The close before which the position was last flattened
+    flattened_for: Option<DateTime<Utc>>,
 }
 
-impl SmaCrossStrategy {
-    pub fn new(
-        name: String,
-        data_feed: Box<dyn DataFeed>,
-        broker: Arc<dyn Broker>,
-        position_sizer: Box<dyn PositionSizer>,
-        fast_window: usize,
-        slow_window: usize,
-    ) -> Self {
+impl SymbolState {
+    fn new(fast_window: usize, slow_window: usize) -> Self {
         Self {
-            name,
-            data_feed,
-            broker,
-            position_sizer,
-            fast_window,
-            slow_window,
-            prices: Vec::new(),
+            fast: Sma::new(fast_window),
+            slow: Sma::new(slow_window),
             last_signal: None,
+            flattened_for: None,
         }
     }
 
-    fn sma(&self, window: usize) -> Option<f64> {
-        if self.prices.len() < window {
-            return None;
-        }
-        let slice = &self.prices[self.prices.len() - window..];
-        Some(slice.iter().copied().sum::<f64>() / window as f64)
+    fn update(&mut self, price: f64) {
+        self.fast.update(price);
+        self.slow.update(price);
     }
 
     fn check_signal(&mut self) -> Option<SmaCrossSignal> {
-        let fast = self.sma(self.fast_window)?;
-        let slow = self.sma(self.slow_window)?;
+        let fast = self.fast.value()?;
+        let slow = self.slow.value()?;
 
         let new_signal = if fast > slow {
             Some(SmaCrossSignal::Buy)
@@ -71,6 +91,34 @@ impl SmaCrossStrategy {
     }
 }
 
+impl SmaCrossStrategy {
+    pub fn new(
+        name: String,
+        data_feed: Box<dyn DataFeed>,
+        broker: Arc<dyn Broker>,
+        position_sizer: Box<dyn PositionSizer>,
+        fast_window: usize,
+        slow_window: usize,
+    ) -> Self {
+        Self {
+            name,
+            data_feed,
+            broker,
+            position_sizer,
+            fast_window,
+            slow_window,
+            symbols: HashMap::new(),
+            trading_hours: None,
+        }
+    }
+
+    /// Only trade in the given sessions, and optionally flatten positions before the close
+    pub fn with_trading_hours(mut self, trading_hours: TradingHours) -> Self {
+        self.trading_hours = Some(trading_hours);
+        self
+    }
+}
+
 #[async_trait]
 impl Strategy for SmaCrossStrategy {
     fn name(&self) -> &str {
@@ -78,49 +126,54 @@ impl Strategy for SmaCrossStrategy {
     }
 
     async fn run(&mut self) {
-        while let Some(data) = self.data_feed.next_tick().await {
-            self.prices.push(data.price);
-            if let Some(signal) = self.check_signal() {
-                let account_snapshot = self.broker.portfolio_snapshot().await;
-                let qty = self.position_sizer.size(&account_snapshot, data.price);
-                if qty == 0 {
-                    info!("Sizer return qty=0; skipping order");
+        while let Some(event) = self.data_feed.next_tick().await {
+            let data = match event {
+                Ok(data) => data,
+                Err(err) if err.is_fatal() => {
+                    error!("Stopping {}: {}", self.name, err);
+                    break;
+                }
+                Err(err) => {
+                    warn!("{} waits for data: {}", self.name, err);
                     continue;
                 }
-                let order = Order {
-                    symbol: data.symbol,
-                    side: signal.into(),
-                    qty,
-                    price: Some(data.price),
-                    order_type: OrderType::Market,
-                    strategy_name: self.name.clone(),
-                };
-                match self
-                    .broker
-                    .portfolio_pre_reserve_for_order(&order, data.price)
-                    .await
-                {
-                    Ok(_) => {
-                        match self.broker.place_order(&order).await {
-                            Ok(_) => {
-                                // TODO: Improve logging
-                                info!("Placed {:?} at price {}", order, data.price);
-                            }
-                            Err(err) => {
-                                error!("Failed to place order: {err}");
-                                self.broker
-                                    .portfolio_release_reserved_cash(qty, data.price)
-                                    .await;
-                            }
-                        }
-                    }
-                    Err(err) => {
-                        warn!("Order pre-check failed: {}", err);
+            };
+            self.broker.on_market_data(&data).await;
+            // The SMAs are calculated on traded prices only
+            if data.is_quote() {
+                continue;
+            }
+            let (fast_window, slow_window) = (self.fast_window, self.slow_window);
+            let state = self
+                .symbols
+                .entry(data.symbol.clone())
+                .or_insert_with(|| SymbolState::new(fast_window, slow_window));
+            state.update(data.price);
+            if let Some(hours) = &self.trading_hours {
+                match hours.action(data.timestamp, &mut state.flattened_for) {
+                    TradingAction::Trade => {}
+                    TradingAction::Flatten => {
+                        // The trend is evaluated again in the next session
+                        state.last_signal = None;
+                        close_position(self.broker.as_ref(), &self.name, &data).await;
+                        continue;
                     }
+                    // Signals outside the sessions are acted upon in the next one
+                    TradingAction::Wait => continue,
                 }
+            }
+            if let Some(signal) = state.check_signal() {
+                submit_sized_order(
+                    self.broker.as_ref(),
+                    self.position_sizer.as_ref(),
+                    &self.name,
+                    signal.into(),
+                    &data,
+                )
+                .await;
             } else {
                 // TODO: Improve logging. Why no signal at the specific price
-                debug!("No signal at price {}", data.price);
+                debug!("No signal for {} at price {}", data.symbol, data.price);
             }
         }
     }
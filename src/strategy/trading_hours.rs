@@ -0,0 +1,126 @@
+use crate::calendar::{ExchangeCalendar, Session};
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+
+/// What a strategy does with an event, given its trading hours
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradingAction {
+    Trade,
+    /// Outside the sessions, or already flattened before the close
+    Wait,
+    /// Close the positions: the close is near
+    Flatten,
+}
+
+/// When a strategy may open positions, and whether it closes them before the market closes
+#[derive(Debug, Clone)]
+pub struct TradingHours {
+    calendar: Arc<ExchangeCalendar>,
+    sessions: Vec<Session>,
+    flatten_before_close: Option<Duration>,
+}
+
+impl TradingHours {
+    pub fn new(
+        calendar: Arc<ExchangeCalendar>,
+        sessions: Vec<Session>,
+        flatten_before_close: Option<Duration>,
+    ) -> Self {
+        Self {
+            calendar,
+            sessions,
+            flatten_before_close,
+        }
+    }
+
+    pub fn calendar(&self) -> &ExchangeCalendar {
+        &self.calendar
+    }
+
+    /// Whether orders may be placed at `timestamp`: it is in one of the sessions and not in
+    /// the time before the close that is reserved for flattening
+    pub fn can_trade(&self, timestamp: DateTime<Utc>) -> bool {
+        self.sessions.contains(&self.calendar.session(timestamp))
+            && self.flatten_deadline(timestamp).is_none()
+    }
+
+    /// The regular close that positions have to be flattened for, if `timestamp` is in the
+    /// time before it (or after it, on the same day)
+    pub fn flatten_deadline(&self, timestamp: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let before_close = self.flatten_before_close?;
+        let close = self.calendar.regular_close(timestamp)?;
+        (timestamp >= close - before_close).then_some(close)
+    }
+
+    /// The action for an event at `timestamp`. `flattened_for` is the close positions were
+    /// last flattened for, so they are flattened once per close.
+    pub fn action(
+        &self,
+        timestamp: DateTime<Utc>,
+        flattened_for: &mut Option<DateTime<Utc>>,
+    ) -> TradingAction {
+        match self.flatten_deadline(timestamp) {
+            Some(close) if *flattened_for != Some(close) => {
+                *flattened_for = Some(close);
+                TradingAction::Flatten
+            }
+            Some(_) => TradingAction::Wait,
+            None if self.can_trade(timestamp) => TradingAction::Trade,
+            None => TradingAction::Wait,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::calendar::Exchange;
+
+    fn utc(datetime: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(datetime).unwrap().into()
+    }
+
+    #[test]
+    fn test_trading_hours() {
+        let hours = TradingHours::new(
+            Arc::new(ExchangeCalendar::new(Exchange::Nyse)),
+            vec![Session::Regular],
+            Some(Duration::minutes(15)),
+        );
+        // 2024-07-01: regular session from 13:30 to 20:00 UTC
+        assert!(!hours.can_trade(utc("2024-07-01T13:29:00Z")));
+        assert!(hours.can_trade(utc("2024-07-01T13:30:00Z")));
+        assert!(hours.can_trade(utc("2024-07-01T19:44:00Z")));
+        assert_eq!(hours.flatten_deadline(utc("2024-07-01T19:44:00Z")), None);
+        assert!(!hours.can_trade(utc("2024-07-01T19:45:00Z")));
+        assert_eq!(
+            hours.flatten_deadline(utc("2024-07-01T19:45:00Z")),
+            Some(utc("2024-07-01T20:00:00Z"))
+        );
+        // Early close on the day before Independence Day
+        assert!(
+            hours
+                .flatten_deadline(utc("2024-07-03T16:50:00Z"))
+                .is_some()
+        );
+
+        let mut flattened_for = None;
+        let action = |timestamp, flattened_for: &mut _| hours.action(utc(timestamp), flattened_for);
+        assert_eq!(
+            action("2024-07-01T13:00:00Z", &mut flattened_for),
+            TradingAction::Wait
+        );
+        assert_eq!(
+            action("2024-07-01T19:45:00Z", &mut flattened_for),
+            TradingAction::Flatten
+        );
+        assert_eq!(
+            action("2024-07-01T19:46:00Z", &mut flattened_for),
+            TradingAction::Wait
+        );
+        assert_eq!(
+            action("2024-07-02T13:30:00Z", &mut flattened_for),
+            TradingAction::Trade
+        );
+    }
+}
@@ -23,13 +23,49 @@ pub struct Order {
     pub strategy_name: String,
 }
 
+/// The id a broker tracks a placed order by
+pub type OrderId = String;
+
+/// The state of a placed order, as far as the broker reported it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    /// Working at the broker, possibly partially filled
+    Open {
+        filled_qty: u32,
+    },
+    Filled,
+    /// Cancelled (or rejected) before it was completely filled
+    Cancelled {
+        filled_qty: u32,
+    },
+}
+
 #[derive(Debug, Clone)]
 pub struct Position {
     pub symbol: String,
-    pub qty: u32,
+    /// Negative for short positions
+    pub qty: i64,
+    /// The average price the position was opened at (sold at, for short positions)
     pub avg_price: f64,
 }
 
+impl Position {
+    /// Add `qty` bought (positive) or sold (negative) at `price`. Orders that add to the
+    /// position update its average price, orders that reduce it keep it, and orders that turn
+    /// it from long to short (or back) start it over at `price`.
+    pub fn apply(&mut self, qty: i64, price: f64) {
+        let new_qty = self.qty + qty;
+        if self.qty == 0 || self.qty.signum() == qty.signum() {
+            let prev_value = self.qty.abs() as f64 * self.avg_price;
+            let added_value = qty.abs() as f64 * price;
+            self.avg_price = (prev_value + added_value) / new_qty.abs() as f64;
+        } else if new_qty.signum() == qty.signum() {
+            self.avg_price = price;
+        }
+        self.qty = new_qty;
+    }
+}
+
 #[derive(Debug)]
 pub struct Fill {
     pub order_id: String,
@@ -38,4 +74,12 @@ pub struct Fill {
     pub price: f64,
     pub side: OrderSide,
     pub timestamp: NaiveDateTime,
+    /// The broker's execution id, if the broker reports one (used to de-duplicate fills)
+    pub execution_id: Option<String>,
+    /// The commission charged for this fill
+    pub commission: f64,
+    /// The strategy that placed the order
+    pub strategy_name: String,
+    /// The price the order reserved cash (or margin) at, which the fill releases
+    pub reservation_price: f64,
 }
@@ -0,0 +1,112 @@
+use rusty_trader::broker::dummy::DummyBroker;
+use rusty_trader::data_feed::csv_data_feed::CsvDataFeed;
+use rusty_trader::position_sizer::fixed_sizer::FixedSizer;
+use rusty_trader::strategy::Strategy;
+use rusty_trader::strategy::breakout::{BreakoutParams, BreakoutStrategy, SqueezeParams};
+use rusty_trader::types::{Order, OrderSide};
+use std::io::Write;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+mod common;
+use common::Scenario;
+
+/// Bars with highs and lows: a range between 98 and 102 for 60 bars and a rally by 2 per bar
+/// to 140
+fn range_and_rally() -> Vec<f64> {
+    let range = (0..60).map(|i| if i % 2 == 0 { 98.0 } else { 102.0 });
+    let rally = (1..=20).map(|i| 100.0 + i as f64 * 2.0);
+    range.chain(rally).collect()
+}
+
+async fn run_strategy(params: BreakoutParams) -> Vec<Order> {
+    // The rally drops by 5 per bar back to 100
+    let drop = (1..=8).map(|i| 140.0 - i as f64 * 5.0);
+    let closes: Vec<f64> = range_and_rally().into_iter().chain(drop).collect();
+    let csv_feed_file = Scenario::minutes(closes.len(), &["AAPL"])
+        .with_bar_range(1.0)
+        .generate(|i, _| Some(closes[i]));
+    run_on(csv_feed_file, params).await
+}
+
+async fn run_on(csv_feed_file: NamedTempFile, params: BreakoutParams) -> Vec<Order> {
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let mut strat = BreakoutStrategy::new(
+        "TestBreakout".to_string(),
+        Box::new(feed),
+        broker.clone(),
+        Box::new(FixedSizer::new("Fixed sizer".into(), 2)),
+        params,
+    );
+    strat.run().await;
+    broker.get_orders().await
+}
+
+#[tokio::test]
+async fn test_breakout_pyramids_and_exits_below_the_channel() {
+    let orders = run_strategy(BreakoutParams::default()).await;
+    let sides: Vec<_> = orders.iter().map(|order| order.side).collect();
+    assert_eq!(
+        sides,
+        vec![
+            OrderSide::Buy,
+            OrderSide::Buy,
+            OrderSide::Buy,
+            OrderSide::Buy,
+            OrderSide::Sell
+        ]
+    );
+    // The first high above the range of 103
+    assert_eq!(orders[0].price, Some(104.0));
+    // All units at once, when the low of 124 breaks below the lows of the last 10 bars (125)
+    assert_eq!(orders[4].qty, 8);
+    assert_eq!(orders[4].price, Some(125.0));
+}
+
+#[tokio::test]
+async fn test_breakout_out_of_squeeze_only() {
+    let squeeze = |max_bandwidth| BreakoutParams {
+        squeeze: Some(SqueezeParams {
+            max_bandwidth,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    // The bandwidth of the range is 0.08
+    assert!(run_strategy(squeeze(0.05)).await.is_empty());
+    assert_eq!(run_strategy(squeeze(0.1)).await.len(), 5);
+}
+
+#[tokio::test]
+async fn test_breakout_stop_sells_at_the_stop_or_the_gap() {
+    // After the rally, a bar that opens at `open`, dips to 90 and closes back at 139
+    let run = |open: f64| async move {
+        let closes = range_and_rally();
+        let mut csv_feed_file = Scenario::minutes(closes.len(), &["AAPL"])
+            .with_bar_range(1.0)
+            .generate(|i, _| Some(closes[i]));
+        writeln!(
+            csv_feed_file,
+            "2023-01-01 10:50:00,139,1500,AAPL,{open},141,90"
+        )
+        .unwrap();
+        run_on(csv_feed_file, BreakoutParams::default()).await
+    };
+
+    // The bar trades through the stop, which sells there rather than at the close
+    let orders = run(140.0).await;
+    let sell = orders.last().unwrap();
+    assert_eq!(sell.side, OrderSide::Sell);
+    let stop = sell.price.unwrap();
+    assert!(stop > 90.0 && stop < 139.0, "{orders:?}");
+
+    // The bar opens below the stop, which sells at the open
+    let orders = run(95.0).await;
+    let sell = orders.last().unwrap();
+    assert_eq!(sell.side, OrderSide::Sell);
+    assert!(stop > 95.0);
+    assert_eq!(sell.price, Some(95.0));
+}
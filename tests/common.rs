@@ -1,3 +1,6 @@
+// Every test crate includes this module but uses only some of the generators
+#![allow(dead_code)]
+
 use chrono::{Duration, NaiveDateTime};
 use std::io::Write;
 use tempfile::NamedTempFile;
@@ -19,3 +22,87 @@ pub fn generate_backtest_csv() -> NamedTempFile {
     }
     file
 }
+
+/// Prices of one or more symbols at a fixed interval, written as a CSV with one row per symbol
+/// and step
+pub struct Scenario {
+    start: NaiveDateTime,
+    interval: Duration,
+    steps: usize,
+    symbols: Vec<&'static str>,
+    /// Rows are bars whose high and low are this far from the close
+    bar_range: Option<f64>,
+}
+
+impl Scenario {
+    /// Every minute from 2023-01-01 09:30:00
+    pub fn minutes(steps: usize, symbols: &[&'static str]) -> Self {
+        Self {
+            start: parse_timestamp("2023-01-01 09:30:00"),
+            interval: Duration::minutes(1),
+            steps,
+            symbols: symbols.to_vec(),
+            bar_range: None,
+        }
+    }
+
+    /// Every day from Monday 2024-01-01 14:30:00
+    pub fn days(steps: usize, symbols: &[&'static str]) -> Self {
+        Self {
+            start: parse_timestamp("2024-01-01 14:30:00"),
+            interval: Duration::days(1),
+            ..Self::minutes(steps, symbols)
+        }
+    }
+
+    /// Start at `start` (`YYYY-MM-DD HH:MM:SS`) instead
+    pub fn starting_at(self, start: &str) -> Self {
+        Self {
+            start: parse_timestamp(start),
+            ..self
+        }
+    }
+
+    /// Write bars whose high and low are `range` away from the close
+    pub fn with_bar_range(self, range: f64) -> Self {
+        Self {
+            bar_range: Some(range),
+            ..self
+        }
+    }
+
+    /// `price(step, symbol)` is the close of `symbol` at `step`, or `None` where it does not
+    /// trade
+    pub fn generate(&self, price: impl Fn(usize, &str) -> Option<f64>) -> NamedTempFile {
+        let mut file = NamedTempFile::new().unwrap();
+        match self.bar_range {
+            Some(_) => writeln!(file, "timestamp,price,volume,symbol,open,high,low").unwrap(),
+            None => writeln!(file, "timestamp,price,volume,symbol").unwrap(),
+        }
+        for step in 0..self.steps {
+            let ts = self.start + self.interval * step as i32;
+            for symbol in &self.symbols {
+                let Some(close) = price(step, symbol) else {
+                    continue;
+                };
+                write!(file, "{},{},{},{}", ts, close, 1500, symbol).unwrap();
+                if let Some(range) = self.bar_range {
+                    write!(file, ",{},{},{}", close, close + range, close - range).unwrap();
+                }
+                writeln!(file).unwrap();
+            }
+        }
+        file
+    }
+}
+
+fn parse_timestamp(timestamp: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S").unwrap()
+}
+
+/// A sine wave with a period of 50 steps and an amplitude of 10 around a line that starts at
+/// 100 and changes by `trend` per step
+pub fn wave(step: usize, trend: f64) -> f64 {
+    let wave = 10.0 * (step as f64 * std::f64::consts::TAU / 50.0).sin();
+    100.0 + trend * step as f64 + wave
+}
@@ -0,0 +1,84 @@
+use rusty_trader::broker::Broker;
+use rusty_trader::broker::dummy::DummyBroker;
+use rusty_trader::data_feed::csv_data_feed::CsvDataFeed;
+use rusty_trader::strategy::Strategy;
+use rusty_trader::strategy::dca_rebalance::{DcaRebalanceParams, DcaRebalanceStrategy, Schedule};
+use rusty_trader::types::{Order, OrderSide};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+mod common;
+use common::Scenario;
+
+async fn run_strategy(broker: Arc<DummyBroker>, params: DcaRebalanceParams) -> Vec<Order> {
+    // UP rises from 20 by 0.5 a day, DOWN falls from 40 by 0.25 a day
+    let csv_feed_file = Scenario::days(60, &["UP", "DOWN"]).generate(|i, symbol| match symbol {
+        "UP" => Some(20.0 + i as f64 * 0.5),
+        _ => Some(40.0 - i as f64 * 0.25),
+    });
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let mut strat = DcaRebalanceStrategy::new(
+        "TestDca".to_string(),
+        Box::new(feed),
+        broker.clone(),
+        params,
+    );
+    strat.run().await;
+    broker.get_orders().await
+}
+
+fn equal_weights() -> BTreeMap<String, f64> {
+    BTreeMap::from([("UP".to_string(), 0.5), ("DOWN".to_string(), 0.5)])
+}
+
+#[tokio::test]
+async fn test_dca_buys_the_basket_every_week() {
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let params = DcaRebalanceParams {
+        weights: equal_weights(),
+        schedule: Schedule::Weekly {
+            weekday: chrono::Weekday::Mon,
+        },
+        amount: Some(100.0),
+        drift_band: None,
+    };
+    let orders = run_strategy(broker.clone(), params).await;
+
+    // Both symbols on each of the 9 Mondays, with less UP as it gets more expensive
+    assert_eq!(orders.len(), 18, "{orders:?}");
+    assert!(orders.iter().all(|order| order.side == OrderSide::Buy));
+    let up: Vec<_> = orders.iter().filter(|order| order.symbol == "UP").collect();
+    assert_eq!((up[0].qty, up[0].price), (2, Some(20.0)));
+    assert_eq!((up[8].qty, up[8].price), (1, Some(48.0)));
+    let bought_up: u32 = up.iter().map(|order| order.qty).sum();
+    assert_eq!(broker.position_qty("UP").await, bought_up as i64);
+}
+
+#[tokio::test]
+async fn test_rebalance_sells_the_winner_beyond_the_drift_band() {
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let params = DcaRebalanceParams {
+        weights: equal_weights(),
+        schedule: Schedule::Monthly { day: 1 },
+        amount: None,
+        drift_band: Some(0.05),
+    };
+    let orders = run_strategy(broker.clone(), params).await;
+    let trades: Vec<_> = orders
+        .iter()
+        .map(|order| (order.symbol.as_str(), order.side, order.qty))
+        .collect();
+
+    // Invests the cash on January 1st, and rebalances from UP to DOWN on February 1st
+    assert_eq!(
+        trades,
+        vec![
+            ("DOWN", OrderSide::Buy, 12),
+            ("UP", OrderSide::Buy, 25),
+            ("UP", OrderSide::Sell, 7),
+            ("DOWN", OrderSide::Buy, 8),
+        ]
+    );
+}
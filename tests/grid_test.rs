@@ -0,0 +1,120 @@
+use rusty_trader::broker::Broker;
+use rusty_trader::broker::dummy::DummyBroker;
+use rusty_trader::data_feed::csv_data_feed::CsvDataFeed;
+use rusty_trader::position_sizer::fixed_sizer::FixedSizer;
+use rusty_trader::strategy::Strategy;
+use rusty_trader::strategy::grid::{GridParams, GridStrategy};
+use rusty_trader::types::{OrderSide, OrderStatus, OrderType};
+use std::sync::Arc;
+
+mod common;
+use common::{Scenario, wave};
+
+async fn run_strategy(trend: f64, params: GridParams) -> Arc<DummyBroker> {
+    let csv_feed_file = Scenario::minutes(400, &["AAPL"]).generate(|i, _| Some(wave(i, trend)));
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let mut strat = GridStrategy::new(
+        "TestGrid".to_string(),
+        Box::new(feed),
+        broker.clone(),
+        Box::new(FixedSizer::new("Fixed sizer".into(), 1)),
+        params,
+    );
+    strat.run().await;
+    broker
+}
+
+/// The placed orders with their final status
+async fn orders_with_status(broker: &DummyBroker) -> Vec<(OrderSide, f64, OrderStatus)> {
+    let mut orders = Vec::new();
+    for (id, order) in broker.get_orders().await.into_iter().enumerate() {
+        assert!(matches!(order.order_type, OrderType::Limit));
+        let status = broker.order_status(&id.to_string()).await.unwrap();
+        orders.push((order.side, order.price.unwrap(), status));
+    }
+    orders
+}
+
+#[tokio::test]
+async fn test_grid_buys_low_and_sells_one_level_higher() {
+    // Three levels 2% apart
+    let params = GridParams {
+        levels: 3,
+        spacing: 0.02,
+        max_inventory: None,
+    };
+    let broker = run_strategy(0.0, params).await;
+    let orders = orders_with_status(&broker).await;
+
+    let filled = |side| {
+        let mut prices: Vec<_> = orders
+            .iter()
+            .filter(|(order_side, _, status)| *order_side == side && *status == OrderStatus::Filled)
+            .map(|(_, price, _)| *price)
+            .collect();
+        prices.sort_by(f64::total_cmp);
+        prices
+    };
+    let (buys, sells) = (filled(OrderSide::Buy), filled(OrderSide::Sell));
+    // The ladder moves up until the wave turns, and then every swing fills all of its levels
+    let mut levels = buys.clone();
+    levels.dedup();
+    assert_eq!(levels.len(), 3, "{orders:?}");
+    assert!(buys.len() >= 3 * 6, "{orders:?}");
+    assert!(buys.len() - sells.len() <= 3);
+    // Each level sells one level higher
+    let step = levels[1] - levels[0];
+    assert!((levels[2] - levels[1] - step).abs() < 1e-9);
+    assert!(
+        sells
+            .iter()
+            .all(|sell| { levels.iter().any(|buy| (sell - buy - step).abs() < 1e-9) })
+    );
+    // Every round trip earns at least a step, more when a limit fills at a better open
+    let account = broker.portfolio_snapshot().await;
+    // What is held is offered one step above its cost
+    let held: f64 = orders
+        .iter()
+        .filter(|(side, _, status)| {
+            *side == OrderSide::Sell && matches!(status, OrderStatus::Open { .. })
+        })
+        .map(|(_, price, _)| price - step)
+        .sum();
+    let earned = account.cash + held - 1000.0;
+    assert!(earned >= step * sells.len() as f64 - 1e-6, "{earned}");
+}
+
+#[tokio::test]
+async fn test_grid_caps_inventory_and_moves_up_with_the_price() {
+    let params = GridParams {
+        levels: 3,
+        spacing: 0.02,
+        max_inventory: Some(2),
+    };
+    let broker = run_strategy(0.1, params).await;
+    let orders = orders_with_status(&broker).await;
+
+    // Never more than two units held or bid for
+    let open_buys = orders
+        .iter()
+        .filter(|(side, _, status)| {
+            *side == OrderSide::Buy && matches!(status, OrderStatus::Open { .. })
+        })
+        .count() as i64;
+    assert!(broker.position_qty("AAPL").await + open_buys <= 2);
+    // The ladder was taken down and placed higher as the price rose
+    let cancelled = orders
+        .iter()
+        .filter(|(_, _, status)| matches!(status, OrderStatus::Cancelled { .. }))
+        .count();
+    assert!(cancelled > 0);
+    let highest_buy = orders
+        .iter()
+        .filter(|(side, _, _)| *side == OrderSide::Buy)
+        .map(|(_, price, _)| *price)
+        .fold(0.0, f64::max);
+    assert!(highest_buy > 120.0, "{orders:?}");
+}
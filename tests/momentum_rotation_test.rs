@@ -0,0 +1,186 @@
+use rusty_trader::broker::Broker;
+use rusty_trader::broker::dummy::DummyBroker;
+use rusty_trader::data_feed::DataFeed;
+use rusty_trader::data_feed::csv_data_feed::CsvDataFeed;
+use rusty_trader::data_feed::synthetic_data_feed::{
+    PriceModel, Regime, SyntheticDataFeed, SyntheticDataFeedConfig,
+};
+use rusty_trader::strategy::Strategy;
+use rusty_trader::strategy::momentum_rotation::{
+    MomentumRotationParams, MomentumRotationStrategy, Weighting,
+};
+use rusty_trader::types::OrderSide;
+use std::sync::Arc;
+use tempfile::NamedTempFile;
+
+mod common;
+use common::Scenario;
+
+fn params() -> MomentumRotationParams {
+    MomentumRotationParams {
+        lookback: 20,
+        rebalance_days: 30,
+        top: 2,
+        weighting: Weighting::Equal,
+        volatility_window: 20,
+        min_return: None,
+        max_stale_days: 5,
+    }
+}
+
+async fn run_strategy(feed: Box<dyn DataFeed>, params: MomentumRotationParams) -> Arc<DummyBroker> {
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let mut strat =
+        MomentumRotationStrategy::new("TestMomentum".to_string(), feed, broker.clone(), params);
+    strat.run().await;
+    broker
+}
+
+/// Daily prices from 2024-01-01 for 200 days. A leads for the first 100 days and then falls,
+/// while C is flat and then leads. B rises slowly throughout and D falls, until `d_until`.
+fn universe_csv(d_until: Option<usize>) -> NamedTempFile {
+    Scenario::days(200, &["A", "B", "C", "D"]).generate(|i, symbol| {
+        let day = i as f64;
+        let late = (day - 100.0).max(0.0);
+        match symbol {
+            "A" => Some(50.0 + 0.5 * day.min(100.0) - 0.5 * late),
+            "B" => Some(50.0 + 0.2 * day),
+            "C" => Some(50.0 + 0.6 * late),
+            _ => d_until
+                .is_none_or(|until| i < until)
+                .then_some(80.0 - 0.1 * day),
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_momentum_rotation_rotates_into_the_leaders() {
+    let csv_feed_file = universe_csv(None);
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let broker = run_strategy(Box::new(feed), params()).await;
+    let orders = broker.get_orders().await;
+
+    // A and B lead first: half of the 1000 each
+    let first_buys: Vec<_> = orders
+        .iter()
+        .take(2)
+        .map(|order| (order.symbol.as_str(), order.side, order.qty))
+        .collect();
+    assert_eq!(
+        first_buys,
+        vec![("A", OrderSide::Buy, 8), ("B", OrderSide::Buy, 9)]
+    );
+    // D never leads
+    assert!(orders.iter().all(|order| order.symbol != "D"));
+    // A is sold once it falls, and C bought as it leads
+    let a_sold = orders
+        .iter()
+        .position(|order| order.symbol == "A" && order.side == OrderSide::Sell)
+        .unwrap();
+    let c_bought = orders
+        .iter()
+        .position(|order| order.symbol == "C" && order.side == OrderSide::Buy)
+        .unwrap();
+    assert!(a_sold < c_bought, "{orders:?}");
+    assert_eq!(broker.position_qty("A").await, 0);
+    assert!(broker.position_qty("B").await > 0);
+    assert!(broker.position_qty("C").await > 0);
+}
+
+#[tokio::test]
+async fn test_momentum_rotation_drops_symbols_that_stop_trading() {
+    // D stops trading before the second ranking
+    let csv_feed_file = universe_csv(Some(45));
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let broker = run_strategy(Box::new(feed), params()).await;
+
+    // The rankings go on without D
+    assert!(broker.position_qty("C").await > 0);
+    assert_eq!(broker.position_qty("A").await, 0);
+}
+
+#[tokio::test]
+async fn test_momentum_rotation_sells_held_symbols_that_stop_trading() {
+    // A leads the first ranking and stops trading on day 40
+    let csv_feed_file = Scenario::days(100, &["A", "B", "C"]).generate(|i, symbol| {
+        let day = i as f64;
+        match symbol {
+            "A" => (i < 40).then_some(50.0 + 0.5 * day),
+            "B" => Some(50.0 + 0.2 * day),
+            _ => Some(50.0),
+        }
+    });
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let broker = run_strategy(Box::new(feed), params()).await;
+    let orders = broker.get_orders().await;
+
+    assert_eq!(orders[0].symbol, "A");
+    assert_eq!(orders[0].side, OrderSide::Buy);
+    // Dropped, and sold at its last price
+    let a_sold = orders
+        .iter()
+        .find(|order| order.symbol == "A" && order.side == OrderSide::Sell)
+        .unwrap();
+    assert_eq!(a_sold.qty, orders[0].qty);
+    assert_eq!(a_sold.price, Some(50.0 + 0.5 * 39.0));
+    assert_eq!(broker.position_qty("A").await, 0);
+}
+
+#[tokio::test]
+async fn test_momentum_rotation_on_synthetic_universe() {
+    // Six symbols that switch between bull and bear regimes, the same on every run
+    let config = SyntheticDataFeedConfig {
+        model: PriceModel::RegimeSwitching {
+            regimes: vec![
+                Regime {
+                    drift: 0.4,
+                    volatility: 0.2,
+                },
+                Regime {
+                    drift: -0.3,
+                    volatility: 0.35,
+                },
+            ],
+            switch_probability: 0.02,
+        },
+        seed: 7,
+        initial_price: 50.0,
+        steps: 300,
+        interval_secs: 86_400,
+        start: "2024-01-01T14:30:00Z".parse().unwrap(),
+    };
+    let symbols: Vec<String> = ["A", "B", "C", "D", "E", "F"].map(String::from).to_vec();
+    let mut runs = Vec::new();
+    for _ in 0..2 {
+        let feed =
+            SyntheticDataFeed::new("synthetic".into(), symbols.clone(), config.clone()).unwrap();
+        let broker = run_strategy(Box::new(feed), params()).await;
+
+        let mut held = 0;
+        for symbol in &symbols {
+            let qty = broker.position_qty(symbol).await;
+            assert!(qty >= 0, "{symbol} is short");
+            held += (qty > 0) as usize;
+        }
+        assert!(held <= 2);
+        let account = broker.portfolio_snapshot().await;
+        assert!(account.cash >= 0.0);
+        assert_eq!(account.reserved_cash, 0.0);
+
+        let orders: Vec<_> = broker
+            .get_orders()
+            .await
+            .into_iter()
+            .map(|order| (order.symbol, order.side, order.qty))
+            .collect();
+        assert!(!orders.is_empty());
+        runs.push(orders);
+    }
+    assert_eq!(runs[0], runs[1]);
+}
@@ -0,0 +1,92 @@
+use rusty_trader::broker::Broker;
+use rusty_trader::broker::dummy::DummyBroker;
+use rusty_trader::data_feed::csv_data_feed::CsvDataFeed;
+use rusty_trader::position_sizer::fixed_sizer::FixedSizer;
+use rusty_trader::strategy::Strategy;
+use rusty_trader::strategy::pairs_trading::{
+    HedgeRatioModel, PairsTradingParams, PairsTradingStrategy,
+};
+use rusty_trader::types::{Order, OrderSide};
+use std::sync::Arc;
+
+mod common;
+use common::Scenario;
+
+fn params(hedge_ratio: HedgeRatioModel) -> PairsTradingParams {
+    PairsTradingParams {
+        symbol_y: "Y".into(),
+        symbol_x: "X".into(),
+        hedge_ratio,
+        z_window: 60,
+        entry_z: 1.2,
+        exit_z: 0.3,
+        stop_z: None,
+    }
+}
+
+async fn run_strategy(broker: Arc<DummyBroker>, params: PairsTradingParams) -> Vec<Order> {
+    // X wanders around 100 and Y follows `2 * X + 5`, plus a spread that oscillates between
+    // -3 and 3 every 40 minutes
+    let csv_feed_file = Scenario::minutes(400, &["X", "Y"]).generate(|i, symbol| {
+        let x = 100.0 + 10.0 * (i as f64 / 30.0).sin();
+        let spread = 3.0 * (i as f64 * std::f64::consts::TAU / 40.0).sin();
+        match symbol {
+            "X" => Some(x),
+            _ => Some(2.0 * x + 5.0 + spread),
+        }
+    });
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let mut strat = PairsTradingStrategy::new(
+        "TestPairs".to_string(),
+        Box::new(feed),
+        broker.clone(),
+        Box::new(FixedSizer::new("Fixed sizer".into(), 2)),
+        params,
+    );
+    strat.run().await;
+    broker.get_orders().await
+}
+
+#[tokio::test]
+async fn test_pairs_trading_opens_and_closes_both_legs() {
+    for hedge_ratio in [
+        HedgeRatioModel::Ols { window: 60 },
+        HedgeRatioModel::Kalman {
+            delta: 1e-4,
+            observation_variance: 1.0,
+        },
+    ] {
+        let broker = Arc::new(DummyBroker::new("Dummy".to_string()).with_short_selling());
+        let orders = run_strategy(broker.clone(), params(hedge_ratio)).await;
+        assert!(orders.len() >= 8, "{hedge_ratio:?}: {orders:?}");
+        // Every entry and exit trades both legs in opposite directions
+        for legs in orders.chunks(2) {
+            let (y, x) = (&legs[0], &legs[1]);
+            assert_eq!((y.symbol.as_str(), x.symbol.as_str()), ("Y", "X"));
+            assert_ne!(y.side, x.side);
+            // About two X per Y
+            assert!((3..=5).contains(&x.qty), "{hedge_ratio:?}: {legs:?}");
+        }
+        // Round trips alternate between opening and closing
+        for round_trip in orders.chunks(4).filter(|orders| orders.len() == 4) {
+            assert_ne!(round_trip[0].side, round_trip[2].side);
+        }
+        assert!(broker.position_qty("X").await.abs() <= 5);
+    }
+}
+
+#[tokio::test]
+async fn test_pairs_trading_needs_short_selling() {
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let orders = run_strategy(broker.clone(), params(HedgeRatioModel::default())).await;
+    // Only long legs go through, and they are unwound right away
+    assert!(
+        orders
+            .iter()
+            .all(|order| { order.side == OrderSide::Buy || order.symbol == "Y" })
+    );
+    assert_eq!(broker.position_qty("X").await, 0);
+    assert_eq!(broker.position_qty("Y").await, 0);
+}
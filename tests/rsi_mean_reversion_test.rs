@@ -0,0 +1,62 @@
+use rusty_trader::broker::dummy::DummyBroker;
+use rusty_trader::data_feed::csv_data_feed::CsvDataFeed;
+use rusty_trader::position_sizer::fixed_sizer::FixedSizer;
+use rusty_trader::strategy::Strategy;
+use rusty_trader::strategy::rsi_mean_reversion::{
+    RsiMeanReversionParams, RsiMeanReversionStrategy,
+};
+use rusty_trader::types::{Order, OrderSide};
+use std::sync::Arc;
+
+mod common;
+use common::{Scenario, wave};
+
+async fn run_strategy(trend: f64, params: RsiMeanReversionParams) -> Vec<Order> {
+    let csv_feed_file = Scenario::minutes(400, &["AAPL"]).generate(|i, _| Some(wave(i, trend)));
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let mut strat = RsiMeanReversionStrategy::new(
+        "TestRSI".to_string(),
+        Box::new(feed),
+        broker.clone(),
+        Box::new(FixedSizer::new("Fixed sizer".into(), 2)),
+        params,
+    );
+    strat.run().await;
+    broker.get_orders().await
+}
+
+#[tokio::test]
+async fn test_rsi_mean_reversion_buys_dips_and_sells_recoveries() {
+    let orders = run_strategy(0.0, RsiMeanReversionParams::default()).await;
+    // One round trip per wave after the RSI warmed up
+    assert!(orders.len() >= 12, "{orders:?}");
+    for (i, order) in orders.iter().enumerate() {
+        let side = if i % 2 == 0 {
+            OrderSide::Buy
+        } else {
+            OrderSide::Sell
+        };
+        assert_eq!(order.side, side);
+    }
+    for round_trip in orders.chunks(2) {
+        assert!(round_trip[0].price.unwrap() < 100.0);
+        assert_eq!(round_trip[0].qty, round_trip.last().unwrap().qty);
+    }
+}
+
+#[tokio::test]
+async fn test_rsi_mean_reversion_trend_filter() {
+    let params = RsiMeanReversionParams {
+        trend_window: Some(100),
+        ..Default::default()
+    };
+    // The dips of a downtrend stay below the SMA
+    assert!(run_strategy(-0.2, params).await.is_empty());
+    // The dips of an uptrend stay above it
+    let orders = run_strategy(0.2, params).await;
+    assert!(!orders.is_empty());
+    assert_eq!(orders[0].side, OrderSide::Buy);
+}
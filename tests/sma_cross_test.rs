@@ -1,13 +1,16 @@
+use rusty_trader::broker::Broker;
 use rusty_trader::broker::dummy::DummyBroker;
+use rusty_trader::calendar::{Exchange, ExchangeCalendar, Session};
 use rusty_trader::data_feed::csv_data_feed::CsvDataFeed;
 use rusty_trader::position_sizer::fixed_sizer::FixedSizer;
 use rusty_trader::strategy::Strategy;
 use rusty_trader::strategy::sma_cross::SmaCrossStrategy;
-use rusty_trader::types::OrderSide;
+use rusty_trader::strategy::trading_hours::TradingHours;
+use rusty_trader::types::{Order, OrderSide, OrderType};
 use std::sync::Arc;
 
 mod common;
-use common::generate_backtest_csv;
+use common::{Scenario, generate_backtest_csv};
 
 #[tokio::test]
 async fn test_sma_cross_strategy_signals() {
@@ -30,3 +33,93 @@ async fn test_sma_cross_strategy_signals() {
     assert_eq!(orders[0].side, OrderSide::Buy);
     assert_eq!(orders[1].side, OrderSide::Sell);
 }
+
+#[tokio::test]
+async fn test_sma_cross_strategy_trades_symbols_independently() {
+    // UP rises then falls like `generate_backtest_csv`, DOWN mirrors it
+    let csv_feed_file = Scenario::minutes(400, &["UP", "DOWN"]).generate(|i, symbol| {
+        let change = if i < 200 {
+            i as f64 * 0.2
+        } else {
+            40.0 - (i as f64 - 200.0) * 0.3
+        };
+        match symbol {
+            "UP" => Some(100.0 + change),
+            _ => Some(150.0 - change),
+        }
+    });
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    let mut strat = SmaCrossStrategy::new(
+        "TestSMA".to_string(),
+        Box::new(feed),
+        broker.clone(),
+        Box::new(FixedSizer::new("Fixed sizer".into(), 2)),
+        50,
+        200,
+    );
+    strat.run().await;
+    let orders = broker.get_orders().await;
+    let sides = |symbol: &str| {
+        orders
+            .iter()
+            .filter(|order| order.symbol == symbol)
+            .map(|order| order.side)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(sides("UP"), vec![OrderSide::Buy, OrderSide::Sell]);
+    // The initial sell signal is rejected without a position
+    assert_eq!(sides("DOWN"), vec![OrderSide::Buy]);
+}
+
+#[tokio::test]
+async fn test_sma_cross_strategy_trades_in_regular_session_and_flattens_before_close() {
+    // A rising price every minute of 2024-07-01 from 12:00 to 20:59 UTC, i.e. from before the
+    // NYSE opens (13:30 UTC) until after it closes (20:00 UTC)
+    let csv_feed_file = Scenario::minutes(540, &["AAPL"])
+        .starting_at("2024-07-01 12:00:00")
+        .generate(|i, _| Some(100.0 + i as f64 * 0.1));
+    let path = csv_feed_file.path().to_string_lossy().to_string();
+
+    let feed = CsvDataFeed::new("backtest".to_string(), path).unwrap();
+    let broker = Arc::new(DummyBroker::new("Dummy".to_string()));
+    // Shares of another strategy on the same broker
+    broker
+        .place_order(&Order {
+            symbol: "AAPL".into(),
+            side: OrderSide::Buy,
+            qty: 3,
+            price: Some(100.0),
+            order_type: OrderType::Market,
+            strategy_name: "Other".into(),
+        })
+        .await
+        .unwrap();
+    let trading_hours = TradingHours::new(
+        Arc::new(ExchangeCalendar::new(Exchange::Nyse)),
+        vec![Session::Regular],
+        Some(chrono::Duration::minutes(15)),
+    );
+    let mut strat = SmaCrossStrategy::new(
+        "TestSMA".to_string(),
+        Box::new(feed),
+        broker.clone(),
+        Box::new(FixedSizer::new("Fixed sizer".into(), 2)),
+        5,
+        20,
+    )
+    .with_trading_hours(trading_hours);
+    strat.run().await;
+    let orders = &broker.get_orders().await[1..];
+    assert_eq!(orders.len(), 2);
+    // The uptrend of the pre-market is bought at the open (13:30 UTC)
+    assert_eq!(orders[0].side, OrderSide::Buy);
+    assert_eq!(orders[0].price, Some(109.0));
+    // and sold 15 minutes before the close (20:00 UTC), without the other strategy's shares
+    assert_eq!(orders[1].side, OrderSide::Sell);
+    assert_eq!(orders[1].qty, 2);
+    assert_eq!(orders[1].price, Some(146.5));
+    assert_eq!(broker.position_qty("AAPL").await, 3);
+}